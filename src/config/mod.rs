@@ -0,0 +1,924 @@
+use anyhow::{anyhow, Context, Result};
+use livesplit_hotkey::Hotkey;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::display::{NotificationLevel, NotificationSettings, NotificationUrgency};
+use crate::foreground::ForegroundWindow;
+use crate::hotkey::{self, Action, ComboSpec, ControllerCombos};
+use crate::language;
+use crate::ocr::{OcrConfig, PoolSize, Preprocessing, ScreenRegions};
+use crate::overlay::{OverlayBehaviour, OverlayTheme};
+use crate::retry::RetryPolicy;
+use crate::translator::{TranslationBackend, TranslatorConfig, DEFAULT_SYSTEM_PROMPT};
+
+/// Loaded from the working directory when --config is not given
+pub const DEFAULT_CONFIG_FILE: &str = "ocrtrans.toml";
+/// Characters of notification text when notifications.max_length is not set
+const DEFAULT_NOTIFICATION_LENGTH: usize = 200;
+/// Prompt preset used when translation.prompt is not set
+pub const DEFAULT_PROMPT: &str = "default";
+
+/// One layer of settings, layers are stacked: defaults < config file < profile < env vars and CLI flags.
+/// Every field is optional so a layer only overrides what it sets
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layer {
+	pub screen_regions: Option<Vec<String>>,
+	pub src_lang: Option<String>,
+	pub target_lang: Option<String>,
+	pub word_per_sec: Option<u32>,
+	pub in_place: Option<bool>,
+	pub hide_on_region_change: Option<bool>,
+	pub translation: TranslationLayer,
+	pub ocr: OcrLayer,
+	pub network: NetworkLayer,
+	/// Source term -> translation, merged term by term
+	pub glossary: BTreeMap<String, String>,
+	/// Named system prompts selected by translation.prompt, merged name by name
+	pub prompts: BTreeMap<String, String>,
+	pub overlay: OverlayLayer,
+	pub hotkeys: BindingsLayer,
+	pub controller: ControllerLayer,
+	pub server: ServerLayer,
+	pub outputs: OutputsLayer,
+	pub notifications: NotificationsLayer,
+	/// Only allowed at the top level of the config file
+	pub profiles: BTreeMap<String, Layer>,
+	/// Only allowed in profiles, activates the profile when the focused window matches
+	#[serde(rename = "match")]
+	pub match_window: Option<ProfileMatch>,
+}
+
+/// A window matches when any of the processes or title parts does, both case insensitive
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileMatch {
+	/// Executable file names, ex. "P5R.exe"
+	#[serde(deserialize_with = "one_or_many")]
+	pub process: Option<Vec<String>>,
+	/// Parts of the window title
+	#[serde(deserialize_with = "one_or_many")]
+	pub title: Option<Vec<String>>,
+}
+
+impl ProfileMatch {
+	pub fn matches(&self, window: &ForegroundWindow) -> bool {
+		let title = window.title.to_lowercase();
+		self.process.iter().flatten().any(|process| !window.process.is_empty() && process.eq_ignore_ascii_case(&window.process))
+			|| self.title.iter().flatten().any(|part| !part.is_empty() && title.contains(&part.to_lowercase()))
+	}
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranslationLayer {
+	pub endpoint: Option<String>,
+	pub model: Option<String>,
+	pub api_key: Option<String>,
+	/// Name of a prompt preset
+	pub prompt: Option<String>,
+	/// Backends tried in order when the main one keeps failing, replaced as a whole by upper layers
+	pub fallbacks: Option<Vec<TranslationFallback>>,
+	pub on_new_capture: Option<NewCapturePolicy>,
+	/// Show captures detected to be in target_lang as they are, only when src_lang is "auto"
+	pub skip_target_language: Option<bool>,
+	/// Source language -> prompt preset used for it instead of prompt, merged language by language
+	pub language_prompts: BTreeMap<String, String>,
+}
+
+/// What a capture does while the previous one is still being translated
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NewCapturePolicy {
+	/// Cancel the current translation, only the newest capture is translated
+	#[default]
+	Replace,
+	/// Translate every capture in order
+	Queue,
+}
+
+/// endpoint and model default to the main ones, api_key only does when the endpoint is the same
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TranslationFallback {
+	pub endpoint: Option<String>,
+	pub model: Option<String>,
+	pub api_key: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcrLayer {
+	pub endpoint: Option<String>,
+	/// Language pack sent to the server, e.g. "jpn". Taken from src_lang when not set, empty lets the server pick
+	pub language: Option<String>,
+	/// Tried in order when endpoint keeps failing
+	pub fallback_endpoints: Option<Vec<String>>,
+	pub scale: Option<f32>,
+	pub contrast: Option<f32>,
+	pub threshold: Option<u8>,
+	/// Captures read at the same time
+	pub workers: Option<usize>,
+	/// Captures waiting for a free worker, more are dropped
+	pub queue_size: Option<usize>,
+}
+
+/// Timeouts and retries of the OCR and translation requests
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkLayer {
+	pub connect_timeout_ms: Option<u64>,
+	/// Longest wait for response data, the whole request for OCR
+	pub read_timeout_ms: Option<u64>,
+	/// Per endpoint, after the first attempt
+	pub retries: Option<u32>,
+	/// First retry delay, doubled for each following one. A longer Retry-After from the server moves on to the next endpoint
+	pub backoff_ms: Option<u64>,
+	pub max_backoff_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayLayer {
+	/// Seconds
+	pub auto_hide: Option<f64>,
+	pub fade_ms: Option<u64>,
+	pub show_source: Option<bool>,
+	pub font: Option<String>,
+	pub font_size: Option<i32>,
+	/// "#RRGGBB"
+	pub text_color: Option<String>,
+	pub source_font: Option<String>,
+	pub source_font_size: Option<i32>,
+	pub source_color: Option<String>,
+	pub background_color: Option<String>,
+	/// 0-255
+	pub opacity: Option<u8>,
+}
+
+/// Bindings per action, each one is a string or a list of strings
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BindingsLayer {
+	#[serde(deserialize_with = "one_or_many")]
+	pub translate: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub retranslate: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub toggle_overlay: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub pin_overlay: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub toggle_source: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub clear_context: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub cycle_region: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub history_back: Option<Vec<String>>,
+	#[serde(deserialize_with = "one_or_many")]
+	pub history_forward: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllerLayer {
+	pub enabled: Option<bool>,
+	/// First connected controller if not set
+	pub index: Option<u32>,
+	pub poll_stats: Option<bool>,
+	pub combos: BindingsLayer,
+}
+
+/// Control API on localhost, see the server module
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerLayer {
+	pub enabled: Option<bool>,
+	/// 0 picks a free port
+	pub port: Option<u16>,
+	/// Required from clients, as "Authorization: Bearer TOKEN" or ?token=TOKEN. A new one is generated and printed
+	/// on each start when not set
+	pub token: Option<String>,
+}
+
+/// Where finished translations are also sent, an empty path or port 0 turns an output off
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputsLayer {
+	/// Text file the translations are appended to
+	pub transcript: Option<String>,
+	/// One JSON object per translation
+	pub jsonl: Option<String>,
+	pub clipboard: Option<bool>,
+	/// Caption page and WebSocket feed for OBS browser sources on localhost
+	pub captions_port: Option<u16>,
+	/// Directory each run records its translations to, the history is loaded from it too. Not recorded when not set
+	pub sessions: Option<String>,
+}
+
+/// Desktop notifications for capture results and errors
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsLayer {
+	pub show: Option<NotificationLevel>,
+	/// Characters of the source and translation, 0 shows them whole
+	pub max_length: Option<usize>,
+	/// 0 keeps them until dismissed, the system default when not set
+	pub timeout_ms: Option<u64>,
+	pub urgency: Option<NotificationUrgency>,
+	pub app_name: Option<String>,
+	/// Icon name or path
+	pub icon: Option<String>,
+	pub include_source: Option<bool>,
+	pub include_region: Option<bool>,
+}
+
+impl OutputsLayer {
+	/// None when session recording is off
+	pub fn sessions_dir(&self) -> Option<PathBuf> {
+		self.sessions.as_deref().filter(|dir| !dir.is_empty()).map(PathBuf::from)
+	}
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Vec<String>>, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum OneOrMany {
+		One(String),
+		Many(Vec<String>),
+	}
+	Ok(Some(match OneOrMany::deserialize(deserializer)? {
+		OneOrMany::One(binding) => vec![binding],
+		OneOrMany::Many(bindings) => bindings,
+	}))
+}
+
+/// Replace target only when the upper layer sets a value
+fn set<T>(target: &mut Option<T>, value: Option<T>) {
+	if value.is_some() {
+		*target = value;
+	}
+}
+
+impl BindingsLayer {
+	fn actions(&self) -> [(Action, &Option<Vec<String>>); 9] {
+		[
+			(Action::Translate, &self.translate),
+			(Action::Retranslate, &self.retranslate),
+			(Action::ToggleOverlay, &self.toggle_overlay),
+			(Action::PinOverlay, &self.pin_overlay),
+			(Action::ToggleSource, &self.toggle_source),
+			(Action::ClearContext, &self.clear_context),
+			(Action::CycleRegion, &self.cycle_region),
+			(Action::HistoryBack, &self.history_back),
+			(Action::HistoryForward, &self.history_forward),
+		]
+	}
+
+	pub fn action_mut(&mut self, action: Action) -> &mut Option<Vec<String>> {
+		match action {
+			Action::Translate => &mut self.translate,
+			Action::Retranslate => &mut self.retranslate,
+			Action::ToggleOverlay => &mut self.toggle_overlay,
+			Action::PinOverlay => &mut self.pin_overlay,
+			Action::ToggleSource => &mut self.toggle_source,
+			Action::ClearContext => &mut self.clear_context,
+			Action::CycleRegion => &mut self.cycle_region,
+			Action::HistoryBack => &mut self.history_back,
+			Action::HistoryForward => &mut self.history_forward,
+		}
+	}
+
+	/// An action set in other replaces all bindings of that action
+	fn merge(&mut self, other: BindingsLayer) {
+		set(&mut self.translate, other.translate);
+		set(&mut self.retranslate, other.retranslate);
+		set(&mut self.toggle_overlay, other.toggle_overlay);
+		set(&mut self.pin_overlay, other.pin_overlay);
+		set(&mut self.toggle_source, other.toggle_source);
+		set(&mut self.clear_context, other.clear_context);
+		set(&mut self.cycle_region, other.cycle_region);
+		set(&mut self.history_back, other.history_back);
+		set(&mut self.history_forward, other.history_forward);
+	}
+
+	fn bindings(&self) -> impl Iterator<Item = (Action, &str)> {
+		self.actions().into_iter().flat_map(|(action, bindings)| bindings.iter().flatten().map(move |binding| (action, binding.as_str())))
+	}
+}
+
+impl Layer {
+	/// Built-in values, the bottom layer
+	pub fn defaults() -> Self {
+		let mut layer = Layer {
+			src_lang: Some(language::AUTO.to_string()),
+			target_lang: Some("English".to_string()),
+			word_per_sec: Some(10),
+			in_place: Some(false),
+			hide_on_region_change: Some(false),
+			translation: TranslationLayer {
+				endpoint: Some("https://api.openai.com/v1".to_string()),
+				model: Some("gpt-4o".to_string()),
+				api_key: None,
+				prompt: Some(DEFAULT_PROMPT.to_string()),
+				fallbacks: None,
+				on_new_capture: Some(NewCapturePolicy::Replace),
+				skip_target_language: Some(true),
+				language_prompts: BTreeMap::new(),
+			},
+			ocr: OcrLayer {
+				endpoint: Some("http://172.22.22.172:5000/extract_text".to_string()),
+				..Default::default()
+			},
+			prompts: BTreeMap::from([(DEFAULT_PROMPT.to_string(), DEFAULT_SYSTEM_PROMPT.to_string())]),
+			overlay: OverlayLayer {
+				fade_ms: Some(200),
+				show_source: Some(false),
+				..Default::default()
+			},
+			controller: ControllerLayer {
+				enabled: Some(true),
+				poll_stats: Some(false),
+				..Default::default()
+			},
+			server: ServerLayer {
+				enabled: Some(false),
+				port: Some(7878),
+				token: None,
+			},
+			..Default::default()
+		};
+		layer.hotkeys.translate = Some(vec!["F3".to_string()]);
+		layer.controller.combos.translate = Some(vec!["DPadRight+Select".to_string()]);
+		layer
+	}
+
+	/// Stack other on top of self, profiles are not merged
+	pub fn merge(&mut self, other: Layer) {
+		set(&mut self.screen_regions, other.screen_regions);
+		set(&mut self.src_lang, other.src_lang);
+		set(&mut self.target_lang, other.target_lang);
+		set(&mut self.word_per_sec, other.word_per_sec);
+		set(&mut self.in_place, other.in_place);
+		set(&mut self.hide_on_region_change, other.hide_on_region_change);
+
+		set(&mut self.translation.endpoint, other.translation.endpoint);
+		set(&mut self.translation.model, other.translation.model);
+		set(&mut self.translation.api_key, other.translation.api_key);
+		set(&mut self.translation.prompt, other.translation.prompt);
+		set(&mut self.translation.fallbacks, other.translation.fallbacks);
+		set(&mut self.translation.on_new_capture, other.translation.on_new_capture);
+		set(&mut self.translation.skip_target_language, other.translation.skip_target_language);
+		self.translation.language_prompts.extend(other.translation.language_prompts);
+
+		set(&mut self.ocr.endpoint, other.ocr.endpoint);
+		set(&mut self.ocr.language, other.ocr.language);
+		set(&mut self.ocr.fallback_endpoints, other.ocr.fallback_endpoints);
+		set(&mut self.ocr.scale, other.ocr.scale);
+		set(&mut self.ocr.contrast, other.ocr.contrast);
+		set(&mut self.ocr.threshold, other.ocr.threshold);
+		set(&mut self.ocr.workers, other.ocr.workers);
+		set(&mut self.ocr.queue_size, other.ocr.queue_size);
+
+		set(&mut self.network.connect_timeout_ms, other.network.connect_timeout_ms);
+		set(&mut self.network.read_timeout_ms, other.network.read_timeout_ms);
+		set(&mut self.network.retries, other.network.retries);
+		set(&mut self.network.backoff_ms, other.network.backoff_ms);
+		set(&mut self.network.max_backoff_ms, other.network.max_backoff_ms);
+
+		self.glossary.extend(other.glossary);
+		self.prompts.extend(other.prompts);
+
+		set(&mut self.overlay.auto_hide, other.overlay.auto_hide);
+		set(&mut self.overlay.fade_ms, other.overlay.fade_ms);
+		set(&mut self.overlay.show_source, other.overlay.show_source);
+		set(&mut self.overlay.font, other.overlay.font);
+		set(&mut self.overlay.font_size, other.overlay.font_size);
+		set(&mut self.overlay.text_color, other.overlay.text_color);
+		set(&mut self.overlay.source_font, other.overlay.source_font);
+		set(&mut self.overlay.source_font_size, other.overlay.source_font_size);
+		set(&mut self.overlay.source_color, other.overlay.source_color);
+		set(&mut self.overlay.background_color, other.overlay.background_color);
+		set(&mut self.overlay.opacity, other.overlay.opacity);
+
+		self.hotkeys.merge(other.hotkeys);
+		set(&mut self.controller.enabled, other.controller.enabled);
+		set(&mut self.controller.index, other.controller.index);
+		set(&mut self.controller.poll_stats, other.controller.poll_stats);
+		self.controller.combos.merge(other.controller.combos);
+
+		set(&mut self.server.enabled, other.server.enabled);
+		set(&mut self.server.port, other.server.port);
+		set(&mut self.server.token, other.server.token);
+
+		set(&mut self.outputs.transcript, other.outputs.transcript);
+		set(&mut self.outputs.jsonl, other.outputs.jsonl);
+		set(&mut self.outputs.clipboard, other.outputs.clipboard);
+		set(&mut self.outputs.captions_port, other.outputs.captions_port);
+		set(&mut self.outputs.sessions, other.outputs.sessions);
+
+		set(&mut self.notifications.show, other.notifications.show);
+		set(&mut self.notifications.max_length, other.notifications.max_length);
+		set(&mut self.notifications.timeout_ms, other.notifications.timeout_ms);
+		set(&mut self.notifications.urgency, other.notifications.urgency);
+		set(&mut self.notifications.app_name, other.notifications.app_name);
+		set(&mut self.notifications.icon, other.notifications.icon);
+		set(&mut self.notifications.include_source, other.notifications.include_source);
+		set(&mut self.notifications.include_region, other.notifications.include_region);
+	}
+
+	/// Validate everything and build the settings, all problems are reported at once
+	pub fn resolve(self) -> Result<Settings> {
+		let mut errors = Errors::default();
+		let screen_regions = errors.check(match self.screen_regions {
+			Some(regions) => ScreenRegions::new(regions),
+			None => Err(anyhow!("No screen region set, use --screen-region or screen_regions in the config file")),
+		});
+		let system_prompt = errors.check(match &self.translation.prompt {
+			Some(prompt) => self.prompts.get(prompt).cloned().ok_or_else(|| anyhow!(
+				"Unknown prompt \"{}\", available: {}", prompt, self.prompts.keys().cloned().collect::<Vec<_>>().join(", ")
+			)),
+			None => Ok(DEFAULT_SYSTEM_PROMPT.to_string()),
+		});
+		let language_prompts = errors.check(self.translation.language_prompts.iter().map(|(language, prompt)| {
+			let language = language::find(language).ok_or_else(|| anyhow!("Unknown language \"{}\" in translation.language_prompts", language))?;
+			let prompt = self.prompts.get(prompt).ok_or_else(|| anyhow!("Unknown prompt \"{}\" for {} in translation.language_prompts", prompt, language.name))?;
+			Ok((language.name.to_string(), prompt.clone()))
+		}).collect::<Result<Vec<_>>>());
+		let key_bindings = errors.check(hotkey::parse_bindings(self.hotkeys.bindings()));
+		let controller_combos = errors.check(self.controller.combos.bindings()
+			.map(|(action, combo)| hotkey::parse_gamepad_combo(combo).map(|combo| (action, combo)))
+			.collect::<Result<Vec<_>>>());
+		let theme = errors.check(resolve_theme(&self.overlay));
+		let preprocessing = errors.check(resolve_preprocessing(&self.ocr));
+		let retry_policy = errors.check(resolve_retry_policy(&self.network));
+		let src_lang = errors.check(required(self.src_lang, "src_lang"));
+		let target_lang = errors.check(required(self.target_lang, "target_lang"));
+		let translation_endpoint = errors.check(required(self.translation.endpoint, "translation.endpoint"));
+		let translation_model = errors.check(required(self.translation.model, "translation.model"));
+		let ocr_endpoint = errors.check(required(self.ocr.endpoint, "ocr.endpoint"));
+		if self.overlay.auto_hide.is_some_and(|auto_hide| !(auto_hide > 0.0 && auto_hide.is_finite())) {
+			errors.push("overlay.auto_hide should be a positive number of seconds");
+		}
+		if self.ocr.workers == Some(0) {
+			errors.push("ocr.workers should be at least 1");
+		}
+		if self.word_per_sec == Some(0) {
+			errors.push("word_per_sec should be at least 1");
+		}
+		if self.glossary.keys().any(|term| term.trim().is_empty()) {
+			errors.push("Glossary terms can't be empty");
+		}
+		if !self.profiles.is_empty() {
+			errors.push("Profiles can only be defined at the top level of the config file");
+		}
+		if self.translation.fallbacks.iter().flatten().any(|fallback| fallback.endpoint.is_none() && fallback.model.is_none()) {
+			errors.push("translation.fallbacks entries need an endpoint, a model or both");
+		}
+		if self.server.token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+			errors.push("server.token can't be empty, remove it to get a generated one");
+		}
+
+		let (Some(screen_regions), Some(system_prompt), Some(language_prompts), Some(key_bindings), Some(controller_combos), Some(theme), Some(preprocessing), Some(retry_policy),
+			Some(src_lang), Some(target_lang), Some(translation_endpoint), Some(translation_model), Some(ocr_endpoint), true) =
+			(screen_regions, system_prompt, language_prompts, key_bindings, controller_combos, theme, preprocessing, retry_policy,
+			src_lang, target_lang, translation_endpoint, translation_model, ocr_endpoint, errors.0.is_empty())
+		else {
+			return Err(anyhow!(errors.0.join("\n")));
+		};
+		let translation_fallbacks = self.translation.fallbacks.unwrap_or_default().into_iter().map(|fallback| {
+			let same_endpoint = fallback.endpoint.as_ref().is_none_or(|endpoint| *endpoint == translation_endpoint);
+			TranslationBackend {
+				api_key: fallback.api_key.or_else(|| self.translation.api_key.clone().filter(|_| same_endpoint)),
+				endpoint: fallback.endpoint.unwrap_or_else(|| translation_endpoint.clone()),
+				model: fallback.model.unwrap_or_else(|| translation_model.clone()),
+			}
+		}).collect();
+		// An explicit source language picks its pack, "auto" lets the pipeline detect it
+		let (ocr_language, detect_language) = match self.ocr.language {
+			Some(ocr_language) => (Some(ocr_language).filter(|ocr_language| !ocr_language.is_empty()), false),
+			None if language::is_auto(&src_lang) => (None, true),
+			None => (language::find(&src_lang).map(|language| language.ocr_language.to_string()), false),
+		};
+
+		Ok(Settings {
+			screen_regions,
+			src_lang,
+			target_lang,
+			word_per_sec: self.word_per_sec.unwrap_or(10),
+			in_place: self.in_place.unwrap_or_default(),
+			hide_on_region_change: self.hide_on_region_change.unwrap_or_default(),
+			on_new_capture: self.translation.on_new_capture.unwrap_or_default(),
+			skip_target_language: self.translation.skip_target_language.unwrap_or(true),
+			translator: TranslatorConfig {
+				endpoint: translation_endpoint,
+				api_key: self.translation.api_key,
+				model: translation_model,
+				system_prompt,
+				language_prompts,
+				glossary: self.glossary.into_iter().collect(),
+				fallbacks: translation_fallbacks,
+				retry: retry_policy,
+			},
+			ocr: OcrConfig {
+				endpoint: ocr_endpoint,
+				fallback_endpoints: self.ocr.fallback_endpoints.unwrap_or_default(),
+				preprocessing,
+				retry: retry_policy,
+				language: ocr_language,
+				detect_language,
+			},
+			ocr_pool: PoolSize {
+				workers: self.ocr.workers.unwrap_or(PoolSize::default().workers),
+				queue: self.ocr.queue_size.unwrap_or(PoolSize::default().queue),
+			},
+			overlay: OverlayBehaviour {
+				auto_hide: self.overlay.auto_hide.map(Duration::from_secs_f64),
+				fade: Duration::from_millis(self.overlay.fade_ms.unwrap_or_default()),
+				show_source: self.overlay.show_source.unwrap_or_default(),
+				theme,
+			},
+			key_bindings,
+			controller: ControllerSettings {
+				enabled: self.controller.enabled.unwrap_or(true),
+				index: self.controller.index,
+				poll_stats: self.controller.poll_stats.unwrap_or_default(),
+				combos: controller_combos,
+			},
+			server: self.server.enabled.unwrap_or_default().then(|| ServerSettings {
+				port: self.server.port.unwrap_or(7878),
+				token: self.server.token,
+			}),
+			notifications: NotificationSettings {
+				level: self.notifications.show.unwrap_or_default(),
+				max_length: Some(self.notifications.max_length.unwrap_or(DEFAULT_NOTIFICATION_LENGTH)).filter(|length| *length != 0),
+				timeout: self.notifications.timeout_ms.map(Duration::from_millis),
+				urgency: self.notifications.urgency.unwrap_or_default(),
+				app_name: self.notifications.app_name.unwrap_or_else(|| "ocrtrans".to_string()),
+				icon: self.notifications.icon.filter(|icon| !icon.is_empty()),
+				include_source: self.notifications.include_source.unwrap_or_default(),
+				include_region: self.notifications.include_region.unwrap_or_default(),
+			},
+			outputs: OutputSettings {
+				sessions: self.outputs.sessions_dir(),
+				transcript: self.outputs.transcript.filter(|path| !path.is_empty()).map(PathBuf::from),
+				jsonl: self.outputs.jsonl.filter(|path| !path.is_empty()).map(PathBuf::from),
+				clipboard: self.outputs.clipboard.unwrap_or_default(),
+				captions_port: self.outputs.captions_port.filter(|port| *port != 0),
+			},
+		})
+	}
+}
+
+/// Collects every problem instead of stopping at the first one
+#[derive(Default)]
+struct Errors(Vec<String>);
+
+impl Errors {
+	fn check<T>(&mut self, result: Result<T>) -> Option<T> {
+		result.map_err(|e| self.0.push(format!("{:#}", e))).ok()
+	}
+
+	fn push(&mut self, error: &str) {
+		self.0.push(error.to_string());
+	}
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T> {
+	value.ok_or_else(|| anyhow!("{} is not set", name))
+}
+
+fn resolve_preprocessing(ocr: &OcrLayer) -> Result<Preprocessing> {
+	let defaults = Preprocessing::default();
+	let preprocessing = Preprocessing {
+		scale: ocr.scale.unwrap_or(defaults.scale),
+		contrast: ocr.contrast.unwrap_or(defaults.contrast),
+		threshold: ocr.threshold.unwrap_or(defaults.threshold),
+	};
+	if !(preprocessing.scale > 0.0 && preprocessing.scale <= 4.0) {
+		return Err(anyhow!("ocr.scale should be in (0, 4], got {}", preprocessing.scale));
+	}
+	if !preprocessing.contrast.is_finite() {
+		return Err(anyhow!("ocr.contrast should be a number"));
+	}
+	Ok(preprocessing)
+}
+
+fn resolve_retry_policy(network: &NetworkLayer) -> Result<RetryPolicy> {
+	let defaults = RetryPolicy::default();
+	let duration = |value: Option<u64>, default: Duration| value.map_or(default, Duration::from_millis);
+	let retry_policy = RetryPolicy {
+		connect_timeout: duration(network.connect_timeout_ms, defaults.connect_timeout),
+		read_timeout: duration(network.read_timeout_ms, defaults.read_timeout),
+		retries: network.retries.unwrap_or(defaults.retries),
+		backoff: duration(network.backoff_ms, defaults.backoff),
+		max_backoff: duration(network.max_backoff_ms, defaults.max_backoff),
+	};
+	if retry_policy.connect_timeout.is_zero() || retry_policy.read_timeout.is_zero() {
+		return Err(anyhow!("network.connect_timeout_ms and network.read_timeout_ms should be at least 1"));
+	}
+	if retry_policy.backoff > retry_policy.max_backoff {
+		return Err(anyhow!("network.backoff_ms should not be above network.max_backoff_ms"));
+	}
+	Ok(retry_policy)
+}
+
+fn resolve_theme(overlay: &OverlayLayer) -> Result<OverlayTheme> {
+	let defaults = OverlayTheme::default();
+	let color = |value: &Option<String>, name: &str, default: [u8; 3]| value.as_deref().map_or(Ok(default), |value| parse_color(value).with_context(|| format!("overlay.{}", name)));
+	let font_size = |value: Option<i32>, name: &str, default: i32| match value {
+		Some(size) if !(6..=200).contains(&size) => Err(anyhow!("overlay.{} should be between 6 and 200, got {}", name, size)),
+		Some(size) => Ok(size),
+		None => Ok(default),
+	};
+	Ok(OverlayTheme {
+		font: overlay.font.clone().unwrap_or(defaults.font),
+		font_size: font_size(overlay.font_size, "font_size", defaults.font_size)?,
+		text_color: color(&overlay.text_color, "text_color", defaults.text_color)?,
+		source_font: overlay.source_font.clone().unwrap_or(defaults.source_font),
+		source_font_size: font_size(overlay.source_font_size, "source_font_size", defaults.source_font_size)?,
+		source_color: color(&overlay.source_color, "source_color", defaults.source_color)?,
+		background_color: color(&overlay.background_color, "background_color", defaults.background_color)?,
+		opacity: overlay.opacity.unwrap_or(defaults.opacity),
+	})
+}
+
+/// "#RRGGBB" or "RRGGBB"
+fn parse_color(value: &str) -> Result<[u8; 3]> {
+	let hex = value.trim_start_matches('#');
+	if hex.len() != 6 || !hex.is_ascii() {
+		return Err(anyhow!("Invalid color \"{}\", use #RRGGBB", value));
+	}
+	let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("Invalid color \"{}\", use #RRGGBB", value));
+	Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Everything validated and ready to use
+#[derive(Clone, Debug)]
+pub struct Settings {
+	pub screen_regions: ScreenRegions,
+	pub src_lang: String,
+	pub target_lang: String,
+	pub word_per_sec: u32,
+	pub in_place: bool,
+	pub hide_on_region_change: bool,
+	pub on_new_capture: NewCapturePolicy,
+	/// Captures detected to be in target_lang are not translated
+	pub skip_target_language: bool,
+	pub translator: TranslatorConfig,
+	pub ocr: OcrConfig,
+	pub ocr_pool: PoolSize,
+	pub overlay: OverlayBehaviour,
+	pub key_bindings: Vec<(Action, ComboSpec<Hotkey>)>,
+	pub controller: ControllerSettings,
+	/// None when the control API is off
+	pub server: Option<ServerSettings>,
+	pub outputs: OutputSettings,
+	pub notifications: NotificationSettings,
+}
+
+#[derive(Clone, Debug)]
+pub struct ControllerSettings {
+	pub enabled: bool,
+	pub index: Option<u32>,
+	pub poll_stats: bool,
+	pub combos: ControllerCombos,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerSettings {
+	pub port: u16,
+	/// None generates one when the server starts
+	pub token: Option<String>,
+}
+
+/// Outputs that are None or false are off
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSettings {
+	pub transcript: Option<PathBuf>,
+	pub jsonl: Option<PathBuf>,
+	pub clipboard: bool,
+	pub captions_port: Option<u16>,
+	pub sessions: Option<PathBuf>,
+}
+
+/// The parsed config file, path is None when running without one
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFile {
+	pub path: Option<PathBuf>,
+	pub root: Layer,
+}
+
+impl ConfigFile {
+	/// An explicit path has to exist, otherwise DEFAULT_CONFIG_FILE is used if present
+	pub fn load(path: Option<&Path>) -> Result<Self> {
+		let path = match path {
+			Some(path) => path.to_path_buf(),
+			None if Path::new(DEFAULT_CONFIG_FILE).exists() => PathBuf::from(DEFAULT_CONFIG_FILE),
+			None => return Ok(Self::default()),
+		};
+		let content = std::fs::read_to_string(&path).with_context(|| format!("Reading config file {} failed", path.display()))?;
+		let root: Layer = toml_edit::de::from_str(&content).with_context(|| format!("Invalid config file {}", path.display()))?;
+		if root.match_window.is_some() {
+			return Err(anyhow!("Invalid config file {}: match can only be set in profiles", path.display()));
+		}
+		Ok(Self { path: Some(path), root })
+	}
+
+	pub fn profile_names(&self) -> Vec<&str> {
+		self.root.profiles.keys().map(String::as_str).collect()
+	}
+
+	/// Whether any profile can be activated by the focused window
+	pub fn has_window_matches(&self) -> bool {
+		self.root.profiles.values().any(|profile| profile.match_window.is_some())
+	}
+
+	/// First profile(by name) matching the window
+	pub fn match_profile(&self, window: &ForegroundWindow) -> Option<&str> {
+		self.root.profiles.iter()
+			.find(|(_, profile)| profile.match_window.as_ref().is_some_and(|match_window| match_window.matches(window)))
+			.map(|(name, _)| name.as_str())
+	}
+
+	/// defaults < config file < profile < overrides(env vars and CLI flags)
+	pub fn settings(&self, profile: Option<&str>, overrides: &Layer) -> Result<Settings> {
+		self.layer(profile, overrides)?.resolve()
+	}
+
+	/// The merged layers before they are checked, for tools that only need part of the settings
+	pub fn layer(&self, profile: Option<&str>, overrides: &Layer) -> Result<Layer> {
+		let mut layer = Layer::defaults();
+		let mut root = self.root.clone();
+		let profiles = std::mem::take(&mut root.profiles);
+		layer.merge(root);
+		if let Some(profile) = profile {
+			let profile_layer = profiles.get(profile).ok_or_else(|| anyhow!(
+				"Unknown profile \"{}\", available: {}", profile, self.profile_names().join(", ")
+			))?;
+			if !profile_layer.profiles.is_empty() {
+				return Err(anyhow!("Profile \"{}\" defines profiles, they can only be defined at the top level", profile));
+			}
+			layer.merge(profile_layer.clone());
+		}
+		layer.merge(overrides.clone());
+		Ok(layer)
+	}
+
+	/// Last modification time of the file, None without a file
+	pub fn modified(&self) -> Option<SystemTime> {
+		modified(self.path.as_deref()?)
+	}
+
+	/// Resolve the global settings and every profile, printing the result of each
+	pub fn check(&self, overrides: &Layer) -> Result<()> {
+		match &self.path {
+			Some(path) => println!("Checking {}", path.display()),
+			None => println!("No config file found, checking defaults and flags only"),
+		}
+		let mut failed = 0;
+		for profile in std::iter::once(None).chain(self.profile_names().into_iter().map(Some)) {
+			let name = profile.map_or("global".to_string(), |profile| format!("profile \"{}\"", profile));
+			match self.settings(profile, overrides) {
+				Ok(_) => println!("{}: OK", name),
+				Err(e) => {
+					failed += 1;
+					println!("{}:\n{:#}", name, e);
+				},
+			}
+		}
+		if failed > 0 {
+			return Err(anyhow!("{} configuration(s) invalid", failed));
+		}
+		Ok(())
+	}
+}
+
+/// What the running settings are built from, the profile changes with the focused window and the file on reload
+#[derive(Clone, Debug)]
+pub struct ConfigSource {
+	pub file: ConfigFile,
+	pub profile: Option<String>,
+	/// Env vars and CLI flags
+	pub overrides: Layer,
+}
+
+impl ConfigSource {
+	pub fn settings(&self) -> Result<Settings> {
+		self.file.settings(self.profile.as_deref(), &self.overrides)
+	}
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// WARNING: Blocking function, never returns
+///
+/// Polls the modification time of path, on_change() gets the file parsed again after each change.
+/// A deleted file is ignored until it comes back
+pub fn config_file_watcher(path: &Path, mut last_modified: Option<SystemTime>, poll_interval: Duration, mut on_change: impl FnMut(Result<ConfigFile>)) {
+	loop {
+		std::thread::sleep(poll_interval);
+		let current_modified = modified(path);
+		if current_modified.is_some() && current_modified != last_modified {
+			last_modified = current_modified;
+			on_change(ConfigFile::load(Some(path)));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONFIG: &str = r#"
+		screen_regions = ["(0, 1, 0, 1)"]
+		target_lang = "French"
+		word_per_sec = 5
+		glossary = { "勇者" = "Hero" }
+
+		[translation]
+		model = "file-model"
+
+		[profiles.p5r]
+		match = { process = "P5R.exe" }
+		glossary = { "怪盗" = "Phantom Thief" }
+		translation = { model = "profile-model" }
+
+		[profiles.novel]
+		match = { title = ["Visual Novel", "VN"] }
+		in_place = true
+	"#;
+
+	fn config_file(content: &str) -> ConfigFile {
+		ConfigFile { path: None, root: toml_edit::de::from_str(content).unwrap() }
+	}
+
+	fn window(process: &str, title: &str) -> ForegroundWindow {
+		ForegroundWindow { process: process.to_string(), title: title.to_string() }
+	}
+
+	#[test]
+	fn layers_override_in_order() {
+		let config_file = config_file(CONFIG);
+		let overrides = Layer {
+			translation: TranslationLayer { model: Some("cli-model".to_string()), ..Default::default() },
+			..Default::default()
+		};
+		let global = config_file.settings(None, &Layer::default()).unwrap();
+		assert_eq!(global.translator.model, "file-model");
+		assert_eq!((global.src_lang.as_str(), global.target_lang.as_str(), global.word_per_sec), ("auto", "French", 5));
+		assert_eq!(global.translator.endpoint, "https://api.openai.com/v1");
+		let profile = config_file.settings(Some("p5r"), &Layer::default()).unwrap();
+		assert_eq!(profile.translator.model, "profile-model");
+		assert_eq!(profile.target_lang, "French");
+		// Glossaries are merged term by term
+		assert_eq!(profile.translator.glossary.len(), 2);
+		let overridden = config_file.settings(Some("p5r"), &overrides).unwrap();
+		assert_eq!(overridden.translator.model, "cli-model");
+		assert_eq!(overridden.word_per_sec, 5);
+	}
+
+	#[test]
+	fn selects_profiles() {
+		let config_file = config_file(CONFIG);
+		assert!(config_file.has_window_matches());
+		assert_eq!(config_file.match_profile(&window("p5r.exe", "Persona 5 Royal")), Some("p5r"));
+		assert_eq!(config_file.match_profile(&window("game.exe", "My vn - Chapter 1")), Some("novel"));
+		assert_eq!(config_file.match_profile(&window("", "Editor")), None);
+		assert!(config_file.settings(Some("novel"), &Layer::default()).unwrap().in_place);
+		let unknown = config_file.settings(Some("missing"), &Layer::default()).unwrap_err().to_string();
+		assert_eq!(unknown, "Unknown profile \"missing\", available: novel, p5r");
+	}
+
+	#[test]
+	fn reports_every_invalid_field() {
+		let config_file = config_file(r#"
+			word_per_sec = 0
+			translation = { prompt = "missing" }
+			ocr = { workers = 0 }
+			overlay = { auto_hide = -1.0, text_color = "red" }
+			server = { token = " " }
+		"#);
+		let errors = config_file.settings(None, &Layer::default()).unwrap_err().to_string();
+		let lines: Vec<&str> = errors.lines().collect();
+		assert_eq!(lines.len(), 7, "{}", errors);
+		for expected in ["No screen region set", "Unknown prompt \"missing\"", "Invalid color \"red\"", "overlay.auto_hide", "ocr.workers", "word_per_sec", "server.token"] {
+			assert!(lines.iter().any(|line| line.contains(expected)), "{} not in {}", expected, errors);
+		}
+	}
+
+	#[test]
+	fn outputs_are_off_by_default() {
+		let settings = config_file(CONFIG).settings(None, &Layer::default()).unwrap();
+		assert_eq!(settings.outputs, OutputSettings { transcript: None, jsonl: None, clipboard: false, captions_port: None, sessions: None });
+		assert!(settings.server.is_none());
+		let outputs = |sessions: &str| OutputsLayer { sessions: Some(sessions.to_string()), ..Default::default() }.sessions_dir();
+		assert_eq!(outputs(""), None);
+		assert_eq!(outputs("recordings"), Some(PathBuf::from("recordings")));
+	}
+}
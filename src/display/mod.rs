@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast;
+use crate::overlay::{StatusKind, UpdateHandle, WindowChannelMessage};
+
+mod notifications;
+mod outputs;
+pub use notifications::{NotificationLevel, NotificationSettings, NotificationSink, NotificationUrgency};
+pub use outputs::{parse_utc_timestamp, utc_timestamp, ClipboardSink, JsonlSink, TranscriptSink};
+pub(crate) use outputs::jsonl_record;
+
+/// Everything the pipeline wants to show, fanned out to every sink by spawn_display_dispatcher()
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum DisplayEvent {
+	/// A new translation is starting, drop whatever is displayed
+	Clear,
+	/// Streaming translation delta
+	Append(String),
+	/// Replace the whole displayed translation
+	Replace(String),
+	/// Text being translated, e.g. OCR output
+	Source(String),
+	Status(Status),
+	/// Short informational message, e.g. the active profile changed
+	Notice(String),
+	/// Short reason, it has to fit the overlay
+	Error(Stage, String),
+	Done(TranslationDone),
+}
+
+/// Pipeline progress shown while there is no translation text yet
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Status {
+	Capturing,
+	OcrRunning,
+	Translating,
+	RateLimited,
+}
+
+impl Status {
+	pub fn label(&self) -> &'static str {
+		match self {
+			Status::Capturing => "Capturing...",
+			Status::OcrRunning => "Reading text...",
+			Status::Translating => "Translating...",
+			Status::RateLimited => "Rate limited, try again shortly",
+		}
+	}
+
+	/// Busy states are expected progress, the rest needs the user's attention
+	pub fn is_busy(&self) -> bool {
+		!matches!(self, Status::RateLimited)
+	}
+}
+
+/// Where in the pipeline something failed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Stage {
+	Capture,
+	Ocr,
+	Translation,
+	Settings,
+}
+
+impl Stage {
+	pub fn label(&self) -> &'static str {
+		match self {
+			Stage::Capture => "Capture failed",
+			Stage::Ocr => "OCR failed",
+			Stage::Translation => "Translation failed",
+			Stage::Settings => "Settings not applied",
+		}
+	}
+}
+
+/// Who asked for the translation, sinks use it to avoid echoing what the user is already looking at
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+	Capture,
+	Terminal,
+	/// Sent to the control API
+	Api,
+}
+
+impl Origin {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Origin::Capture => "capture",
+			Origin::Terminal => "terminal",
+			Origin::Api => "api",
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct TranslationDone {
+	pub origin: Origin,
+	/// Text that was translated
+	pub source: String,
+	pub translation: String,
+	pub target_lang: String,
+	/// Main model of the translator settings, a fallback may have answered instead
+	pub model: String,
+	/// Screen region of captures
+	pub region: Option<String>,
+	pub latency: Duration,
+}
+
+impl DisplayEvent {
+	/// {"type": ..., ...} object sent to API clients
+	pub fn to_json(&self) -> serde_json::Value {
+		match self {
+			DisplayEvent::Clear => json!({ "type": "clear" }),
+			DisplayEvent::Append(text) => json!({ "type": "append", "text": text }),
+			DisplayEvent::Replace(text) => json!({ "type": "replace", "text": text }),
+			DisplayEvent::Source(text) => json!({ "type": "source", "text": text }),
+			DisplayEvent::Status(status) => json!({ "type": "status", "status": status, "label": status.label() }),
+			DisplayEvent::Notice(text) => json!({ "type": "notice", "text": text }),
+			DisplayEvent::Error(stage, error) => json!({ "type": "error", "stage": stage, "error": error }),
+			DisplayEvent::Done(done) => json!({
+				"type": "done",
+				"origin": done.origin,
+				"source": done.source,
+				"translation": done.translation,
+				"target_lang": done.target_lang,
+				"model": done.model,
+				"region": done.region,
+				"latency_ms": done.latency.as_millis() as u64,
+			}),
+		}
+	}
+}
+
+pub trait DisplaySink: Send {
+	fn handle(&mut self, event: &DisplayEvent);
+
+	/// Sinks only showing the current translation skip the events still queued when a Clear comes in,
+	/// e.g. the paced streaming text of a cancelled translation
+	fn skips_cleared(&self) -> bool {
+		false
+	}
+}
+
+/// Display stage input, sending never blocks so it works from async tasks and plain threads alike
+pub type DisplaySender = tokio::sync::mpsc::UnboundedSender<DisplayEvent>;
+
+/// Spawn the dispatcher task plus one thread per sink, the returned sender is the only thing the pipeline needs.
+/// Sinks block(overlay pacing, notifications) so each one gets its own thread, a rate limited sink can't hold back the others
+pub fn spawn_display_dispatcher(sinks: Vec<Box<dyn DisplaySink>>) -> DisplaySender {
+	let sink_channels: Vec<Sender<DisplayEvent>> = sinks.into_iter().map(|mut sink| {
+		let (sink_tx, sink_rx) = std::sync::mpsc::channel::<DisplayEvent>();
+		std::thread::spawn(move || {
+			let mut pending = VecDeque::new();
+			while let Ok(event) = sink_rx.recv() {
+				pending.push_back(event);
+				loop {
+					// Checked before each event, a paced sink may have slept through the Clear
+					pending.extend(sink_rx.try_iter());
+					if sink.skips_cleared() {
+						if let Some(last_clear) = pending.iter().rposition(|event| matches!(event, DisplayEvent::Clear)) {
+							pending.drain(..last_clear);
+						}
+					}
+					let Some(event) = pending.pop_front() else {
+						break;
+					};
+					sink.handle(&event);
+				}
+			}
+		});
+		sink_tx
+	}).collect();
+	let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel::<DisplayEvent>();
+	tokio::spawn(async move {
+		while let Some(event) = event_rx.recv().await {
+			for sink_channel in &sink_channels {
+				let _ = sink_channel.send(event.clone());
+			}
+		}
+	});
+	event_tx
+}
+
+/// Keeps the tail of the streaming translation and rate limits overlay refreshes
+pub struct OverlaySink {
+	window_content_output: Sender<WindowChannelMessage>,
+	window_refresh: UpdateHandle,
+	buffer: String,
+	max_len: usize,
+	min_interval: Duration, // The minimum time interval between consecutive sends
+	last_send_time: Instant,
+}
+
+impl OverlaySink {
+	pub fn new(window_content_output: Sender<WindowChannelMessage>, window_refresh: UpdateHandle, max_len: usize, min_interval: Duration) -> Self {
+		Self {
+			window_content_output,
+			window_refresh,
+			buffer: String::new(),
+			max_len,
+			min_interval,
+			last_send_time: Instant::now(),
+		}
+	}
+
+	fn send(&self, message: WindowChannelMessage) {
+		let _ = self.window_content_output.send(message);
+		let _ = self.window_refresh.update_window();
+	}
+
+	fn send_buffer(&mut self) {
+		while self.buffer.chars().count() > self.max_len {
+			self.buffer.remove(0);
+		}
+
+		// Sleep for the remaining time if the last send is too recent
+		let elapsed_time = self.last_send_time.elapsed();
+		if elapsed_time < self.min_interval {
+			std::thread::sleep(self.min_interval - elapsed_time);
+		}
+
+		self.send(WindowChannelMessage::Text(self.buffer.clone()));
+		self.last_send_time = Instant::now();
+	}
+}
+
+impl DisplaySink for OverlaySink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		match event {
+			DisplayEvent::Clear => {
+				self.buffer.clear();
+				self.send(WindowChannelMessage::Clear);
+			},
+			DisplayEvent::Append(content) => {
+				self.buffer.push_str(content);
+				self.send_buffer();
+			},
+			DisplayEvent::Replace(content) => {
+				self.buffer = content.clone();
+				self.send_buffer();
+			},
+			DisplayEvent::Source(source) => self.send(WindowChannelMessage::Source(source.clone())),
+			DisplayEvent::Status(status) => self.send(WindowChannelMessage::Status(
+				status.label().to_string(),
+				if status.is_busy() { StatusKind::Busy } else { StatusKind::Warning },
+			)),
+			DisplayEvent::Notice(notice) => self.send(WindowChannelMessage::Status(notice.clone(), StatusKind::Busy)),
+			DisplayEvent::Error(stage, error) => self.send(WindowChannelMessage::Status(format!("{}: {}", stage.label(), error), StatusKind::Error)),
+			DisplayEvent::Done(_) => (),
+		}
+	}
+
+	fn skips_cleared(&self) -> bool {
+		true
+	}
+}
+
+/// Passes every event on to the subscribers of the channel, see Pipeline::subscribe()
+pub struct BroadcastSink(pub broadcast::Sender<DisplayEvent>);
+
+impl DisplaySink for BroadcastSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		// Fails only while nobody is subscribed
+		let _ = self.0.send(event.clone());
+	}
+}
+
+/// Prints results of captures, terminal input is already streamed to stdout by the input loop
+pub struct TerminalSink;
+
+impl DisplaySink for TerminalSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		match event {
+			DisplayEvent::Status(status) if !status.is_busy() => println!("[{}]", status.label()),
+			DisplayEvent::Notice(notice) => println!("[{}]", notice),
+			DisplayEvent::Error(stage, error) => eprintln!("{}: {}", stage.label(), error),
+			DisplayEvent::Done(done) if done.origin == Origin::Capture => {
+				println!("{} Output> ({:.1}s)\n{}", done.target_lang, done.latency.as_secs_f64(), done.translation);
+			},
+			_ => (),
+		}
+	}
+}
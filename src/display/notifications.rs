@@ -0,0 +1,151 @@
+use notify_rust::{Notification, Timeout};
+use serde::Deserialize;
+use std::time::Duration;
+use super::{DisplayEvent, DisplaySink, Origin, TranslationDone};
+
+/// Which events get a desktop notification
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationLevel {
+	Off,
+	/// Failed captures and translations only
+	Errors,
+	/// Errors and every capture result
+	#[default]
+	All,
+}
+
+/// Only Linux and BSD notification servers use it
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationUrgency {
+	Low,
+	#[default]
+	Normal,
+	/// Stays until dismissed on most desktops
+	Critical,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotificationSettings {
+	pub level: NotificationLevel,
+	/// Characters of the source and translation shown, longer ones are cut with "…"
+	pub max_length: Option<usize>,
+	/// None for the system default, zero keeps them until dismissed
+	pub timeout: Option<Duration>,
+	pub urgency: NotificationUrgency,
+	pub app_name: String,
+	/// Icon name or path
+	pub icon: Option<String>,
+	/// OCR text above the translation
+	pub include_source: bool,
+	/// Capture region in the title
+	pub include_region: bool,
+}
+
+/// Desktop notifications for capture results and errors, as configured
+pub struct NotificationSink {
+	settings: NotificationSettings,
+}
+
+impl NotificationSink {
+	pub fn new(settings: NotificationSettings) -> Self {
+		Self { settings }
+	}
+
+	fn show(&self, summary: &str, body: &str) {
+		let mut notification = Notification::new();
+		notification.appname(&self.settings.app_name).summary(summary).body(body);
+		if let Some(icon) = &self.settings.icon {
+			notification.icon(icon);
+		}
+		match self.settings.timeout {
+			Some(timeout) if timeout.is_zero() => notification.timeout(Timeout::Never),
+			Some(timeout) => notification.timeout(Timeout::Milliseconds(timeout.as_millis().min(u32::MAX as u128) as u32)),
+			None => &mut notification,
+		};
+		#[cfg(all(unix, not(target_os = "macos")))]
+		notification.urgency(match self.settings.urgency {
+			NotificationUrgency::Low => notify_rust::Urgency::Low,
+			NotificationUrgency::Normal => notify_rust::Urgency::Normal,
+			NotificationUrgency::Critical => notify_rust::Urgency::Critical,
+		});
+		// No notification server(e.g. a bare X session) is not worth an error per capture
+		let _ = notification.show();
+	}
+}
+
+impl DisplaySink for NotificationSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		match (event, self.settings.level) {
+			(_, NotificationLevel::Off) => (),
+			(DisplayEvent::Error(stage, error), _) => self.show(stage.label(), &truncate(error, self.settings.max_length)),
+			(DisplayEvent::Done(done), NotificationLevel::All) if done.origin == Origin::Capture => {
+				let (summary, body) = result_notification(&self.settings, done);
+				self.show(&summary, &body);
+			},
+			_ => (),
+		}
+	}
+}
+
+/// (summary, body)
+fn result_notification(settings: &NotificationSettings, done: &TranslationDone) -> (String, String) {
+	let summary = match &done.region {
+		Some(region) if settings.include_region => format!("Translation result, region {}", region),
+		_ => "Translation result".to_string(),
+	};
+	let translation = truncate(done.translation.trim(), settings.max_length);
+	let body = if settings.include_source {
+		format!("{}\n→ {}", truncate(done.source.trim(), settings.max_length), translation)
+	} else {
+		translation
+	};
+	(summary, body)
+}
+
+fn truncate(text: &str, max_length: Option<usize>) -> String {
+	match max_length {
+		Some(max_length) if text.chars().count() > max_length => {
+			let cut: String = text.chars().take(max_length.saturating_sub(1)).collect();
+			format!("{}…", cut.trim_end())
+		},
+		_ => text.to_string(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn result_summary_and_body() {
+		let mut settings = NotificationSettings {
+			level: NotificationLevel::All,
+			max_length: Some(8),
+			timeout: None,
+			urgency: NotificationUrgency::Normal,
+			app_name: "ocrtrans".to_string(),
+			icon: None,
+			include_source: false,
+			include_region: false,
+		};
+		let done = TranslationDone {
+			origin: Origin::Capture,
+			source: "こんにちは、世界\n".to_string(),
+			translation: "Hello, world\n".to_string(),
+			target_lang: "English".to_string(),
+			model: "gpt-4o".to_string(),
+			region: Some("(0, 1, 0.7, 1)".to_string()),
+			latency: Duration::from_millis(900),
+		};
+		assert_eq!(result_notification(&settings, &done), ("Translation result".to_string(), "Hello,…".to_string()));
+		settings.include_source = true;
+		settings.include_region = true;
+		settings.max_length = None;
+		assert_eq!(result_notification(&settings, &done), (
+			"Translation result, region (0, 1, 0.7, 1)".to_string(),
+			"こんにちは、世界\n→ Hello, world".to_string(),
+		));
+	}
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use super::{DisplayEvent, DisplaySink, Origin, TranslationDone};
+
+// Sinks writing finished translations somewhere else than the screen, enabled in the [outputs] config section
+
+/// Appends "[time, origin] source / -> translation" blocks to a text file
+pub struct TranscriptSink {
+	file: File,
+}
+
+impl TranscriptSink {
+	pub fn open(path: &Path) -> Result<Self> {
+		Ok(Self { file: open_append(path)? })
+	}
+}
+
+impl DisplaySink for TranscriptSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		let DisplayEvent::Done(done) = event else {
+			return;
+		};
+		let origin = match (done.origin, &done.region) {
+			(Origin::Capture, Some(region)) => format!("capture {}", region),
+			(origin, _) => origin.name().to_string(),
+		};
+		let entry = format!("[{}, {}]\n{}\n-> {}\n\n", utc_timestamp(SystemTime::now()), origin, done.source.trim(), done.translation.trim());
+		if let Err(e) = self.file.write_all(entry.as_bytes()) {
+			eprintln!("Writing the transcript failed: {}", e);
+		}
+	}
+}
+
+/// One JSON object per line and translation: timestamp, origin, region, source, translation, target_lang, model and latency_ms
+pub struct JsonlSink {
+	file: File,
+}
+
+impl JsonlSink {
+	pub fn open(path: &Path) -> Result<Self> {
+		Ok(Self { file: open_append(path)? })
+	}
+}
+
+impl DisplaySink for JsonlSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		let DisplayEvent::Done(done) = event else {
+			return;
+		};
+		if let Err(e) = writeln!(self.file, "{}", jsonl_record(done, SystemTime::now())) {
+			eprintln!("Writing the JSONL output failed: {}", e);
+		}
+	}
+}
+
+pub(crate) fn jsonl_record(done: &TranslationDone, time: SystemTime) -> serde_json::Value {
+	json!({
+		"timestamp": utc_timestamp(time),
+		"origin": done.origin,
+		"region": done.region,
+		"source": done.source.trim(),
+		"translation": done.translation.trim(),
+		"target_lang": done.target_lang,
+		"model": done.model,
+		"latency_ms": done.latency.as_millis() as u64,
+	})
+}
+
+/// Copies each translation so it can be pasted, e.g. into a dictionary
+pub struct ClipboardSink;
+
+impl DisplaySink for ClipboardSink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		if let DisplayEvent::Done(done) = event {
+			if let Err(e) = set_clipboard(done.translation.trim()) {
+				eprintln!("Copying the translation failed: {:#}", e);
+			}
+		}
+	}
+}
+
+fn open_append(path: &Path) -> Result<File> {
+	OpenOptions::new().create(true).append(true).open(path).map_err(|e| anyhow!("Opening {} failed: {}", path.display(), e))
+}
+
+/// "2026-01-31T12:34:56.789Z"
+pub fn utc_timestamp(time: SystemTime) -> String {
+	let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+	let seconds = since_epoch.as_secs();
+	let (year, month, day) = civil_date(seconds / 86400);
+	format!(
+		"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+		year, month, day, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60, since_epoch.subsec_millis(),
+	)
+}
+
+/// Reads utc_timestamp() output back, the fraction is optional
+pub fn parse_utc_timestamp(timestamp: &str) -> Option<SystemTime> {
+	let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+	let mut date = date.splitn(3, '-').map(|part| part.parse::<u64>().ok());
+	let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+	let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+	let mut time = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+	let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+		return None;
+	}
+	let millis = match fraction {
+		"" => 0,
+		fraction => format!("{:0<3}", fraction).get(..3)?.parse::<u64>().ok()?,
+	};
+	let seconds = days_since_epoch(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+	Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis))
+}
+
+/// Days since 1970-01-01 to (year, month, day), see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_date(days: u64) -> (u64, u64, u64) {
+	let days = days + 719468;
+	let era = days / 146097;
+	let day_of_era = days % 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+	let year = year_of_era + era * 400 + u64::from(month <= 2);
+	(year, month, day)
+}
+
+/// Inverse of civil_date(), see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year / 400;
+	let year_of_era = year % 400;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+#[cfg(target_os = "windows")]
+fn set_clipboard(text: &str) -> Result<()> {
+	use windows::Win32::Foundation::{GlobalFree, HANDLE, HWND};
+	use windows::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData};
+	use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+	use windows::Win32::System::Ole::CF_UNICODETEXT;
+	let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+	unsafe {
+		OpenClipboard(HWND(0))?;
+		let result = (|| -> Result<()> {
+			EmptyClipboard()?;
+			let memory = GlobalAlloc(GMEM_MOVEABLE, text.len() * 2)?;
+			let buffer = GlobalLock(memory) as *mut u16;
+			if buffer.is_null() {
+				let _ = GlobalFree(memory);
+				return Err(anyhow!("Clipboard memory allocation failed"));
+			}
+			std::ptr::copy_nonoverlapping(text.as_ptr(), buffer, text.len());
+			let _ = GlobalUnlock(memory);
+			// The clipboard owns the memory once this succeeds
+			if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(memory.0 as isize)) {
+				let _ = GlobalFree(memory);
+				return Err(e.into());
+			}
+			Ok(())
+		})();
+		let _ = CloseClipboard();
+		result
+	}
+}
+
+/// Through the first clipboard tool found
+#[cfg(not(target_os = "windows"))]
+fn set_clipboard(text: &str) -> Result<()> {
+	use std::process::{Command, Stdio};
+	let tools: [&[&str]; 3] = [&["wl-copy"], &["xclip", "-selection", "clipboard"], &["pbcopy"]];
+	for tool in tools {
+		let Ok(mut child) = Command::new(tool[0]).args(&tool[1..]).stdin(Stdio::piped()).spawn() else {
+			continue;
+		};
+		child.stdin.take().expect("stdin is piped").write_all(text.as_bytes())?;
+		let status = child.wait()?;
+		if !status.success() {
+			return Err(anyhow!("{} failed: {}", tool[0], status));
+		}
+		return Ok(());
+	}
+	Err(anyhow!("No clipboard tool found, install wl-copy or xclip"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formats_utc_timestamps() {
+		assert_eq!(utc_timestamp(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+		assert_eq!(utc_timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00.000Z");
+		assert_eq!(utc_timestamp(SystemTime::UNIX_EPOCH + Duration::from_millis(1_792_355_242_050)), "2026-10-18T20:27:22.050Z");
+	}
+
+	#[test]
+	fn parses_utc_timestamps() {
+		for millis in [0, 951_782_400_000, 1_792_355_242_050, 4_107_542_399_999] {
+			let time = SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+			assert_eq!(parse_utc_timestamp(&utc_timestamp(time)), Some(time));
+		}
+		assert_eq!(parse_utc_timestamp("2026-10-18T20:27:22Z"), Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_355_242)));
+		assert_eq!(parse_utc_timestamp("2026-10-18T20:27:22.5Z"), Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_792_355_242_500)));
+		assert_eq!(parse_utc_timestamp("2026-13-18T20:27:22Z"), None);
+		assert_eq!(parse_utc_timestamp("yesterday"), None);
+	}
+
+	#[test]
+	fn jsonl_record_fields() {
+		let done = TranslationDone {
+			origin: Origin::Capture,
+			source: "こんにちは\n".to_string(),
+			translation: "Hello\n".to_string(),
+			target_lang: "English".to_string(),
+			model: "gpt-4o".to_string(),
+			region: Some("(0, 1, 0, 1)".to_string()),
+			latency: Duration::from_millis(1234),
+		};
+		assert_eq!(jsonl_record(&done, SystemTime::UNIX_EPOCH), json!({
+			"timestamp": "1970-01-01T00:00:00.000Z",
+			"origin": "capture",
+			"region": "(0, 1, 0, 1)",
+			"source": "こんにちは",
+			"translation": "Hello",
+			"target_lang": "English",
+			"model": "gpt-4o",
+			"latency_ms": 1234,
+		}));
+	}
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+use anyhow::Result;
+#[cfg(not(target_os = "windows"))]
+use anyhow::anyhow;
+
+#[cfg(target_os = "windows")]
+mod windows;
+
+/// The window that has the keyboard focus
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForegroundWindow {
+	/// Executable file name, ex. "P5R.exe". Empty if the process can't be queried(elevated or protected)
+	pub process: String,
+	pub title: String,
+}
+
+/// Ok(None) while no window has the focus, e.g. during an alt-tab
+pub fn foreground_window() -> Result<Option<ForegroundWindow>> {
+	#[cfg(target_os = "windows")]
+	return Ok(windows::foreground_window());
+	#[cfg(not(target_os = "windows"))]
+	return Err(anyhow!("Foreground window detection is only supported on Windows"));
+}
+
+/// WARNING: Blocking function, only returns when detection is not supported
+///
+/// on_change() is called every time another window gets the focus, including the first one seen
+pub fn foreground_watcher(poll_interval: Duration, mut on_change: impl FnMut(&ForegroundWindow)) -> Result<()> {
+	let mut last_window = None;
+	loop {
+		if let Some(window) = foreground_window()? {
+			if last_window.as_ref() != Some(&window) {
+				on_change(&window);
+				last_window = Some(window);
+			}
+		}
+		std::thread::sleep(poll_interval);
+	}
+}
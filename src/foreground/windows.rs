@@ -0,0 +1,40 @@
+use windows::{
+	core::PWSTR,
+	Win32::{
+		Foundation::{CloseHandle, MAX_PATH},
+		System::Threading::{OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION},
+		UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId},
+	},
+};
+use super::ForegroundWindow;
+
+pub fn foreground_window() -> Option<ForegroundWindow> {
+	unsafe {
+		let hwnd = GetForegroundWindow();
+		if hwnd.0 == 0 {
+			return None;
+		}
+		let mut title = [0u16; 512];
+		let title_len = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+		let mut process_id = 0;
+		GetWindowThreadProcessId(hwnd, Some(&mut process_id as *mut u32));
+		Some(ForegroundWindow {
+			process: process_name(process_id).unwrap_or_default(),
+			title: String::from_utf16_lossy(&title[..title_len]),
+		})
+	}
+}
+
+/// Executable file name without its directory
+fn process_name(process_id: u32) -> Option<String> {
+	unsafe {
+		let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+		let mut path = [0u16; MAX_PATH as usize];
+		let mut path_len = path.len() as u32;
+		let result = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut path_len);
+		let _ = CloseHandle(process);
+		result.ok()?;
+		let path = String::from_utf16_lossy(&path[..path_len as usize]);
+		path.rsplit(['\\', '/']).next().map(str::to_string)
+	}
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use crate::display::{DisplayEvent, DisplaySink, Origin};
+use crate::session::{self, SessionEntry};
+
+/// Older entries are dropped past this
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+	pub time: SystemTime,
+	pub origin: Origin,
+	pub source: String,
+	pub translation: String,
+	pub model: String,
+}
+
+impl From<SessionEntry> for HistoryEntry {
+	fn from(entry: SessionEntry) -> Self {
+		Self {
+			time: entry.time,
+			origin: entry.origin,
+			source: entry.source,
+			translation: entry.translation,
+			model: entry.model,
+		}
+	}
+}
+
+#[derive(Default)]
+struct Entries {
+	entries: VecDeque<HistoryEntry>,
+	/// Entry shown by step(), None while following the latest one
+	cursor: Option<usize>,
+}
+
+/// Translations of this session and, when loaded from_sessions(), of the recorded ones before it.
+/// Cloned handles share the entries
+#[derive(Clone, Default)]
+pub struct History {
+	entries: Arc<Mutex<Entries>>,
+}
+
+impl History {
+	/// The last translations of the sessions recorded in dir(see the session module), the new ones are recorded
+	/// there by the SessionRecorder so they are back on the next start
+	pub fn from_sessions(dir: &Path) -> Result<Self> {
+		let mut entries = VecDeque::new();
+		for path in session::list(dir)?.iter().rev() {
+			// One unreadable session doesn't lose the others
+			let session = match session::load(path) {
+				Ok(session) => session,
+				Err(e) => {
+					eprintln!("Skipping session {}: {:#}", path.display(), e);
+					continue;
+				},
+			};
+			let count = session.len().min(MAX_ENTRIES - entries.len());
+			for entry in session.into_iter().rev().take(count) {
+				entries.push_front(HistoryEntry::from(entry));
+			}
+			if entries.len() == MAX_ENTRIES {
+				break;
+			}
+		}
+		Ok(Self { entries: Arc::new(Mutex::new(Entries { entries, cursor: None })) })
+	}
+
+	/// Browsing goes back to following the latest entry
+	pub fn push(&self, entry: HistoryEntry) {
+		let mut entries = self.entries.lock().unwrap();
+		if entries.entries.len() >= MAX_ENTRIES {
+			entries.entries.pop_front();
+		}
+		entries.entries.push_back(entry);
+		entries.cursor = None;
+	}
+
+	/// The last count entries, oldest first
+	pub fn recent(&self, count: usize) -> Vec<HistoryEntry> {
+		let entries = self.entries.lock().unwrap();
+		entries.entries.iter().skip(entries.entries.len().saturating_sub(count)).cloned().collect()
+	}
+
+	/// Entry back entries before the latest one, 0 is the latest
+	pub fn back(&self, back: usize) -> Option<HistoryEntry> {
+		let entries = self.entries.lock().unwrap();
+		entries.entries.iter().rev().nth(back).cloned()
+	}
+
+	/// Move the browsing cursor by offset entries(negative goes back), stopping at the oldest and latest ones.
+	/// Returns the entry with its position counting from 1 and the entry count, None without entries
+	pub fn step(&self, offset: isize) -> Option<(usize, usize, HistoryEntry)> {
+		let mut entries = self.entries.lock().unwrap();
+		let count = entries.entries.len();
+		let current = entries.cursor.unwrap_or(count.checked_sub(1)?);
+		let position = current.saturating_add_signed(offset).min(count - 1);
+		entries.cursor = Some(position);
+		Some((position + 1, count, entries.entries[position].clone()))
+	}
+}
+
+/// Records every finished translation
+pub struct HistorySink {
+	history: History,
+}
+
+impl HistorySink {
+	pub fn new(history: History) -> Self {
+		Self { history }
+	}
+}
+
+impl DisplaySink for HistorySink {
+	fn handle(&mut self, event: &DisplayEvent) {
+		if let DisplayEvent::Done(done) = event {
+			self.history.push(HistoryEntry {
+				time: SystemTime::now(),
+				origin: done.origin,
+				source: done.source.clone(),
+				translation: done.translation.clone(),
+				model: done.model.clone(),
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(translation: &str) -> HistoryEntry {
+		HistoryEntry {
+			time: SystemTime::UNIX_EPOCH,
+			origin: Origin::Capture,
+			source: String::new(),
+			translation: translation.to_string(),
+			model: "gpt-4o".to_string(),
+		}
+	}
+
+	#[test]
+	fn steps_through_entries() {
+		let history = History::default();
+		assert!(history.step(-1).is_none());
+		for translation in ["a", "b", "c"] {
+			history.push(entry(translation));
+		}
+		let step = |offset| history.step(offset).map(|(position, count, entry)| (position, count, entry.translation));
+		assert_eq!(step(-1), Some((2, 3, "b".to_string())));
+		assert_eq!(step(-1), Some((1, 3, "a".to_string())));
+		assert_eq!(step(-1), Some((1, 3, "a".to_string())));
+		assert_eq!(step(1), Some((2, 3, "b".to_string())));
+		// A new translation is shown, browsing starts from it again
+		history.push(entry("d"));
+		assert_eq!(step(-1), Some((3, 4, "c".to_string())));
+		assert_eq!(step(5), Some((4, 4, "d".to_string())));
+		assert_eq!(history.back(1).map(|entry| entry.translation), Some("c".to_string()));
+	}
+}
@@ -0,0 +1,400 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const DEFAULT_HOLD: Duration = Duration::from_millis(800);
+const DEFAULT_DOUBLE_TAP_WINDOW: Duration = Duration::from_millis(300);
+const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+	/// Every input held together
+	Chord,
+	/// Every input held together for at least this long
+	Hold(Duration),
+	/// The chord pressed twice, second press within this window of the first
+	DoubleTap(Duration),
+	/// Inputs pressed one after another, each within this timeout of the previous one
+	Sequence(Duration),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FireOn {
+	Press,
+	Release,
+}
+
+/// A combo over any kind of input, B is a gamepad button or a keyboard hotkey
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComboSpec<B> {
+	/// Chord members, or sequence steps in order
+	pub inputs: Vec<B>,
+	pub pattern: Pattern,
+	pub fire_on: FireOn,
+	/// An input has to stay in its new state this long before the change counts
+	pub debounce: Duration,
+}
+
+/// Parse "<inputs> [options]", inputs are split on ',' into sequence steps and each step is handed to parse_step.
+/// Options: hold[=800ms], double[=300ms], within=1s(sequence timeout), press, release, debounce=20ms
+pub fn parse_combo<B: PartialEq>(spec: &str, default_fire_on: FireOn, parse_step: impl Fn(&str) -> Result<Vec<B>>) -> Result<ComboSpec<B>> {
+	let mut hold = None;
+	let mut double_tap = None;
+	let mut sequence_timeout = None;
+	let mut fire_on = default_fire_on;
+	let mut debounce = Duration::ZERO;
+	let mut input_tokens = Vec::new();
+	for token in spec.split_whitespace() {
+		let (name, value) = match token.split_once('=') {
+			Some((name, value)) => (name, Some(value)),
+			None => (token, None),
+		};
+		let duration = |default: Duration| value.map_or(Ok(default), parse_duration);
+		match name.to_ascii_lowercase().as_str() {
+			"hold" => hold = Some(duration(DEFAULT_HOLD)?),
+			"double" => double_tap = Some(duration(DEFAULT_DOUBLE_TAP_WINDOW)?),
+			"within" => sequence_timeout = Some(duration(DEFAULT_SEQUENCE_TIMEOUT)?),
+			"debounce" => debounce = duration(Duration::ZERO)?,
+			"press" if value.is_none() => fire_on = FireOn::Press,
+			"release" if value.is_none() => fire_on = FireOn::Release,
+			_ if value.is_some() => return Err(anyhow!("Unknown option \"{}\" in combo \"{}\"", token, spec)),
+			_ => input_tokens.push(token),
+		}
+	}
+	let inputs_spec = input_tokens.join(" ");
+	if inputs_spec.is_empty() {
+		return Err(anyhow!("Combo \"{}\" has no inputs", spec));
+	}
+	let steps: Vec<&str> = inputs_spec.split(',').map(str::trim).collect();
+	let (inputs, pattern) = if steps.len() > 1 {
+		if hold.is_some() || double_tap.is_some() {
+			return Err(anyhow!("Combo \"{}\": a sequence can't also be hold or double", spec));
+		}
+		let inputs = steps.iter().map(|step| {
+			let mut inputs = parse_step(step)?;
+			match inputs.len() {
+				1 => Ok(inputs.remove(0)),
+				_ => Err(anyhow!("Combo \"{}\": each sequence step must be a single input, got \"{}\"", spec, step)),
+			}
+		}).collect::<Result<Vec<B>>>()?;
+		(inputs, Pattern::Sequence(sequence_timeout.unwrap_or(DEFAULT_SEQUENCE_TIMEOUT)))
+	} else {
+		let mut inputs = parse_step(steps[0])?;
+		inputs.dedup();
+		let pattern = match (hold, double_tap) {
+			(Some(_), Some(_)) => return Err(anyhow!("Combo \"{}\" can't be both hold and double", spec)),
+			(Some(duration), None) => Pattern::Hold(duration),
+			(None, Some(window)) => Pattern::DoubleTap(window),
+			(None, None) => Pattern::Chord,
+		};
+		(inputs, pattern)
+	};
+	if inputs.is_empty() {
+		return Err(anyhow!("Combo \"{}\" has no inputs", spec));
+	}
+	Ok(ComboSpec { inputs, pattern, fire_on, debounce })
+}
+
+/// "300ms", "1.5s" or a bare number of milliseconds
+fn parse_duration(value: &str) -> Result<Duration> {
+	let (number, unit_ms) = if let Some(number) = value.strip_suffix("ms") {
+		(number, 1.0)
+	} else if let Some(number) = value.strip_suffix('s') {
+		(number, 1000.0)
+	} else {
+		(value, 1.0)
+	};
+	match number.parse::<f64>() {
+		Ok(number) if number >= 0.0 => Ok(Duration::from_secs_f64(number * unit_ms / 1000.0)),
+		_ => Err(anyhow!("Invalid duration \"{}\", use something like 300ms or 1.5s", value)),
+	}
+}
+
+impl<B: fmt::Display> fmt::Display for ComboSpec<B> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let separator = if matches!(self.pattern, Pattern::Sequence(_)) { ", " } else { " + " };
+		let inputs: Vec<String> = self.inputs.iter().map(ToString::to_string).collect();
+		write!(f, "{}", inputs.join(separator))?;
+		match self.pattern {
+			Pattern::Hold(duration) => write!(f, " (hold {:.1}s)", duration.as_secs_f64()),
+			Pattern::DoubleTap(_) => write!(f, " (double tap)"),
+			Pattern::Chord | Pattern::Sequence(_) => Ok(()),
+		}
+	}
+}
+
+/// Tracks one ComboSpec, feed it every input sample with update()
+#[derive(Clone, Debug)]
+pub struct ComboMachine<B> {
+	spec: ComboSpec<B>,
+	/// Distinct inputs, sequences may repeat one
+	distinct: Vec<B>,
+	/// spec.inputs as indexes of distinct
+	steps: Vec<usize>,
+	held: Vec<bool>,
+	pending_since: Vec<Option<Instant>>,
+	chord_held_since: Option<Instant>,
+	hold_fired: bool,
+	first_tap: Option<Instant>,
+	second_tap: bool,
+	next_step: usize,
+	last_step_at: Option<Instant>,
+	awaiting_release: Option<usize>,
+}
+
+impl<B: PartialEq + Clone> ComboMachine<B> {
+	pub fn new(spec: ComboSpec<B>) -> Self {
+		let mut distinct: Vec<B> = Vec::new();
+		let steps = spec.inputs.iter().map(|input| {
+			distinct.iter().position(|known| known == input).unwrap_or_else(|| {
+				distinct.push(input.clone());
+				distinct.len() - 1
+			})
+		}).collect();
+		let input_count = distinct.len();
+		Self {
+			spec,
+			distinct,
+			steps,
+			held: vec![false; input_count],
+			pending_since: vec![None; input_count],
+			chord_held_since: None,
+			hold_fired: false,
+			first_tap: None,
+			second_tap: false,
+			next_step: 0,
+			last_step_at: None,
+			awaiting_release: None,
+		}
+	}
+
+	pub fn spec(&self) -> &ComboSpec<B> {
+		&self.spec
+	}
+
+	/// Forget everything, e.g. the controller was disconnected
+	pub fn reset(&mut self) {
+		*self = Self::new(self.spec.clone());
+	}
+
+	/// Sample every input with is_held at time now, returns true when the combo fires
+	pub fn update(&mut self, is_held: impl Fn(&B) -> bool, now: Instant) -> bool {
+		let mut pressed = Vec::new();
+		let mut released = Vec::new();
+		for (i, input) in self.distinct.iter().enumerate() {
+			let raw_held = is_held(input);
+			if raw_held == self.held[i] {
+				self.pending_since[i] = None;
+				continue;
+			}
+			let since = *self.pending_since[i].get_or_insert(now);
+			if now.duration_since(since) >= self.spec.debounce {
+				self.held[i] = raw_held;
+				self.pending_since[i] = None;
+				if raw_held { pressed.push(i) } else { released.push(i) }
+			}
+		}
+		match self.spec.pattern {
+			Pattern::Sequence(timeout) => self.update_sequence(&pressed, &released, timeout, now),
+			_ => self.update_chord(now),
+		}
+	}
+
+	fn update_chord(&mut self, now: Instant) -> bool {
+		let fire_on_press = self.spec.fire_on == FireOn::Press;
+		let all_held = self.held.iter().all(|held| *held);
+		match (self.chord_held_since, all_held) {
+			(None, true) => {
+				self.chord_held_since = Some(now);
+				self.hold_fired = false;
+				match self.spec.pattern {
+					Pattern::Chord => fire_on_press,
+					Pattern::Hold(_) => self.check_hold(now),
+					Pattern::DoubleTap(window) => {
+						if self.first_tap.is_some_and(|first_tap| now.duration_since(first_tap) <= window) {
+							self.first_tap = None;
+							self.second_tap = true;
+							fire_on_press
+						} else {
+							self.first_tap = Some(now);
+							false
+						}
+					},
+					Pattern::Sequence(_) => false,
+				}
+			},
+			(Some(_), true) => self.check_hold(now),
+			(Some(since), false) => {
+				self.chord_held_since = None;
+				match self.spec.pattern {
+					Pattern::Chord => !fire_on_press,
+					Pattern::Hold(duration) => !fire_on_press && now.duration_since(since) >= duration,
+					Pattern::DoubleTap(_) => std::mem::take(&mut self.second_tap) && !fire_on_press,
+					Pattern::Sequence(_) => false,
+				}
+			},
+			(None, false) => false,
+		}
+	}
+
+	/// Fire-on-press hold combos fire once the chord has been held long enough, without waiting for release
+	fn check_hold(&mut self, now: Instant) -> bool {
+		let (Pattern::Hold(duration), FireOn::Press, Some(since)) = (self.spec.pattern, self.spec.fire_on, self.chord_held_since) else {
+			return false;
+		};
+		if self.hold_fired || now.duration_since(since) < duration {
+			return false;
+		}
+		self.hold_fired = true;
+		true
+	}
+
+	fn update_sequence(&mut self, pressed: &[usize], released: &[usize], timeout: Duration, now: Instant) -> bool {
+		let mut fired = false;
+		for &input in pressed {
+			if self.last_step_at.is_some_and(|last_step_at| now.duration_since(last_step_at) > timeout) {
+				self.next_step = 0;
+			}
+			self.awaiting_release = None;
+			if input == self.steps[self.next_step] {
+				self.next_step += 1;
+			} else {
+				// A wrong input may still be the start of a new attempt
+				self.next_step = usize::from(input == self.steps[0]);
+			}
+			self.last_step_at = Some(now);
+			if self.next_step == self.steps.len() {
+				self.next_step = 0;
+				match self.spec.fire_on {
+					FireOn::Press => fired = true,
+					FireOn::Release => self.awaiting_release = Some(input),
+				}
+			}
+		}
+		for &input in released {
+			if self.awaiting_release == Some(input) {
+				self.awaiting_release = None;
+				fired = true;
+			}
+		}
+		fired
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TICK: Duration = Duration::from_millis(10);
+
+	fn spec(inputs: &[char], pattern: Pattern, fire_on: FireOn) -> ComboSpec<char> {
+		ComboSpec { inputs: inputs.to_vec(), pattern, fire_on, debounce: Duration::ZERO }
+	}
+
+	/// Feed one sample per TICK, each sample is the set of held inputs, returns the sample indexes where the combo fired
+	fn run(spec: ComboSpec<char>, samples: &[&str]) -> Vec<usize> {
+		let mut machine = ComboMachine::new(spec);
+		let start = Instant::now();
+		samples.iter().enumerate()
+			.filter(|(i, held)| machine.update(|input| held.contains(*input), start + TICK * *i as u32))
+			.map(|(i, _)| i)
+			.collect()
+	}
+
+	fn two_key_release() -> ComboSpec<char> {
+		spec(&['a', 'b'], Pattern::Chord, FireOn::Release)
+	}
+
+	#[test]
+	fn fires_on_release_after_both_held() {
+		assert_eq!(run(two_key_release(), &["a", "ab", "ab", ""]), vec![3]);
+		assert_eq!(run(two_key_release(), &["b", "ab", "b"]), vec![2]);
+	}
+
+	#[test]
+	fn single_key_never_fires() {
+		assert!(run(two_key_release(), &["a", "a", ""]).is_empty());
+		assert!(run(two_key_release(), &["b", "", "b", ""]).is_empty());
+	}
+
+	#[test]
+	fn holding_both_fires_once() {
+		let mut samples = vec!["a"];
+		samples.extend(["ab"; 100]);
+		samples.push("");
+		assert_eq!(run(two_key_release(), &samples), vec![101]);
+	}
+
+	#[test]
+	fn simultaneous_press_counts_immediately() {
+		assert_eq!(run(two_key_release(), &["ab", ""]), vec![1]);
+	}
+
+	#[test]
+	fn releasing_one_key_rearms_with_the_other_held() {
+		assert_eq!(run(two_key_release(), &["a", "ab", "a", "ab", "ab", ""]), vec![2, 5]);
+	}
+
+	#[test]
+	fn single_button_combo() {
+		assert_eq!(run(spec(&['a'], Pattern::Chord, FireOn::Release), &["a", "a", ""]), vec![2]);
+		assert_eq!(run(spec(&['a'], Pattern::Chord, FireOn::Press), &["a", "a", "", "a"]), vec![0, 3]);
+	}
+
+	#[test]
+	fn n_button_chord_fires_on_press() {
+		let chord = spec(&['a', 'b', 'c'], Pattern::Chord, FireOn::Press);
+		assert_eq!(run(chord.clone(), &["a", "ab", "abc", "abc", "ab", "abc"]), vec![2, 5]);
+		assert!(run(chord, &["ab", "bc", "ac", ""]).is_empty());
+	}
+
+	#[test]
+	fn hold_fires_after_duration() {
+		let hold = |fire_on| spec(&['a'], Pattern::Hold(TICK * 3), fire_on);
+		assert_eq!(run(hold(FireOn::Press), &["a", "a", "a", "a", "a", ""]), vec![3]);
+		assert_eq!(run(hold(FireOn::Release), &["a", "a", "a", "a", "a", ""]), vec![5]);
+		assert!(run(hold(FireOn::Press), &["a", "a", "", "a", "a", ""]).is_empty());
+		assert!(run(hold(FireOn::Release), &["a", "a", ""]).is_empty());
+	}
+
+	#[test]
+	fn double_tap_within_window() {
+		let double = |fire_on| spec(&['a'], Pattern::DoubleTap(TICK * 3), fire_on);
+		assert_eq!(run(double(FireOn::Press), &["a", "", "a", ""]), vec![2]);
+		assert_eq!(run(double(FireOn::Release), &["a", "", "a", "a", ""]), vec![4]);
+		assert!(run(double(FireOn::Press), &["a", "", "", "", "a", ""]).is_empty());
+		// A third tap starts a new double tap instead of firing again
+		assert_eq!(run(double(FireOn::Press), &["a", "", "a", "", "a", "", "a"]), vec![2, 6]);
+	}
+
+	#[test]
+	fn sequence_in_order_within_timeout() {
+		let sequence = |fire_on| spec(&['a', 'a', 'b'], Pattern::Sequence(TICK * 3), fire_on);
+		assert_eq!(run(sequence(FireOn::Press), &["a", "", "a", "", "b", ""]), vec![4]);
+		assert_eq!(run(sequence(FireOn::Release), &["a", "", "a", "", "b", "b", ""]), vec![6]);
+		assert!(run(sequence(FireOn::Press), &["a", "", "b", "", "a", "", "b"]).is_empty());
+		assert!(run(sequence(FireOn::Press), &["a", "", "", "", "", "a", "", "b"]).is_empty());
+		// A wrong input restarts the sequence if it is the first step
+		assert_eq!(run(sequence(FireOn::Press), &["a", "", "b", "a", "", "a", "", "b"]), vec![7]);
+	}
+
+	#[test]
+	fn debounce_ignores_glitches() {
+		let mut chord = spec(&['a'], Pattern::Chord, FireOn::Release);
+		chord.debounce = TICK * 2;
+		assert!(run(chord.clone(), &["a", "", "a", ""]).is_empty());
+		assert_eq!(run(chord, &["a", "a", "a", "", "a", "a", "", "", ""]), vec![8]);
+	}
+
+	#[test]
+	fn parse_options() {
+		let parse_chars = |step: &str| Ok(step.split('+').filter_map(|s| s.trim().chars().next()).collect::<Vec<char>>());
+		assert_eq!(parse_combo("a+b", FireOn::Release, parse_chars).unwrap(), spec(&['a', 'b'], Pattern::Chord, FireOn::Release));
+		assert_eq!(parse_combo("a hold=1.5s press", FireOn::Release, parse_chars).unwrap(), spec(&['a'], Pattern::Hold(Duration::from_millis(1500)), FireOn::Press));
+		assert_eq!(parse_combo("a double", FireOn::Press, parse_chars).unwrap(), spec(&['a'], Pattern::DoubleTap(DEFAULT_DOUBLE_TAP_WINDOW), FireOn::Press));
+		assert_eq!(parse_combo("a, a, b within=500", FireOn::Press, parse_chars).unwrap(), spec(&['a', 'a', 'b'], Pattern::Sequence(Duration::from_millis(500)), FireOn::Press));
+		assert!(parse_combo("a+b, c", FireOn::Press, parse_chars).is_err());
+		assert!(parse_combo("a hold double", FireOn::Press, parse_chars).is_err());
+		assert!(parse_combo("a hold=soon", FireOn::Press, parse_chars).is_err());
+	}
+}
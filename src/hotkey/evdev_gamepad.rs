@@ -0,0 +1,138 @@
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSetRef, Device, Key};
+use anyhow::{anyhow, Result};
+use super::gamepad::{Buttons, Gamepad, GamepadButton, GamepadPoll};
+
+/// Reads /dev/input/event* directly, the user needs to be in the "input" group
+pub struct EvdevGamepad {
+	/// Fixed controller, the first one found if None
+	index: Option<u32>,
+	device: Option<Device>,
+	/// The kernel state is read on every poll, only changes are reported
+	last_buttons: Option<Buttons>,
+}
+
+impl EvdevGamepad {
+	/// A missing controller is not an error here, poll() keeps looking for it like XInput does
+	pub fn open(index: Option<u32>) -> Result<Self> {
+		Ok(Self {
+			index,
+			device: None,
+			last_buttons: None,
+		})
+	}
+}
+
+impl Gamepad for EvdevGamepad {
+	fn poll(&mut self) -> Result<GamepadPoll> {
+		let (device, connected) = match self.device.take() {
+			Some(device) => (device, false),
+			None => (find_gamepad(self.index)?, true),
+		};
+		let buttons = read_buttons(&device).map_err(|e| anyhow!("Controller disconnected: {}", e))?;
+		let name = device.name().unwrap_or("unnamed controller").to_string();
+		self.device = Some(device);
+		if connected {
+			self.last_buttons = Some(buttons);
+			return Ok(GamepadPoll::Connected(name, buttons));
+		}
+		if self.last_buttons == Some(buttons) {
+			return Ok(GamepadPoll::Unchanged);
+		}
+		self.last_buttons = Some(buttons);
+		Ok(GamepadPoll::Buttons(buttons))
+	}
+}
+
+/// index counts only devices with gamepad buttons, ordered by device path
+fn find_gamepad(index: Option<u32>) -> Result<Device> {
+	let mut gamepads: Vec<_> = evdev::enumerate()
+		.filter(|(_, device)| device.supported_keys().is_some_and(|keys| keys.contains(Key::BTN_SOUTH)))
+		.collect();
+	gamepads.sort_by(|(a, _), (b, _)| a.cmp(b));
+	gamepads.into_iter().nth(index.unwrap_or(0) as usize).map(|(_, device)| device).ok_or_else(|| match index {
+		Some(index) => anyhow!("Controller {} not found, check it is connected and /dev/input/event* is readable(input group)", index),
+		None => anyhow!("No controller found, check one is connected and /dev/input/event* is readable(input group)"),
+	})
+}
+
+fn read_buttons(device: &Device) -> std::io::Result<Buttons> {
+	let keys = device.get_key_state()?;
+	let axes = device.supported_absolute_axes();
+	let abs_state = if axes.is_some() { Some(device.get_abs_state()?) } else { None };
+	let axis = |axis: AbsoluteAxisType| {
+		if !axes.is_some_and(|axes| axes.contains(axis)) {
+			return None;
+		}
+		abs_state.as_ref().map(|state| {
+			let info = state[axis.0 as usize];
+			AbsInfo::new(info.value, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution)
+		})
+	};
+	Ok(decode_buttons(&keys, axis))
+}
+
+/// axis() is None for the axes the device doesn't have.
+/// D-Pads are usually a hat axis, triggers an analog axis resting at its minimum
+fn decode_buttons(keys: &AttributeSetRef<Key>, axis: impl Fn(AbsoluteAxisType) -> Option<AbsInfo>) -> Buttons {
+	let hat = |hat_axis: AbsoluteAxisType, direction: i32| axis(hat_axis).is_some_and(|info| info.value() * direction > 0);
+	let trigger = |trigger_axis: AbsoluteAxisType| axis(trigger_axis).is_some_and(|info| {
+		info.minimum() >= 0 && info.value() > info.minimum() + (info.maximum() - info.minimum()) / 2
+	});
+	Buttons::from_states([
+		(GamepadButton::South, keys.contains(Key::BTN_SOUTH)),
+		(GamepadButton::East, keys.contains(Key::BTN_EAST)),
+		(GamepadButton::West, keys.contains(Key::BTN_WEST)),
+		(GamepadButton::North, keys.contains(Key::BTN_NORTH)),
+		(GamepadButton::DPadUp, keys.contains(Key::BTN_DPAD_UP) || hat(AbsoluteAxisType::ABS_HAT0Y, -1)),
+		(GamepadButton::DPadDown, keys.contains(Key::BTN_DPAD_DOWN) || hat(AbsoluteAxisType::ABS_HAT0Y, 1)),
+		(GamepadButton::DPadLeft, keys.contains(Key::BTN_DPAD_LEFT) || hat(AbsoluteAxisType::ABS_HAT0X, -1)),
+		(GamepadButton::DPadRight, keys.contains(Key::BTN_DPAD_RIGHT) || hat(AbsoluteAxisType::ABS_HAT0X, 1)),
+		(GamepadButton::Select, keys.contains(Key::BTN_SELECT)),
+		(GamepadButton::Start, keys.contains(Key::BTN_START)),
+		(GamepadButton::Guide, keys.contains(Key::BTN_MODE)),
+		(GamepadButton::LeftShoulder, keys.contains(Key::BTN_TL)),
+		(GamepadButton::RightShoulder, keys.contains(Key::BTN_TR)),
+		(GamepadButton::LeftTrigger, keys.contains(Key::BTN_TL2) || trigger(AbsoluteAxisType::ABS_Z)),
+		(GamepadButton::RightTrigger, keys.contains(Key::BTN_TR2) || trigger(AbsoluteAxisType::ABS_RZ)),
+		(GamepadButton::LeftThumb, keys.contains(Key::BTN_THUMBL)),
+		(GamepadButton::RightThumb, keys.contains(Key::BTN_THUMBR)),
+	])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evdev::AttributeSet;
+
+	fn decode(keys: &[Key], axes: &[(AbsoluteAxisType, AbsInfo)]) -> Vec<GamepadButton> {
+		let keys: AttributeSet<Key> = keys.iter().collect();
+		let buttons = decode_buttons(&keys, |axis| axes.iter().find(|(known, _)| *known == axis).map(|(_, info)| *info));
+		[GamepadButton::South, GamepadButton::East, GamepadButton::DPadUp, GamepadButton::DPadDown, GamepadButton::DPadLeft, GamepadButton::DPadRight,
+			GamepadButton::Select, GamepadButton::Guide, GamepadButton::RightShoulder, GamepadButton::LeftTrigger, GamepadButton::RightTrigger]
+				.into_iter().filter(|button| buttons.contains(*button)).collect()
+	}
+
+	fn axis(value: i32, minimum: i32, maximum: i32) -> AbsInfo {
+		AbsInfo::new(value, minimum, maximum, 0, 0, 0)
+	}
+
+	#[test]
+	fn maps_keys() {
+		assert_eq!(decode(&[], &[]), []);
+		assert_eq!(decode(&[Key::BTN_SOUTH, Key::BTN_SELECT, Key::BTN_MODE, Key::BTN_TR], &[]), [GamepadButton::South, GamepadButton::Select, GamepadButton::Guide, GamepadButton::RightShoulder]);
+		// D-Pad and triggers as buttons
+		assert_eq!(decode(&[Key::BTN_DPAD_UP, Key::BTN_TR2], &[]), [GamepadButton::DPadUp, GamepadButton::RightTrigger]);
+	}
+
+	#[test]
+	fn maps_hat_and_trigger_axes() {
+		let hat = |x, y| [(AbsoluteAxisType::ABS_HAT0X, axis(x, -1, 1)), (AbsoluteAxisType::ABS_HAT0Y, axis(y, -1, 1))];
+		assert_eq!(decode(&[], &hat(0, 0)), []);
+		assert_eq!(decode(&[], &hat(-1, 1)), [GamepadButton::DPadDown, GamepadButton::DPadLeft]);
+		assert_eq!(decode(&[], &hat(1, -1)), [GamepadButton::DPadUp, GamepadButton::DPadRight]);
+		assert_eq!(decode(&[], &[(AbsoluteAxisType::ABS_Z, axis(200, 0, 255))]), [GamepadButton::LeftTrigger]);
+		assert_eq!(decode(&[], &[(AbsoluteAxisType::ABS_Z, axis(100, 0, 255))]), []);
+		// A right stick axis on pads without analog triggers
+		assert_eq!(decode(&[], &[(AbsoluteAxisType::ABS_RZ, axis(30000, -32768, 32767))]), []);
+	}
+}
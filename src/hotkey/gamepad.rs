@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::str::FromStr;
+use super::Action;
+use super::combo::{parse_combo, ComboSpec, FireOn};
+
+/// Buttons named after their position(Xbox layout in parentheses)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadButton {
+	South, // A
+	East, // B
+	West, // X
+	North, // Y
+	DPadUp,
+	DPadDown,
+	DPadLeft,
+	DPadRight,
+	Select, // Back
+	Start,
+	Guide,
+	LeftShoulder, // LB
+	RightShoulder, // RB
+	LeftTrigger, // LT
+	RightTrigger, // RT
+	LeftThumb, // LS click
+	RightThumb, // RS click
+}
+
+impl FromStr for GamepadButton {
+	type Err = anyhow::Error;
+
+	/// Case, spaces, '-' and '_' are ignored so "D-Pad Right", "dpad_right" and "DPadRight" are the same button
+	fn from_str(s: &str) -> Result<Self> {
+		let normalized: String = s.chars().filter(|c| !matches!(c, ' ' | '-' | '_')).collect::<String>().to_ascii_lowercase();
+		Ok(match normalized.as_str() {
+			"a" | "south" | "cross" => GamepadButton::South,
+			"b" | "east" | "circle" => GamepadButton::East,
+			"x" | "west" | "square" => GamepadButton::West,
+			"y" | "north" | "triangle" => GamepadButton::North,
+			"dpadup" | "up" => GamepadButton::DPadUp,
+			"dpaddown" | "down" => GamepadButton::DPadDown,
+			"dpadleft" | "left" => GamepadButton::DPadLeft,
+			"dpadright" | "right" => GamepadButton::DPadRight,
+			"select" | "back" | "minus" | "share" => GamepadButton::Select,
+			"start" | "plus" | "options" => GamepadButton::Start,
+			"guide" | "home" | "mode" => GamepadButton::Guide,
+			"lb" | "l1" | "leftshoulder" => GamepadButton::LeftShoulder,
+			"rb" | "r1" | "rightshoulder" => GamepadButton::RightShoulder,
+			"lt" | "l2" | "lefttrigger" => GamepadButton::LeftTrigger,
+			"rt" | "r2" | "righttrigger" => GamepadButton::RightTrigger,
+			"ls" | "l3" | "leftthumb" => GamepadButton::LeftThumb,
+			"rs" | "r3" | "rightthumb" => GamepadButton::RightThumb,
+			_ => return Err(anyhow!("Unknown controller button \"{}\", use A, B, X, Y, DPadUp/Down/Left/Right, Select, Start, Guide, LB, RB, LT, RT, LS or RS", s)),
+		})
+	}
+}
+
+impl fmt::Display for GamepadButton {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+/// Set of held buttons
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Buttons(u32);
+
+impl Buttons {
+	pub fn contains(&self, button: GamepadButton) -> bool {
+		self.0 & (1 << button as u32) != 0
+	}
+
+	pub fn insert(&mut self, button: GamepadButton) {
+		self.0 |= 1 << button as u32;
+	}
+
+	/// Build from (button, held) pairs, convenient for backends reading one flag per button
+	pub fn from_states(states: impl IntoIterator<Item = (GamepadButton, bool)>) -> Self {
+		let mut buttons = Buttons::default();
+		for (button, held) in states {
+			if held {
+				buttons.insert(button);
+			}
+		}
+		buttons
+	}
+
+	/// Held on either, for backends merging several controllers
+	pub fn union(self, other: Buttons) -> Buttons {
+		Buttons(self.0 | other.0)
+	}
+}
+
+/// What a Gamepad::poll() found
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadPoll {
+	/// Nothing changed since the last poll
+	Unchanged,
+	Buttons(Buttons),
+	/// A controller was picked up(again), with its name and held buttons
+	Connected(String, Buttons),
+}
+
+/// A controller polled for its held buttons
+pub trait Gamepad: Send {
+	/// Err when the controller is disconnected or can't be read, callers keep polling to pick it up(or another one) again
+	fn poll(&mut self) -> Result<GamepadPoll>;
+}
+
+/// Open controller number index with the platform backend(XInput on Windows, evdev on Linux),
+/// without an index the first connected controller is used and any other one is picked up when it disconnects
+pub fn open_gamepad(index: Option<u32>) -> Result<Box<dyn Gamepad>> {
+	#[cfg(target_os = "windows")]
+	return Ok(Box::new(super::xinput::XInputGamepad::open(index)?));
+	#[cfg(target_os = "linux")]
+	return Ok(Box::new(super::evdev_gamepad::EvdevGamepad::open(index)?));
+	#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+	return Err(anyhow!("Controller input is not supported on this platform (controller {:?})", index));
+}
+
+/// Split an "action=combo" binding, ex. "translate=DPadRight+Select" or "retranslate=Start hold=1s"
+pub fn split_combo_binding(binding: &str) -> Result<(Action, &str)> {
+	let Some((action, combo)) = binding.split_once('=') else {
+		return Err(anyhow!("Controller binding \"{}\" should look like \"translate=DPadRight+Select\"", binding));
+	};
+	let action = Action::from_name(action.trim()).ok_or_else(|| anyhow!("Unknown action \"{}\" in controller binding \"{}\"", action.trim(), binding))?;
+	Ok((action, combo))
+}
+
+/// Parse a controller combo, see combo::parse_combo() for the options. Controller combos fire on release unless "press" is given
+pub fn parse_gamepad_combo(combo: &str) -> Result<ComboSpec<GamepadButton>> {
+	parse_combo(combo, FireOn::Release, |step| step.split('+').map(GamepadButton::from_str).collect())
+}
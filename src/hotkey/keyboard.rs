@@ -0,0 +1,296 @@
+use livesplit_hotkey::{Hook, Hotkey, KeyCode, Modifiers};
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use super::combo::{parse_combo, ComboMachine, ComboSpec, FireOn, Pattern};
+use super::ActionSender;
+
+/// Everything a hotkey(keyboard or controller) can trigger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+	/// Capture the screen region, OCR and translate it
+	Translate,
+	/// Translate the last OCR result again without capturing
+	Retranslate,
+	ToggleOverlay,
+	PinOverlay,
+	ToggleSource,
+	/// Forget the last capture and clear the displayed translation
+	ClearContext,
+	/// Switch to the next configured screen region
+	CycleRegion,
+	/// Show the previous translation of the history on the overlay
+	HistoryBack,
+	/// Show the next translation of the history, up to the latest one
+	HistoryForward,
+}
+
+impl Action {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Action::Translate => "translate",
+			Action::Retranslate => "retranslate",
+			Action::ToggleOverlay => "toggle overlay",
+			Action::PinOverlay => "pin overlay",
+			Action::ToggleSource => "toggle source",
+			Action::ClearContext => "clear context",
+			Action::CycleRegion => "cycle region",
+			Action::HistoryBack => "history back",
+			Action::HistoryForward => "history forward",
+		}
+	}
+
+	/// Inverse of name(), '-' and '_' can be used instead of spaces
+	pub fn from_name(name: &str) -> Option<Action> {
+		let name = name.to_ascii_lowercase().replace(['-', '_'], " ");
+		[
+			Action::Translate,
+			Action::Retranslate,
+			Action::ToggleOverlay,
+			Action::PinOverlay,
+			Action::ToggleSource,
+			Action::ClearContext,
+			Action::CycleRegion,
+			Action::HistoryBack,
+			Action::HistoryForward,
+		].into_iter().find(|action| action.name() == name)
+	}
+}
+
+/// Parse a hotkey spec like "F3", "Ctrl+Shift+T" or "Alt + Numpad1", modifiers and key names are case insensitive
+pub fn parse_hotkey(spec: &str) -> Result<Hotkey> {
+	let parts: Vec<&str> = spec.split('+').map(str::trim).collect();
+	let Some((key, modifier_names)) = parts.split_last() else {
+		return Err(anyhow!("Empty hotkey"));
+	};
+	if key.is_empty() {
+		return Err(anyhow!("Hotkey \"{}\" has no key, expected something like \"Ctrl+Shift+T\"", spec));
+	}
+	let mut modifiers = Modifiers::empty();
+	for modifier_name in modifier_names {
+		let modifier = match modifier_name.to_ascii_lowercase().as_str() {
+			"ctrl" | "control" => Modifiers::CONTROL,
+			"shift" => Modifiers::SHIFT,
+			"alt" | "option" => Modifiers::ALT,
+			"meta" | "win" | "super" | "cmd" => Modifiers::META,
+			_ => return Err(anyhow!("Unknown modifier \"{}\" in hotkey \"{}\", use Ctrl, Shift, Alt or Meta", modifier_name, spec)),
+		};
+		if modifiers.contains(modifier) {
+			return Err(anyhow!("Modifier \"{}\" repeated in hotkey \"{}\"", modifier_name, spec));
+		}
+		modifiers.insert(modifier);
+	}
+	let key_code = parse_key_code(key).ok_or_else(|| anyhow!(
+		"Unsupported key \"{}\" in hotkey \"{}\", use a letter, a digit, F1-F24 or a key name like Space, Enter, Escape, Tab, Insert, Home, PageUp, ArrowLeft, Numpad1",
+		key, spec
+	))?;
+	Ok(Hotkey { key_code, modifiers })
+}
+
+/// Key names are the W3C code names livesplit-hotkey understands, plus single lowercase characters and a few common aliases
+fn parse_key_code(key: &str) -> Option<KeyCode> {
+	let mut chars = key.chars();
+	if let (Some(c), None) = (chars.next(), chars.next()) {
+		return KeyCode::from_str(&c.to_ascii_uppercase().to_string()).ok();
+	}
+	let alias = match key.to_ascii_lowercase().as_str() {
+		"esc" => "Escape",
+		"return" => "Enter",
+		"del" => "Delete",
+		"ins" => "Insert",
+		"pgup" => "PageUp",
+		"pgdn" | "pgdown" => "PageDown",
+		"left" => "ArrowLeft",
+		"right" => "ArrowRight",
+		"up" => "ArrowUp",
+		"down" => "ArrowDown",
+		"printscreen" | "prtsc" => "PrintScreen",
+		_ => key,
+	};
+	if let Ok(key_code) = KeyCode::from_str(alias) {
+		return Some(key_code);
+	}
+	if !alias.is_ascii() {
+		return None;
+	}
+	// "f3", "space", "numpad1"...
+	let mut capitalized = alias.to_ascii_lowercase();
+	capitalized[..1].make_ascii_uppercase();
+	KeyCode::from_str(&capitalized).ok()
+}
+
+/// Parse every binding, see combo::parse_combo() for double tap and sequence options, ex. "F3 double" or "Ctrl+K, T".
+/// The same combo bound to two different actions is an error instead of silently picking one
+pub fn parse_bindings<'a>(bindings: impl IntoIterator<Item = (Action, &'a str)>) -> Result<Vec<(Action, ComboSpec<Hotkey>)>> {
+	let mut parsed: Vec<(Action, ComboSpec<Hotkey>)> = Vec::new();
+	for (action, spec) in bindings {
+		let combo = parse_combo(spec, FireOn::Press, |step| Ok(vec![parse_hotkey(step)?])).map_err(|e| anyhow!("{} hotkey: {}", action.name(), e))?;
+		// Hooks only report presses, there is no way to tell how long a key is held or when it bounces
+		if matches!(combo.pattern, Pattern::Hold(_)) || !combo.debounce.is_zero() {
+			return Err(anyhow!("{} hotkey \"{}\": hold and debounce are only supported for controller combos", action.name(), spec));
+		}
+		if let Some((bound_action, _)) = parsed.iter().find(|(_, bound_combo)| *bound_combo == combo) {
+			if *bound_action == action {
+				continue;
+			}
+			return Err(anyhow!("Hotkey \"{}\" is bound to both {} and {}", spec, bound_action.name(), action.name()));
+		}
+		parsed.push((action, combo));
+	}
+	Ok(parsed)
+}
+
+type KeyBindings = Vec<(Action, ComboSpec<Hotkey>)>;
+
+/// Owns the keyboard hook on its own thread, so the bindings can be replaced from any thread while running
+pub struct KeyboardBindings {
+	update_channel: Sender<(KeyBindings, Sender<Result<()>>)>,
+}
+
+impl KeyboardBindings {
+	/// Fails if the hook can't be created or a hotkey can't be registered
+	pub fn spawn(bindings: KeyBindings, action_channel: ActionSender) -> Result<Self> {
+		let (update_tx, update_rx) = std::sync::mpsc::channel::<(KeyBindings, Sender<Result<()>>)>();
+		std::thread::spawn(move || {
+			let hook = Hook::new().map_err(|e| format!("Keyboard hotkey init failed: {:?}", e));
+			let mut registered: KeyBindings = Vec::new();
+			while let Ok((bindings, reply_channel)) = update_rx.recv() {
+				let result = match &hook {
+					Ok(hook) => {
+						unregister_bindings(hook, &registered);
+						match register_bindings(hook, &bindings, &action_channel) {
+							Ok(()) => {
+								registered = bindings;
+								Ok(())
+							},
+							Err(e) => {
+								unregister_bindings(hook, &bindings);
+								let _ = register_bindings(hook, &registered, &action_channel);
+								Err(e)
+							},
+						}
+					},
+					Err(e) => Err(anyhow!("{}", e)),
+				};
+				let _ = reply_channel.send(result);
+			}
+		});
+		let keyboard_bindings = Self { update_channel: update_tx };
+		keyboard_bindings.replace(bindings)?;
+		Ok(keyboard_bindings)
+	}
+
+	/// Replace every binding, the previous ones stay active if one of the new hotkeys can't be registered
+	pub fn replace(&self, bindings: KeyBindings) -> Result<()> {
+		let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+		self.update_channel.send((bindings, reply_tx)).map_err(|_| anyhow!("Keyboard hotkey thread stopped"))?;
+		reply_rx.recv().map_err(|_| anyhow!("Keyboard hotkey thread stopped"))?
+	}
+}
+
+fn distinct_hotkeys(bindings: &[(Action, ComboSpec<Hotkey>)]) -> Vec<Hotkey> {
+	let mut hotkeys: Vec<Hotkey> = Vec::new();
+	for (_, combo) in bindings {
+		for hotkey in &combo.inputs {
+			if !hotkeys.contains(hotkey) {
+				hotkeys.push(*hotkey);
+			}
+		}
+	}
+	hotkeys
+}
+
+/// Errors are ignored, some of the hotkeys may not have been registered
+fn unregister_bindings(hook: &Hook, bindings: &[(Action, ComboSpec<Hotkey>)]) {
+	for hotkey in distinct_hotkeys(bindings) {
+		let _ = hook.unregister(hotkey);
+	}
+}
+
+/// Register the keys of every binding on hook, a key shared by several combos is registered once and feeds all of them.
+/// Presses are fed to the combo machines as a press immediately followed by a release
+fn register_bindings(hook: &Hook, bindings: &[(Action, ComboSpec<Hotkey>)], action_channel: &ActionSender) -> Result<()> {
+	let machines: Vec<(Action, Arc<Mutex<ComboMachine<Hotkey>>>)> = bindings.iter()
+		.map(|(action, combo)| (*action, Arc::new(Mutex::new(ComboMachine::new(combo.clone())))))
+		.collect();
+	for hotkey in distinct_hotkeys(bindings) {
+		let listeners: Vec<_> = machines.iter()
+			.filter(|(_, machine)| machine.lock().unwrap().spec().inputs.contains(&hotkey))
+			.cloned()
+			.collect();
+		let action_channel = action_channel.clone();
+		hook.register(hotkey, move || {
+			let now = Instant::now();
+			for (action, machine) in &listeners {
+				let mut machine = machine.lock().unwrap();
+				let fired_on_press = machine.update(|input| *input == hotkey, now);
+				let fired_on_release = machine.update(|_| false, now);
+				if fired_on_press || fired_on_release {
+					let _ = action_channel.send(*action);
+				}
+			}
+		}).map_err(|e| anyhow!("Registering hotkey \"{}\" failed: {:?}, it may be taken by another application", hotkey, e))?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hotkey(key_code: KeyCode, modifiers: Modifiers) -> Hotkey {
+		Hotkey { key_code, modifiers }
+	}
+
+	#[test]
+	fn parses_modifiers_and_keys() {
+		assert_eq!(parse_hotkey("Ctrl+Shift+T").unwrap(), hotkey(KeyCode::KeyT, Modifiers::CONTROL | Modifiers::SHIFT));
+		assert_eq!(parse_hotkey("alt + numpad1").unwrap(), hotkey(KeyCode::Numpad1, Modifiers::ALT));
+		assert_eq!(parse_hotkey("f3").unwrap(), hotkey(KeyCode::F3, Modifiers::empty()));
+		assert_eq!(parse_hotkey("Win+7").unwrap(), hotkey(KeyCode::Digit7, Modifiers::META));
+		assert_eq!(parse_hotkey("PageUp").unwrap(), hotkey(KeyCode::PageUp, Modifiers::empty()));
+	}
+
+	#[test]
+	fn key_name_aliases() {
+		for (alias, key_code) in [
+			("Esc", KeyCode::Escape),
+			("return", KeyCode::Enter),
+			("Del", KeyCode::Delete),
+			("ins", KeyCode::Insert),
+			("PgDn", KeyCode::PageDown),
+			("left", KeyCode::ArrowLeft),
+			("Up", KeyCode::ArrowUp),
+			("PrtSc", KeyCode::PrintScreen),
+			("space", KeyCode::Space),
+		] {
+			assert_eq!(parse_hotkey(alias).unwrap().key_code, key_code, "{}", alias);
+		}
+	}
+
+	#[test]
+	fn rejects_unknown_and_unsupported_keys() {
+		let error = |spec| parse_hotkey(spec).unwrap_err().to_string();
+		assert!(error("Hyper+T").starts_with("Unknown modifier \"Hyper\""));
+		assert!(error("Ctrl+ctrl+T").starts_with("Modifier \"ctrl\" repeated"));
+		assert!(error("Ctrl+").contains("has no key"));
+		assert!(error("Ctrl+Banana").starts_with("Unsupported key \"Banana\""));
+		assert!(error("Ctrl+é").starts_with("Unsupported key \"é\""));
+	}
+
+	#[test]
+	fn parses_bindings() {
+		let bindings = parse_bindings([(Action::Translate, "F3"), (Action::Translate, "f3"), (Action::ToggleOverlay, "Ctrl+K, T")]).unwrap();
+		// The same binding twice for one action is kept once
+		assert_eq!(bindings.len(), 2);
+		assert!(matches!(bindings[1].1.pattern, Pattern::Sequence(_)));
+		let duplicate = parse_bindings([(Action::Translate, "F3"), (Action::CycleRegion, "F3")]).unwrap_err().to_string();
+		assert_eq!(duplicate, "Hotkey \"F3\" is bound to both translate and cycle region");
+		let hold = parse_bindings([(Action::Translate, "F3 hold")]).unwrap_err().to_string();
+		assert!(hold.contains("only supported for controller combos"), "{}", hold);
+		let unknown = parse_bindings([(Action::PinOverlay, "Ctrl+Banana")]).unwrap_err().to_string();
+		assert!(unknown.starts_with("pin overlay hotkey: Unsupported key"), "{}", unknown);
+	}
+}
@@ -0,0 +1,235 @@
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use crate::display::{DisplayEvent, DisplaySender};
+
+mod combo;
+pub use combo::{ComboMachine, ComboSpec};
+mod keyboard;
+pub use keyboard::{parse_bindings, Action, KeyboardBindings};
+mod gamepad;
+pub use gamepad::{open_gamepad, parse_gamepad_combo, split_combo_binding, Buttons, Gamepad, GamepadButton, GamepadPoll};
+#[cfg(target_os = "windows")]
+mod xinput;
+#[cfg(target_os = "linux")]
+mod evdev_gamepad;
+
+/// Where the keyboard and controller listeners send the actions, sending never blocks their threads
+pub type ActionSender = tokio::sync::mpsc::UnboundedSender<Action>;
+
+/// Combos of a controller_combo_listener(), in the order they are checked
+pub type ControllerCombos = Vec<(Action, ComboSpec<GamepadButton>)>;
+
+/// Poll fast while buttons are held or the controller was used recently, a combo needs a few samples to be seen
+const ACTIVE_POLL_INTERVAL: Duration = Duration::from_millis(4);
+/// Idle polling slows down to this, still short enough to catch a quick tap
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(32);
+const IDLE_AFTER: Duration = Duration::from_secs(2);
+/// Looking for a controller is expensive(XInput on empty slots, evdev enumeration), back off while none is connected
+const RECONNECT_INTERVAL_MIN: Duration = Duration::from_millis(333);
+const RECONNECT_INTERVAL_MAX: Duration = Duration::from_secs(3);
+const POLL_STATS_PERIOD: Duration = Duration::from_secs(60);
+
+/// Counts polls and the time spent in them, printed every POLL_STATS_PERIOD
+struct PollStats {
+	polls: u32,
+	busy: Duration,
+	since: Instant,
+}
+
+impl PollStats {
+	fn new() -> Self {
+		Self { polls: 0, busy: Duration::ZERO, since: Instant::now() }
+	}
+
+	fn record(&mut self, poll_time: Duration) {
+		self.polls += 1;
+		self.busy += poll_time;
+		let elapsed = self.since.elapsed();
+		if elapsed >= POLL_STATS_PERIOD {
+			println!(
+				"Controller polling: {} polls in {:.0}s ({:.1}/s), {:.1}ms spent polling",
+				self.polls, elapsed.as_secs_f64(), self.polls as f64 / elapsed.as_secs_f64(), self.busy.as_secs_f64() * 1000.0
+			);
+			*self = Self::new();
+		}
+	}
+}
+
+/// WARNING: Blocking function, run it in its own thread
+///
+/// Polls gamepad and sends the Action of every combo that fires on action_channel, combos received on combo_updates replace the current ones.
+/// Connections, and disconnections once a controller was connected, are shown as notices on display_channel.
+/// The poll interval adapts: fast while in use, slower when idle, backing off further while disconnected
+pub fn controller_combo_listener(
+	gamepad: Box<dyn Gamepad>,
+	combos: ControllerCombos,
+	combo_updates: Receiver<ControllerCombos>,
+	action_channel: ActionSender,
+	display_channel: DisplaySender,
+	print_poll_stats: bool,
+) {
+	let mut poller = ControllerPoller::new(gamepad, combos, action_channel, display_channel, print_poll_stats);
+	loop {
+		if let Some(combos) = combo_updates.try_iter().last() {
+			poller.set_combos(combos);
+		}
+		match poller.poll(Instant::now()) {
+			Some(interval) => std::thread::sleep(interval),
+			None => return,
+		}
+	}
+}
+
+fn combo_machines(combos: ControllerCombos) -> Vec<(Action, ComboMachine<GamepadButton>)> {
+	combos.into_iter().map(|(action, combo)| (action, ComboMachine::new(combo))).collect()
+}
+
+/// One step of controller_combo_listener() at a time, the loop only sleeps
+struct ControllerPoller {
+	gamepad: Box<dyn Gamepad>,
+	machines: Vec<(Action, ComboMachine<GamepadButton>)>,
+	action_channel: ActionSender,
+	display_channel: DisplaySender,
+	stats: Option<PollStats>,
+	buttons: Buttons,
+	last_change: Instant,
+	poll_interval: Duration,
+	reconnect_interval: Duration,
+	err_print: bool,
+	/// Until a controller shows up the connection errors only go to the terminal, most users play with the keyboard
+	controller_seen: bool,
+}
+
+impl ControllerPoller {
+	fn new(gamepad: Box<dyn Gamepad>, combos: ControllerCombos, action_channel: ActionSender, display_channel: DisplaySender, print_poll_stats: bool) -> Self {
+		Self {
+			gamepad,
+			machines: combo_machines(combos),
+			action_channel,
+			display_channel,
+			stats: print_poll_stats.then(PollStats::new),
+			buttons: Buttons::default(),
+			last_change: Instant::now(),
+			poll_interval: ACTIVE_POLL_INTERVAL,
+			reconnect_interval: RECONNECT_INTERVAL_MIN,
+			err_print: false,
+			controller_seen: false,
+		}
+	}
+
+	fn set_combos(&mut self, combos: ControllerCombos) {
+		self.machines = combo_machines(combos);
+	}
+
+	/// Polls once and returns how long to wait before the next poll, None once the actions can't be sent anymore
+	fn poll(&mut self, now: Instant) -> Option<Duration> {
+		let poll_start = Instant::now();
+		let poll_result = self.gamepad.poll();
+		if let Some(stats) = &mut self.stats {
+			stats.record(poll_start.elapsed());
+		}
+		match poll_result {
+			Ok(GamepadPoll::Unchanged) => (),
+			Ok(GamepadPoll::Buttons(buttons)) => {
+				if buttons != self.buttons {
+					self.last_change = now;
+				}
+				self.buttons = buttons;
+			}
+			Ok(GamepadPoll::Connected(name, buttons)) => {
+				let _ = self.display_channel.send(DisplayEvent::Notice(format!("Controller connected: {}", name)));
+				self.controller_seen = true;
+				self.last_change = now;
+				self.buttons = buttons;
+			}
+			Err(e) => {
+				if !self.err_print {
+					let notice = format!("{}, retrying...", e);
+					if self.controller_seen {
+						let _ = self.display_channel.send(DisplayEvent::Notice(notice));
+					} else {
+						println!("{}", notice);
+					}
+					self.err_print = true;
+				}
+				self.machines.iter_mut().for_each(|(_, machine)| machine.reset());
+				self.buttons = Buttons::default();
+				let interval = self.reconnect_interval;
+				self.reconnect_interval = (interval * 2).min(RECONNECT_INTERVAL_MAX);
+				return Some(interval);
+			}
+		}
+		self.err_print = false;
+		self.reconnect_interval = RECONNECT_INTERVAL_MIN;
+
+		let buttons = self.buttons;
+		for (action, machine) in &mut self.machines {
+			if machine.update(|button| buttons.contains(*button), now) && self.action_channel.send(*action).is_err() {
+				return None;
+			}
+		}
+		self.poll_interval = if buttons != Buttons::default() || now.saturating_duration_since(self.last_change) < IDLE_AFTER {
+			ACTIVE_POLL_INTERVAL
+		} else {
+			(self.poll_interval * 2).min(IDLE_POLL_INTERVAL)
+		};
+		Some(self.poll_interval)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use anyhow::{anyhow, Result};
+	use std::collections::VecDeque;
+
+	/// Replays its results, one per poll
+	struct ScriptedGamepad(VecDeque<Result<GamepadPoll>>);
+
+	impl Gamepad for ScriptedGamepad {
+		fn poll(&mut self) -> Result<GamepadPoll> {
+			self.0.pop_front().expect("polled past the script")
+		}
+	}
+
+	fn disconnected() -> Result<GamepadPoll> {
+		Err(anyhow!("Controller disconnected"))
+	}
+
+	#[test]
+	fn backs_off_while_disconnected_and_idles_when_unused() {
+		let mut held = Buttons::default();
+		held.insert(GamepadButton::South);
+		let unchanged = || Ok(GamepadPoll::Unchanged);
+		let script = [
+			disconnected(), disconnected(), disconnected(), disconnected(), disconnected(), disconnected(),
+			Ok(GamepadPoll::Connected("Pad".to_string(), Buttons::default())),
+			Ok(GamepadPoll::Buttons(held)),
+			Ok(GamepadPoll::Buttons(Buttons::default())),
+			unchanged(), unchanged(), unchanged(), unchanged(), unchanged(), unchanged(),
+			disconnected(),
+		];
+		let (action_tx, _action_rx) = tokio::sync::mpsc::unbounded_channel();
+		let (display_tx, mut display_rx) = tokio::sync::mpsc::unbounded_channel();
+		let mut poller = ControllerPoller::new(Box::new(ScriptedGamepad(script.into_iter().collect())), Vec::new(), action_tx, display_tx, false);
+		let start = Instant::now();
+		let mut poll = |at_ms: u64| poller.poll(start + Duration::from_millis(at_ms)).unwrap().as_millis();
+
+		assert_eq!([0, 1, 2, 3, 4, 5].map(&mut poll), [333, 666, 1332, 2664, 3000, 3000]);
+		// Reconnected: fast polling until IDLE_AFTER without a change
+		assert_eq!([6, 7, 8].map(&mut poll), [4, 4, 4]);
+		assert_eq!(poll(1000), 4);
+		assert_eq!([2008, 2009, 2010, 2011, 2012].map(&mut poll), [8, 16, 32, 32, 32]);
+		assert_eq!(poll(2013), 333);
+
+		let notices: Vec<String> = std::iter::from_fn(|| display_rx.try_recv().ok())
+			.map(|event| match event {
+				DisplayEvent::Notice(notice) => notice,
+				event => panic!("unexpected {:?}", event),
+			})
+			.collect();
+		// The errors before the first connection are only printed
+		assert_eq!(notices, ["Controller connected: Pad", "Controller disconnected, retrying..."]);
+	}
+}
@@ -0,0 +1,124 @@
+use rusty_xinput as xi;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use super::gamepad::{Buttons, Gamepad, GamepadButton, GamepadPoll};
+use super::{RECONNECT_INTERVAL_MAX, RECONNECT_INTERVAL_MIN};
+
+const XINPUT_SLOTS: u32 = 4;
+
+/// A connected slot
+struct Pad {
+	slot: u32,
+	/// XInput bumps it on every state change, an unchanged number means there is nothing to decode
+	packet_number: u32,
+	buttons: Buttons,
+}
+
+/// Without a fixed slot every connected controller is read and their buttons merged, so any of them can fire the combos
+pub struct XInputGamepad {
+	handle: xi::XInputHandle,
+	/// Fixed slot, any slot if None
+	slot: Option<u32>,
+	pads: Vec<Pad>,
+	/// Merged buttons of the last poll
+	buttons: Buttons,
+	/// Empty slots are looked at again at this time while a controller is connected, backing off like the caller does while none is
+	next_scan: Instant,
+	scan_interval: Duration,
+}
+
+impl XInputGamepad {
+	pub fn open(slot: Option<u32>) -> Result<Self> {
+		if slot.is_some_and(|slot| slot >= XINPUT_SLOTS) {
+			return Err(anyhow!("XInput only supports controller index 0-{}, got {:?}", XINPUT_SLOTS - 1, slot));
+		}
+		let handle = xi::XInputHandle::load_default().map_err(|e| anyhow!("Init XInput error: {:?}", e))?;
+		Ok(Self {
+			handle,
+			slot,
+			pads: Vec::new(),
+			buttons: Buttons::default(),
+			next_scan: Instant::now(),
+			scan_interval: RECONNECT_INTERVAL_MIN,
+		})
+	}
+
+	/// Slots without a controller, the ones poll() looks at when scanning
+	fn empty_slots(&self) -> Vec<u32> {
+		let slots = match self.slot {
+			Some(slot) => slot..slot + 1,
+			None => 0..XINPUT_SLOTS,
+		};
+		slots.filter(|slot| !self.pads.iter().any(|pad| pad.slot == *slot)).collect()
+	}
+}
+
+fn decode(state: &xi::XInputState) -> Buttons {
+	Buttons::from_states([
+		(GamepadButton::South, state.south_button()),
+		(GamepadButton::East, state.east_button()),
+		(GamepadButton::West, state.west_button()),
+		(GamepadButton::North, state.north_button()),
+		(GamepadButton::DPadUp, state.arrow_up()),
+		(GamepadButton::DPadDown, state.arrow_down()),
+		(GamepadButton::DPadLeft, state.arrow_left()),
+		(GamepadButton::DPadRight, state.arrow_right()),
+		(GamepadButton::Select, state.select_button()),
+		(GamepadButton::Start, state.start_button()),
+		(GamepadButton::Guide, state.guide_button()),
+		(GamepadButton::LeftShoulder, state.left_shoulder()),
+		(GamepadButton::RightShoulder, state.right_shoulder()),
+		(GamepadButton::LeftTrigger, state.left_trigger_bool()),
+		(GamepadButton::RightTrigger, state.right_trigger_bool()),
+		(GamepadButton::LeftThumb, state.left_thumb_button()),
+		(GamepadButton::RightThumb, state.right_thumb_button()),
+	])
+}
+
+impl Gamepad for XInputGamepad {
+	fn poll(&mut self) -> Result<GamepadPoll> {
+		let mut disconnected = None;
+		let handle = &self.handle;
+		self.pads.retain_mut(|pad| match handle.get_state_ex(pad.slot) {
+			Ok(state) => {
+				if state.raw.dwPacketNumber != pad.packet_number {
+					pad.packet_number = state.raw.dwPacketNumber;
+					pad.buttons = decode(&state);
+				}
+				true
+			},
+			Err(e) => {
+				disconnected = Some(anyhow!("Controller {} disconnected: {:?}", pad.slot, e));
+				false
+			},
+		});
+		// Querying empty slots is slow. With no controller the caller backs off while this fails, with one it is done here
+		let now = Instant::now();
+		let mut connected = None;
+		if self.pads.is_empty() || now >= self.next_scan {
+			for slot in self.empty_slots() {
+				if let Ok(state) = self.handle.get_state_ex(slot) {
+					self.pads.push(Pad { slot, packet_number: state.raw.dwPacketNumber, buttons: decode(&state) });
+					connected = Some(slot);
+				}
+			}
+			self.scan_interval = if connected.is_some() { RECONNECT_INTERVAL_MIN } else { (self.scan_interval * 2).min(RECONNECT_INTERVAL_MAX) };
+			self.next_scan = now + self.scan_interval;
+		}
+		if self.pads.is_empty() {
+			self.buttons = Buttons::default();
+			return Err(disconnected.unwrap_or_else(|| match self.slot {
+				Some(slot) => anyhow!("Controller {} not connected", slot),
+				None => anyhow!("No XInput controller connected"),
+			}));
+		}
+		let buttons = self.pads.iter().fold(Buttons::default(), |buttons, pad| buttons.union(pad.buttons));
+		let changed = buttons != self.buttons;
+		self.buttons = buttons;
+		Ok(match connected {
+			Some(slot) => GamepadPoll::Connected(format!("XInput controller {}", slot), buttons),
+			None if changed => GamepadPoll::Buttons(buttons),
+			None => GamepadPoll::Unchanged,
+		})
+	}
+}
@@ -0,0 +1,205 @@
+// Source language detection from the script of the text, and the stop words of the common Latin script languages.
+// Good enough to pick an OCR language pack and prompt, text too short or too mixed to tell is left undetected
+
+/// src_lang value asking for detection
+pub const AUTO: &str = "auto";
+
+/// Fewer letters than this are not worth a guess
+const MIN_LETTERS: usize = 2;
+/// Han only text shorter than this could as well be Japanese written in kanji(names, menu items...)
+const MIN_CHINESE_LETTERS: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub struct Language {
+	/// Used in prompts, as the --src-lang and --target-lang values are
+	pub name: &'static str,
+	/// ISO 639-1
+	pub code: &'static str,
+	/// Tesseract language pack
+	pub ocr_language: &'static str,
+	script: Script,
+	/// Frequent short words telling Latin script languages apart
+	stop_words: &'static [&'static str],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Script {
+	Han,
+	Kana,
+	Hangul,
+	Latin,
+	Cyrillic,
+	Greek,
+	Hebrew,
+	Arabic,
+	Thai,
+}
+
+/// In the Script order, counts are indexed by it
+const SCRIPTS: [Script; 9] = [Script::Han, Script::Kana, Script::Hangul, Script::Latin, Script::Cyrillic, Script::Greek, Script::Hebrew, Script::Arabic, Script::Thai];
+
+pub const LANGUAGES: &[Language] = &[
+	Language { name: "Japanese", code: "ja", ocr_language: "jpn", script: Script::Kana, stop_words: &[] },
+	Language { name: "Chinese", code: "zh", ocr_language: "chi_sim", script: Script::Han, stop_words: &[] },
+	Language { name: "Korean", code: "ko", ocr_language: "kor", script: Script::Hangul, stop_words: &[] },
+	Language { name: "Russian", code: "ru", ocr_language: "rus", script: Script::Cyrillic, stop_words: &[] },
+	Language { name: "Greek", code: "el", ocr_language: "ell", script: Script::Greek, stop_words: &[] },
+	Language { name: "Hebrew", code: "he", ocr_language: "heb", script: Script::Hebrew, stop_words: &[] },
+	Language { name: "Arabic", code: "ar", ocr_language: "ara", script: Script::Arabic, stop_words: &[] },
+	Language { name: "Thai", code: "th", ocr_language: "tha", script: Script::Thai, stop_words: &[] },
+	// Detected by its tone marks rather than stop words
+	Language { name: "Vietnamese", code: "vi", ocr_language: "vie", script: Script::Latin, stop_words: &[] },
+	Language {
+		name: "English", code: "en", ocr_language: "eng", script: Script::Latin,
+		stop_words: &["the", "and", "is", "are", "you", "to", "of", "it", "that", "this", "what", "with", "have", "was", "for", "not", "my", "your", "be", "do"],
+	},
+	Language {
+		name: "French", code: "fr", ocr_language: "fra", script: Script::Latin,
+		stop_words: &["le", "les", "et", "est", "vous", "je", "une", "des", "pas", "qui", "ce", "dans", "pour", "il", "elle", "sur", "mais", "du", "au", "tu"],
+	},
+	Language {
+		name: "German", code: "de", ocr_language: "deu", script: Script::Latin,
+		stop_words: &["der", "die", "das", "und", "ist", "nicht", "ich", "du", "sie", "es", "ein", "eine", "zu", "mit", "den", "auf", "wir", "sind", "was", "auch"],
+	},
+	Language {
+		name: "Spanish", code: "es", ocr_language: "spa", script: Script::Latin,
+		stop_words: &["el", "los", "las", "y", "es", "que", "no", "una", "en", "por", "con", "para", "lo", "se", "está", "pero", "muy", "yo", "del", "al"],
+	},
+	Language {
+		name: "Italian", code: "it", ocr_language: "ita", script: Script::Latin,
+		stop_words: &["il", "che", "di", "non", "per", "sono", "è", "con", "mi", "ti", "gli", "ma", "della", "questo", "io", "sei", "anche", "cosa", "nel", "ho"],
+	},
+	Language {
+		name: "Portuguese", code: "pt", ocr_language: "por", script: Script::Latin,
+		stop_words: &["o", "os", "que", "não", "um", "uma", "é", "em", "para", "com", "você", "eu", "se", "do", "da", "mas", "isso", "ao", "está", "muito"],
+	},
+	Language {
+		name: "Indonesian", code: "id", ocr_language: "ind", script: Script::Latin,
+		stop_words: &["yang", "dan", "ini", "itu", "tidak", "aku", "kamu", "saya", "apa", "dengan", "untuk", "ada", "di", "ke", "akan", "sudah", "bisa", "juga", "dari", "kita"],
+	},
+];
+
+pub fn is_auto(src_lang: &str) -> bool {
+	src_lang.trim().eq_ignore_ascii_case(AUTO)
+}
+
+/// By name, ISO 639-1 code or OCR language pack, case insensitive
+pub fn find(language: &str) -> Option<&'static Language> {
+	let language = language.trim();
+	LANGUAGES.iter().find(|known| [known.name, known.code, known.ocr_language].iter().any(|name| name.eq_ignore_ascii_case(language)))
+}
+
+/// The language most of the letters are written in, None when it can't be told
+pub fn detect(text: &str) -> Option<&'static Language> {
+	detect_after(text, None)
+}
+
+/// detect() for text following some in the previous language, Han only text keeps a previous Japanese or Chinese
+pub fn detect_after(text: &str, previous: Option<&'static Language>) -> Option<&'static Language> {
+	let mut counts = [0usize; SCRIPTS.len()];
+	for c in text.chars() {
+		if let Some(script) = script(c) {
+			counts[script as usize] += 1;
+		}
+	}
+	let count = |script: Script| counts[script as usize];
+	let letters: usize = counts.iter().sum();
+	if letters < MIN_LETTERS {
+		return None;
+	}
+	// Japanese mixes kanji and kana, Chinese has no kana but short Japanese lines may not either
+	let cjk = count(Script::Han) + count(Script::Kana);
+	let script = if cjk * 2 > letters {
+		if count(Script::Kana) > 0 {
+			Script::Kana
+		} else if let Some(previous) = previous.filter(|previous| matches!(previous.script, Script::Kana | Script::Han)) {
+			return Some(previous);
+		} else if count(Script::Han) < MIN_CHINESE_LETTERS {
+			return None;
+		} else {
+			Script::Han
+		}
+	} else {
+		*SCRIPTS.iter().find(|script| count(**script) * 2 > letters)?
+	};
+	if script == Script::Latin {
+		return detect_latin(text);
+	}
+	LANGUAGES.iter().find(|language| language.script == script)
+}
+
+fn detect_latin(text: &str) -> Option<&'static Language> {
+	// Letters with a dot below or hook above(U+1EA0-U+1EF9), plus ơ, ư and đ, are only used by Vietnamese
+	let vietnamese_letters = text.chars().filter(|c| matches!(*c as u32, 0x1EA0..=0x1EF9) || "ơưđƠƯĐ".contains(*c)).count();
+	if vietnamese_letters >= MIN_LETTERS {
+		return find("vi");
+	}
+	let words: Vec<String> = text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect();
+	let mut scores: Vec<(usize, &'static Language)> = LANGUAGES.iter()
+		.filter(|language| !language.stop_words.is_empty())
+		.map(|language| (words.iter().filter(|word| language.stop_words.contains(&word.as_str())).count(), language))
+		.collect();
+	scores.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+	match scores.as_slice() {
+		[(best, language), (second, _), ..] if *best > 0 && best > second => Some(language),
+		_ => None,
+	}
+}
+
+fn script(c: char) -> Option<Script> {
+	Some(match c as u32 {
+		// Hiragana, katakana, phonetic extensions and half-width katakana
+		0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Kana,
+		0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => Script::Han,
+		0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+		0x0400..=0x04FF => Script::Cyrillic,
+		0x0370..=0x03FF => Script::Greek,
+		0x0590..=0x05FF => Script::Hebrew,
+		0x0600..=0x06FF | 0x0750..=0x077F => Script::Arabic,
+		0x0E00..=0x0E7F => Script::Thai,
+		0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF if c.is_alphabetic() => Script::Latin,
+		_ => return None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn detects_languages() {
+		let detected = |text| detect(text).map(|language| language.name);
+		assert_eq!(detected("今日はいい天気ですね。"), Some("Japanese"));
+		assert_eq!(detected("你好，我们现在就去城堡吧！"), Some("Chinese"));
+		assert_eq!(detected("안녕하세요, 반갑습니다!"), Some("Korean"));
+		assert_eq!(detected("Привет, как дела?"), Some("Russian"));
+		assert_eq!(detected("Where is the sword? I have to find it."), Some("English"));
+		assert_eq!(detected("Je ne sais pas où est la clé, mais elle est dans le château."), Some("French"));
+		assert_eq!(detected("Tôi không biết đường đến lâu đài."), Some("Vietnamese"));
+		// No stop words to go by
+		assert_eq!(detected("Excalibur"), None);
+		assert_eq!(detected("HP 120/120"), None);
+		assert_eq!(detected("…！？"), None);
+	}
+
+	#[test]
+	fn kanji_only_text_keeps_the_previous_language() {
+		let detected = |text, previous| detect_after(text, find(previous)).map(|language| language.name);
+		assert_eq!(detected("東京都", ""), None);
+		assert_eq!(detected("東京都", "ja"), Some("Japanese"));
+		assert_eq!(detected("東京都", "zh"), Some("Chinese"));
+		assert_eq!(detected("東京都", "en"), None);
+		assert_eq!(detected("魔王城最上階大広間", "ja"), Some("Japanese"));
+		assert_eq!(detected("你好，我们现在就去城堡吧！", "ko"), Some("Chinese"));
+		// Kana tells it is Japanese whatever came before
+		assert_eq!(detected("ありがとう", "zh"), Some("Japanese"));
+	}
+
+	#[test]
+	fn finds_by_name_or_code() {
+		assert_eq!(find("japanese").map(|language| language.ocr_language), Some("jpn"));
+		assert_eq!(find("ko").map(|language| language.name), Some("Korean"));
+		assert_eq!(find("chi_sim").map(|language| language.code), Some("zh"));
+		assert_eq!(find("Klingon"), None);
+	}
+}
@@ -0,0 +1,21 @@
+//! Screen OCR and translation. Build a Pipeline from Settings, trigger captures or translate text with it and receive
+//! the results through DisplaySinks. The ocrtrans command line tool is built on it
+pub mod config;
+pub mod display;
+pub mod foreground;
+pub mod history;
+pub mod hotkey;
+pub mod language;
+pub mod ocr;
+pub mod overlay;
+pub mod pipeline;
+pub mod retry;
+pub mod server;
+pub mod session;
+pub mod translator;
+
+pub use config::Settings;
+pub use display::{DisplayEvent, DisplaySink, TranslationDone};
+pub use ocr::{CaptureSource, OcrEngine, OcrError, OcrResult, OcrSource, TextBlock};
+pub use pipeline::{Pipeline, PipelineBuilder};
+pub use translator::{TranslateError, TranslateRequest, Translator};
@@ -1,111 +1,317 @@
-mod hotkey;
-mod translator;
-use translator::TranslateRequest;
-mod overlay;
-use overlay::{create_window, UpdateHandle, WindowChannelMessage};
-mod ocr;
-use ocr::screenshot_and_ocr;
-use std::{str::FromStr, time::Duration};
+mod repl;
+use repl::{Command as ReplCommand, RegionSelection};
+use ocrtrans::{config, foreground, hotkey, ocr, server, session};
+use ocrtrans::overlay::{create_inplace_window, create_window, UpdateHandle, WindowChannelMessage};
+use ocrtrans::ocr::OcrSource;
+use ocrtrans::history::{History, HistoryEntry, HistorySink};
+use ocrtrans::config::{ConfigFile, ConfigSource, ControllerLayer, Layer, NewCapturePolicy, NotificationsLayer, OcrLayer, OverlayLayer, ServerLayer, Settings, TranslationLayer};
+use ocrtrans::display::{ClipboardSink, DisplayEvent, DisplaySender, JsonlSink, NotificationLevel, NotificationSink, Origin, OverlaySink, Stage, TerminalSink, TranscriptSink};
+use ocrtrans::server::SessionChange;
+use ocrtrans::session::SessionRecorder;
+use ocrtrans::translator::TranslatorConfig;
+use ocrtrans::Pipeline;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use std::io::Write;
-use std::env;
-use tokio::task::spawn_blocking;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
 pub use openssl;
 
 use dotenvy::dotenv;
-use livesplit_hotkey::{Hook, Hotkey, Modifiers, KeyCode};
-use notify_rust::Notification;
-use clap::Parser;
-use anyhow::Result;
-use openai::{set_base_url, set_key};
-
-const EMPTY_STRING_SIGNAL: &str = "		  			  			   ";
+use hotkey::{Action, ControllerCombos, KeyboardBindings};
+use clap::{Parser, Subcommand};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use anyhow::{anyhow, Context, Result};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-	/// percent of screen coordinates in horizontal then vertical order, ex. (0, 0.166, 0.75, 0.967) gives you bottom left region
+	#[command(subcommand)]
+	command: Option<Command>,
+
+	/// TOML config file, ocrtrans.toml in the working directory is used if it exists. Flags override it
+	#[arg(long, env = "OCRTRANS_CONFIG")]
+	config: Option<PathBuf>,
+
+	/// Config file profile to apply over its global settings, ex. a game name
+	#[arg(long, env = "OCRTRANS_PROFILE")]
+	profile: Option<String>,
+
+	/// percent of screen coordinates in horizontal then vertical order, ex. (0, 0.166, 0.75, 0.967) gives you bottom left region.
+	/// Can be repeated, switch between them with --cycle-region-shortcut
 	#[arg(short, long)]
-	screen_region: String,
+	screen_region: Vec<String>,
+
+	/// OpenAI API endpoint, need /v1/chat/completions suffix [default: https://api.openai.com/v1]
+	#[arg(long)]
+	translation_api_endpoint: Option<String>,
 
-	/// OpenAI API endpoint, need /v1/chat/completions suffix
-	//#[arg(long, default_value = "http://172.22.22.172:8788/v1/chat/completions")]
-	#[arg(long, default_value = "https://api.openai.com/v1")]
-	translation_api_endpoint: String,
+	/// Chat model used for translation [default: gpt-4o]
+	#[arg(long)]
+	translation_model: Option<String>,
 
-	/// OpenAI API endpoint, need /v1/chat/completions suffix
-	#[arg(long, default_value = "http://172.22.22.172:5000/extract_text")]
-	ocr_api_endpoint: String,
+	/// System prompt preset from the config file [default: default]
+	#[arg(long)]
+	prompt: Option<String>,
 
-	/// OpenAI API key, optional if using non-official services
+	/// OpenAI API endpoint, need /v1/chat/completions suffix [default: http://172.22.22.172:5000/extract_text]
 	#[arg(long)]
+	ocr_api_endpoint: Option<String>,
+
+	/// OpenAI API key, optional if using non-official services
+	#[arg(long, env = "OPENAI_KEY", hide_env_values = true)]
 	api_key: Option<String>,
 
-	/// Source translate language
-	#[arg(long, default_value = "Japanese")]
-	src_lang: String,
+	/// Source translate language, "auto" detects it from the text [default: auto]
+	#[arg(long)]
+	src_lang: Option<String>,
+
+	/// Target translate language [default: English]
+	#[arg(long)]
+	target_lang: Option<String>,
+
+	/// Hotkey to trigger a screen translation, ex. F3, Ctrl+Shift+T, "F3 double" or "Ctrl+K, T", can be repeated [default: F3]
+	#[arg(long)]
+	keyboard_shortcut: Vec<String>,
+
+	/// Hotkey to translate the last capture again, can be repeated
+	#[arg(long)]
+	retranslate_shortcut: Vec<String>,
+
+	/// output rate will be limit to 1000/word_per_sec ms per word if output too fast [default: 10]
+	#[arg(long)]
+	word_per_sec: Option<u32>,
+
+	/// Translate each OCR line/block separately and draw it over the original text, needs an OCR server returning "blocks"
+	#[arg(long)]
+	in_place: bool,
+
+	/// Fade the overlay out this many seconds after its last update, keep it forever if not set
+	#[arg(long)]
+	overlay_auto_hide: Option<f64>,
+
+	/// Overlay fade-in/out duration in ms, 0 to show/hide instantly [default: 200]
+	#[arg(long)]
+	overlay_fade_ms: Option<u64>,
+
+	/// Translate every capture in order instead of cancelling the current translation when a new capture comes in
+	#[arg(long)]
+	queue_translations: bool,
+
+	/// Hide the overlay once the captured screen region changes, e.g. the game advanced to the next line
+	#[arg(long)]
+	hide_on_region_change: bool,
+
+	/// Hotkey to show/hide the overlay, can be repeated
+	#[arg(long)]
+	toggle_overlay_shortcut: Vec<String>,
+
+	/// Hotkey to pin the overlay, a pinned overlay is never auto hidden, can be repeated
+	#[arg(long)]
+	pin_overlay_shortcut: Vec<String>,
+
+	/// Show the OCR text above the translation in the overlay
+	#[arg(long)]
+	show_source: bool,
+
+	/// Hotkey to show/hide the OCR text line in the overlay, can be repeated
+	#[arg(long)]
+	toggle_source_shortcut: Vec<String>,
+
+	/// Hotkey to forget the last capture and clear the overlay, can be repeated
+	#[arg(long)]
+	clear_context_shortcut: Vec<String>,
+
+	/// Hotkey to switch to the next --screen-region, can be repeated
+	#[arg(long)]
+	cycle_region_shortcut: Vec<String>,
+
+	/// Hotkey to show the previous translation on the overlay, can be repeated
+	#[arg(long)]
+	history_back_shortcut: Vec<String>,
+
+	/// Hotkey to show the next translation on the overlay, up to the latest one, can be repeated
+	#[arg(long)]
+	history_forward_shortcut: Vec<String>,
+
+	/// Controller combo per action, ex. "translate=DPadRight+Select", "retranslate=Start hold=1s" or "clear-context=Up, Up, Down press", can be repeated.
+	/// Replaces the configured combos of the actions it names [default: translate=DPadRight+Select]
+	#[arg(long)]
+	controller_combo: Vec<String>,
+
+	/// Which controller to listen to, 0 is the first one. If not set the first connected controller is used and others are picked up when it disconnects
+	#[arg(long)]
+	controller_index: Option<u32>,
+
+	/// Print controller polling rate and time spent polling every minute
+	#[arg(long)]
+	controller_poll_stats: bool,
+
+	/// Don't listen to controllers at all
+	#[arg(long)]
+	no_controller: bool,
+
+	/// No desktop notifications, not even for errors
+	#[arg(long)]
+	no_notifications: bool,
+
+	/// Start the control API on localhost, for stream decks and scripts
+	#[arg(long)]
+	server: bool,
+
+	/// Control API port, implies --server [default: 7878]
+	#[arg(long)]
+	server_port: Option<u16>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Config file tools
+	Config {
+		#[command(subcommand)]
+		command: ConfigCommand,
+	},
+	/// Recorded sessions, see outputs.sessions in the config
+	Session {
+		#[command(subcommand)]
+		command: SessionCommand,
+	},
+}
 
-	/// Target translate language
-	#[arg(long, default_value = "English")]
-	target_lang: String,
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+	/// Validate the config file, each of its profiles and the given flags, then exit
+	Check,
+}
 
-	/// Default key to trigger a screen translation
-	#[arg(long, default_value = "F3")]
-	keyboard_shortcut: String,
+#[derive(Subcommand, Debug)]
+enum SessionCommand {
+	/// List the recorded sessions, oldest first
+	List,
+	/// Export a session as subtitles(SRT, WebVTT), Markdown, HTML or CSV
+	Export {
+		/// Session name from the list or a JSONL file, the latest session by default
+		session: Option<String>,
+		/// srt, vtt, md, html or csv, taken from the output file extension by default
+		#[arg(short, long)]
+		format: Option<session::ExportFormat>,
+		/// Written to stdout by default
+		#[arg(short, long)]
+		output: Option<PathBuf>,
+		/// Seconds added to subtitle times to line them up with a recording, negative to move them earlier
+		#[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+		shift: f64,
+	},
+}
 
-	/// output rate will be limit to 1000/word_per_sec ms per word if output too fast
-	#[arg(long, default_value_t = 10)]
-	word_per_sec: u32
+impl Args {
+	/// The top config layer, only what was set on the command line or through env vars
+	fn config_layer(&self) -> Result<Layer> {
+		let mut layer = Layer {
+			screen_regions: (!self.screen_region.is_empty()).then(|| self.screen_region.clone()),
+			src_lang: self.src_lang.clone(),
+			target_lang: self.target_lang.clone(),
+			word_per_sec: self.word_per_sec,
+			in_place: self.in_place.then_some(true),
+			hide_on_region_change: self.hide_on_region_change.then_some(true),
+			translation: TranslationLayer {
+				endpoint: self.translation_api_endpoint.clone(),
+				model: self.translation_model.clone(),
+				api_key: self.api_key.clone(),
+				prompt: self.prompt.clone(),
+				on_new_capture: self.queue_translations.then_some(NewCapturePolicy::Queue),
+				..Default::default()
+			},
+			ocr: OcrLayer {
+				endpoint: self.ocr_api_endpoint.clone(),
+				..Default::default()
+			},
+			overlay: OverlayLayer {
+				auto_hide: self.overlay_auto_hide,
+				fade_ms: self.overlay_fade_ms,
+				show_source: self.show_source.then_some(true),
+				..Default::default()
+			},
+			controller: ControllerLayer {
+				enabled: self.no_controller.then_some(false),
+				index: self.controller_index,
+				poll_stats: self.controller_poll_stats.then_some(true),
+				..Default::default()
+			},
+			notifications: NotificationsLayer {
+				show: self.no_notifications.then_some(NotificationLevel::Off),
+				..Default::default()
+			},
+			server: ServerLayer {
+				enabled: (self.server || self.server_port.is_some()).then_some(true),
+				port: self.server_port,
+				..Default::default()
+			},
+			..Default::default()
+		};
+		for (action, shortcuts) in [
+			(Action::Translate, &self.keyboard_shortcut),
+			(Action::Retranslate, &self.retranslate_shortcut),
+			(Action::ToggleOverlay, &self.toggle_overlay_shortcut),
+			(Action::PinOverlay, &self.pin_overlay_shortcut),
+			(Action::ToggleSource, &self.toggle_source_shortcut),
+			(Action::ClearContext, &self.clear_context_shortcut),
+			(Action::CycleRegion, &self.cycle_region_shortcut),
+			(Action::HistoryBack, &self.history_back_shortcut),
+			(Action::HistoryForward, &self.history_forward_shortcut),
+		] {
+			if !shortcuts.is_empty() {
+				*layer.hotkeys.action_mut(action) = Some(shortcuts.clone());
+			}
+		}
+		for binding in &self.controller_combo {
+			let (action, combo) = hotkey::split_combo_binding(binding)?;
+			layer.controller.combos.action_mut(action).get_or_insert_with(Vec::new).push(combo.to_string());
+		}
+		Ok(layer)
+	}
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+	// A missing .env is fine, the key can come from the environment, the config file or --api-key
+	dotenv().ok();
 	let args = Args::parse();
-	let (screen_region,
-		translation_api_endpoint,
-		ocr_api_endpoint,
-		src_lang,
-		target_lang,
-		word_per_sec,
-		keyboard_shortcut) = (
-			args.screen_region,
-			args.translation_api_endpoint,
-			args.ocr_api_endpoint,
-			args.src_lang,
-			args.target_lang,
-			args.word_per_sec,
-			args.keyboard_shortcut
-		);
-	dotenv().unwrap();
-	set_key(env::var("OPENAI_KEY").unwrap());
-    set_base_url(translation_api_endpoint.clone());
-
-	let (ocr_channel_tx, ocr_channel_rx) = std::sync::mpsc::sync_channel(10);
-
-	let xinput_hotkey_thread = {
-		let screen_region = screen_region.clone();
-		let ocr_channel_tx = ocr_channel_tx.clone();
-		let ocr_api_endpoint = ocr_api_endpoint.clone();
-		std::thread::spawn(|| hotkey::controller_combo_listener(move || screenshot_and_ocr(&screen_region, ocr_channel_tx.clone(), &ocr_api_endpoint)))
-	};
-
-	
-	let Ok(key_code) = KeyCode::from_str(&keyboard_shortcut) else {
-		println!("Keyboard key \"{}\" not supported, view complete key list here: https://docs.rs/livesplit-hotkey/latest/src/livesplit_hotkey/key_code.rs.html#1788-2035", keyboard_shortcut);
-		return Ok(());
-	};
-	let hotkey = Hotkey { key_code , modifiers: Modifiers::empty() };
-	let Ok(hotkeyhook) = Hook::new() else {
-		println!("Keyboard hotkey init failed");
-		return Ok(());
+	let overrides = args.config_layer()?;
+	let config_file = ConfigFile::load(args.config.as_deref())?;
+	if let Some(Command::Config { command: ConfigCommand::Check }) = args.command {
+		return config_file.check(&overrides);
+	}
+	if let Some(Command::Session { command }) = args.command {
+		let layer = config_file.layer(args.profile.as_deref(), &overrides)?;
+		return session_command(command, layer.outputs.sessions_dir().as_deref());
+	}
+	let config_source = ConfigSource {
+		file: config_file,
+		profile: args.profile.clone(),
+		overrides,
 	};
-	{
-		if hotkeyhook.register(hotkey, move || screenshot_and_ocr(&screen_region, ocr_channel_tx.clone(), &ocr_api_endpoint)).is_err() {
-			eprintln!("Keyboard hotkey init failed");
+	let settings = match config_source.settings() {
+		Ok(settings) => settings,
+		Err(e) => {
+			eprintln!("{:#}", e);
 			return Ok(());
 		}
-	}
-	
+	};
+	// Regions, languages, prompt, glossary, OCR, overlay theme and hotkeys follow profile switches and config reloads, the rest is fixed at start
+	let Settings {
+		word_per_sec,
+		in_place,
+		hide_on_region_change,
+		overlay: overlay_behaviour,
+		key_bindings,
+		controller,
+		server: server_settings,
+		outputs,
+		notifications,
+		..
+	} = settings.clone();
+
 	let (result_display_tx, result_display_rx) = std::sync::mpsc::channel();
 	let (window_handle_tx, window_handle_rx) = std::sync::mpsc::channel();
 	std::thread::spawn(move || create_window(result_display_rx, window_handle_tx));
@@ -113,127 +319,490 @@ async fn main() -> Result<()> {
 		eprintln!("Window handle retriving error");
 		return Ok(());
 	};
-	let buffered_display_in_tx_tty;
-	let buffered_display_in_tx_clearer_2;
+	let inplace_window = if in_place {
+		let (inplace_display_tx, inplace_display_rx) = std::sync::mpsc::channel();
+		let (window_handle_tx, window_handle_rx) = std::sync::mpsc::channel();
+		std::thread::spawn(move || create_inplace_window(inplace_display_rx, window_handle_tx));
+		let Ok(inplace_refresh) = window_handle_rx.recv() else {
+			eprintln!("In-place window handle retriving error");
+			return Ok(());
+		};
+		Some((inplace_display_tx, inplace_refresh))
+	} else {
+		None
+	};
+	let overlays: Vec<_> = std::iter::once((result_display_tx.clone(), window_refresh)).chain(inplace_window.clone()).collect();
+	send_overlay_message(&overlays, WindowChannelMessage::Behaviour(overlay_behaviour));
+	let history = match &outputs.sessions {
+		Some(sessions_dir) => History::from_sessions(sessions_dir).unwrap_or_else(|e| {
+			eprintln!("Earlier translations not loaded: {:#}", e);
+			History::default()
+		}),
+		None => History::default(),
+	};
+	let mut pipeline_builder = Pipeline::builder(settings)
+		.sink(OverlaySink::new(result_display_tx, window_refresh, 255, Duration::from_millis((1000.0 / word_per_sec as f64) as u64)))
+		.sink(TerminalSink)
+		.sink(NotificationSink::new(notifications))
+		.sink(HistorySink::new(history.clone()));
+	if let Some(path) = &outputs.transcript {
+		match TranscriptSink::open(path) {
+			Ok(sink) => pipeline_builder = pipeline_builder.sink(sink),
+			Err(e) => eprintln!("Transcript output disabled: {:#}", e),
+		}
+	}
+	if let Some(path) = &outputs.jsonl {
+		match JsonlSink::open(path) {
+			Ok(sink) => pipeline_builder = pipeline_builder.sink(sink),
+			Err(e) => eprintln!("JSONL output disabled: {:#}", e),
+		}
+	}
+	if outputs.clipboard {
+		pipeline_builder = pipeline_builder.sink(ClipboardSink);
+	}
+	if let Some(sessions_dir) = &outputs.sessions {
+		pipeline_builder = pipeline_builder.sink(SessionRecorder::new(sessions_dir));
+	}
+	if let Some((inplace_display_tx, inplace_refresh)) = inplace_window.clone() {
+		pipeline_builder = pipeline_builder.inplace_window(inplace_display_tx, inplace_refresh);
+	}
+	let region_change_arm_rx = if hide_on_region_change {
+		let (arm_tx, arm_rx) = std::sync::mpsc::channel();
+		pipeline_builder = pipeline_builder.region_change_arm(arm_tx);
+		Some(arm_rx)
+	} else {
+		None
+	};
+	let pipeline = pipeline_builder.build();
+	let settings = pipeline.settings().clone();
+	let display_tx = pipeline.display_channel().clone();
+	let (action_tx, mut action_rx) = tokio::sync::mpsc::unbounded_channel();
+
+	let controller_combo_tx = if controller.enabled {
+		match hotkey::open_gamepad(controller.index) {
+			Ok(gamepad) => {
+				let (combo_update_tx, combo_update_rx) = std::sync::mpsc::channel();
+				let action_tx = action_tx.clone();
+				let controller_combos = controller.combos.clone();
+				let display_tx = display_tx.clone();
+				let print_poll_stats = controller.poll_stats;
+				std::thread::spawn(move || hotkey::controller_combo_listener(gamepad, controller_combos, combo_update_rx, action_tx, display_tx, print_poll_stats));
+				Some(combo_update_tx)
+			},
+			Err(e) => {
+				eprintln!("Controller hotkey init failed, continuing without it: {}", e);
+				None
+			},
+		}
+	} else {
+		None
+	};
+
+	let keyboard_bindings = match KeyboardBindings::spawn(key_bindings.clone(), action_tx.clone()) {
+		Ok(keyboard_bindings) => Arc::new(keyboard_bindings),
+		Err(e) => {
+			eprintln!("{}", e);
+			return Ok(());
+		}
+	};
+	let live_settings = LiveSettings {
+		settings: settings.clone(),
+		overlays: overlays.clone(),
+		keyboard_bindings,
+		controller_combo_tx,
+		display_tx: display_tx.clone(),
+	};
+	let config_source = Arc::new(Mutex::new(config_source));
+	if let Some(arm_rx) = region_change_arm_rx {
+		let overlays = overlays.clone();
+		let settings = settings.clone();
+		std::thread::spawn(move || ocr::region_change_watcher(|| settings.read().unwrap().screen_regions.current(), arm_rx, Duration::from_millis(500), || send_overlay_message(&overlays, WindowChannelMessage::Hide)));
+	}
 	{
-		let src_lang = src_lang.clone();
-		let target_lang = target_lang.clone();
-		let (buffered_display_in_tx, buffered_display_in_rx) = std::sync::mpsc::channel();
-		let (buffered_display_out_tx, buffered_display_out_rx) = std::sync::mpsc::channel();
-		let buffered_display_in_tx_clearer_1 = buffered_display_in_tx.clone();
-		buffered_display_in_tx_clearer_2 = buffered_display_in_tx.clone();
-		buffered_display_in_tx_tty = buffered_display_in_tx.clone();
-		let _ = new_display_buffer(buffered_display_in_rx, buffered_display_out_tx, 255, Duration::from_millis((1000.0 / word_per_sec as f64) as u64));
-		spawn_blocking(move || {
-			async_window_text_update(window_refresh, result_display_tx, buffered_display_out_rx)
+		let pipeline = pipeline.clone();
+		let inplace_window = inplace_window.clone();
+		let overlays = overlays.clone();
+		let settings = settings.clone();
+		let history = history.clone();
+		let display_tx = display_tx.clone();
+		tokio::spawn(async move { // run hotkey actions(by action_rx)
+			while let Some(action) = action_rx.recv().await {
+				match action {
+					Action::Translate => pipeline.trigger_screen(),
+					Action::Retranslate => {
+						if !pipeline.retranslate().await {
+							let _ = display_tx.send(DisplayEvent::Notice("Nothing to retranslate yet".to_string()));
+						}
+					},
+					Action::ToggleOverlay => send_overlay_message(&overlays, WindowChannelMessage::ToggleVisibility),
+					Action::PinOverlay => send_overlay_message(&overlays, WindowChannelMessage::TogglePin),
+					Action::ToggleSource => send_overlay_message(&overlays, WindowChannelMessage::ToggleSource),
+					Action::ClearContext => {
+						pipeline.clear_context();
+						if let Some(inplace_window) = &inplace_window {
+							send_overlay_message(std::slice::from_ref(inplace_window), WindowChannelMessage::Clear);
+						}
+					},
+					Action::CycleRegion => {
+						let (index, region) = settings.read().unwrap().screen_regions.cycle();
+						let _ = display_tx.send(DisplayEvent::Notice(format!("Screen region {}: {}", index + 1, region)));
+					},
+					Action::HistoryBack => show_history_entry(&history, &display_tx, -1),
+					Action::HistoryForward => show_history_entry(&history, &display_tx, 1),
+				}
+			}
+		});
+	}
+	if let Some(server_settings) = &server_settings {
+		let on_session_change = {
+			let config_source = config_source.clone();
+			let live_settings = live_settings.clone();
+			Arc::new(move |change: SessionChange| {
+				let notice = [("src_lang", &change.src_lang), ("target_lang", &change.target_lang), ("model", &change.model)].iter()
+					.filter_map(|(name, value)| value.as_ref().map(|value| format!("{}: {}", name, value)))
+					.collect::<Vec<_>>().join(", ");
+				update_overrides(&config_source, &live_settings, &format!("API {}", notice), |overrides| {
+					set_some(&mut overrides.src_lang, change.src_lang);
+					set_some(&mut overrides.target_lang, change.target_lang);
+					set_some(&mut overrides.translation.model, change.model);
+				})
+			})
+		};
+		match server::start(server_settings, pipeline.clone(), on_session_change).await {
+			Ok((address, token)) => println!("Control API listening on http://{}, token {}", address, token),
+			Err(e) => eprintln!("Control API not started, listening on port {} failed: {}", server_settings.port, e),
+		}
+	}
+	if let Some(port) = outputs.captions_port {
+		match server::start_captions(port, pipeline.clone()).await {
+			Ok(address) => println!("Caption page on http://{}", address),
+			Err(e) => eprintln!("Caption page not started, listening on port {} failed: {}", port, e),
+		}
+	}
+	if args.profile.is_none() && config_source.lock().unwrap().file.has_window_matches() {
+		let config_source = config_source.clone();
+		let live_settings = live_settings.clone();
+		std::thread::spawn(move || { // switch profiles with the focused window
+			let result = foreground::foreground_watcher(Duration::from_secs(1), |window| {
+				let mut config_source = config_source.lock().unwrap();
+				// Windows matching no profile keep the current one, e.g. the terminal used to type a translation
+				let Some(profile) = config_source.file.match_profile(window).map(str::to_string) else {
+					return;
+				};
+				if config_source.profile.as_ref() == Some(&profile) {
+					return;
+				}
+				config_source.profile = Some(profile.clone());
+				live_settings.apply(config_source.settings(), &format!("Profile: {}", profile));
+			});
+			if let Err(e) = result {
+				eprintln!("Automatic profile switching disabled: {}", e);
+			}
 		});
-		std::thread::spawn(move || { // spawn the shortcut key listener(by ocr_channel_rx)
-			let rt: tokio::runtime::Runtime = tokio::runtime::Builder::new_multi_thread()
-				.enable_all()
-				.build()
-				.expect("Failed to create Tokio runtime");
-
-			rt.block_on(async {
-				while let Ok(ocr_text) = ocr_channel_rx.recv() {
-					let translation_request = TranslateRequest::new(&ocr_text, &src_lang, &target_lang);
-					let _ = buffered_display_in_tx_clearer_1.send(String::from(EMPTY_STRING_SIGNAL));
-					if let Ok(result) = translator::translate_openai(
-						&translation_request,
-						None,
-						Some(buffered_display_in_tx.clone())
-					).await {
-						println!("{} Output> \n{}", target_lang, result);
-						let _ = Notification::new()
-							.summary("Translation result")
-							.body(&result)
-							.show();
-					};
+	}
+	let config_file_state = {
+		let config_source = config_source.lock().unwrap();
+		config_source.file.path.clone().map(|path| (path, config_source.file.modified()))
+	};
+	if let Some((config_path, last_modified)) = config_file_state {
+		let config_source = config_source.clone();
+		let live_settings = live_settings.clone();
+		std::thread::spawn(move || { // reload the config file when it changes
+			config::config_file_watcher(&config_path, last_modified, Duration::from_secs(1), |config_file| {
+				let mut config_source = config_source.lock().unwrap();
+				match config_file {
+					Ok(config_file) => {
+						config_source.file = config_file;
+						live_settings.apply(config_source.settings(), "Config reloaded");
+					},
+					Err(e) => live_settings.apply(Err(e), "Config reloaded"),
 				}
 			});
 		});
 	}
 
-	std::thread::sleep(std::time::Duration::from_millis(100));
-	if xinput_hotkey_thread.is_finished() {
-		eprintln!("Controller hotkey init failed");
-		std::process::exit(0);
+	let keyboard_translate_combos: Vec<String> = key_bindings.iter()
+		.filter(|(action, _)| *action == Action::Translate)
+		.map(|(_, combo)| combo.to_string())
+		.collect();
+	let controller_translate_combos: Vec<String> = controller.combos.iter()
+		.filter(|(action, _)| *action == Action::Translate)
+		.map(|(_, combo)| combo.to_string())
+		.collect();
+	if !controller.enabled || controller_translate_combos.is_empty() {
+		println!("\nInit complete, press {} to trigger translation.\n", keyboard_translate_combos.join(" / "));
+	} else {
+		println!("\nInit complete, press {} or {} to trigger translation.\n", keyboard_translate_combos.join(" / "), controller_translate_combos.join(" / "));
 	}
-
-	println!("\nInit complete, press {} or D-Pad Right + Select(-) to trigger translation.\n", keyboard_shortcut);
+	println!("Type text to translate it or /help for the commands.\n");
+	let mut editor = match DefaultEditor::new() {
+		Ok(editor) => editor,
+		Err(e) => {
+			eprintln!("Terminal init failed: {}", e);
+			return Ok(());
+		}
+	};
+	let _ = editor.load_history(repl::HISTORY_FILE);
+	let mut last_input: Option<String> = None;
 	loop {
-		let mut user_message = String::new();
-		println!("{} Input> ", src_lang);
-		if std::io::stdin().read_line(&mut user_message).is_err() {
-			continue;
+		let src_lang = settings.read().unwrap().src_lang.clone();
+		let line = match editor.readline(&format!("{} Input> ", src_lang)) {
+			Ok(line) => line,
+			Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+			Err(e) => {
+				eprintln!("Terminal input failed: {}", e);
+				break;
+			}
 		};
-		let user_message = user_message.trim().to_string();
-
-		println!("Streaming {} output> \n", target_lang);
-		let (streaming_output_tx, streaming_output_rx) = tokio::sync::mpsc::channel(10);
-		let translation_request = TranslateRequest::new(&user_message, &src_lang, &target_lang);
-		let _ = buffered_display_in_tx_clearer_2.send(String::from(EMPTY_STRING_SIGNAL));
-		let (_, translate_result) = tokio::join!(async_display_print(streaming_output_rx, false), translator::translate_openai(
-			&translation_request,
-			Some(streaming_output_tx),
-			Some(buffered_display_in_tx_tty.clone()),
-		));
-		match translate_result {
-			Ok(_) => println!("\n\n/Streaming {} output done\n", target_lang),
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+		let _ = editor.add_history_entry(line);
+		let command = match repl::parse_command(line) {
+			Ok(command) => command,
 			Err(e) => {
-				eprintln!("\n\n/translate_openai failed: {}\n", e)
+				println!("{}", e);
+				continue;
+			}
+		};
+		match command {
+			ReplCommand::Translate(text) => {
+				translate_terminal_input(&pipeline, &text, None).await;
+				last_input = Some(text);
+			},
+			ReplCommand::Retry => match &last_input {
+				Some(text) => translate_terminal_input(&pipeline, text, None).await,
+				None => {
+					let _ = action_tx.send(Action::Retranslate);
+				},
+			},
+			ReplCommand::ContextClear => {
+				last_input = None;
+				let _ = action_tx.send(Action::ClearContext);
+			},
+			ReplCommand::Region(None) => {
+				let settings = settings.read().unwrap();
+				let selected = settings.screen_regions.selected();
+				for (index, region) in settings.screen_regions.regions().iter().enumerate() {
+					println!("{} {}: {}", if index == selected { "*" } else { " " }, index + 1, region);
+				}
+			},
+			ReplCommand::Region(Some(RegionSelection::Next)) => {
+				let _ = action_tx.send(Action::CycleRegion);
+			},
+			ReplCommand::Region(Some(RegionSelection::Index(index))) => match settings.read().unwrap().screen_regions.select(index) {
+				Ok(region) => println!("Screen region {}: {}", index + 1, region),
+				Err(e) => println!("{}", e),
 			},
+			ReplCommand::Model(None) => println!("Model: {}", settings.read().unwrap().translator.model),
+			ReplCommand::Glossary => {
+				let settings = settings.read().unwrap();
+				if settings.translator.glossary.is_empty() {
+					println!("The glossary is empty");
+				}
+				for (term, translation) in &settings.translator.glossary {
+					println!("{} = {}", term, translation);
+				}
+			},
+			ReplCommand::History(count) => {
+				let entries = history.recent(count);
+				for (index, entry) in entries.iter().enumerate() {
+					println!("{} [{} UTC, {}] {}\n  -> {}", entries.len() - index, history_time(entry), entry.origin.name(), entry.source.trim(), entry.translation.trim());
+				}
+			},
+			ReplCommand::Retranslate(back, model, prompt) => match history.back(back) {
+				Some(entry) => retranslate_entry(&pipeline, &config_source, &entry.source, model, prompt).await,
+				None => println!("No history entry {}", back + 1),
+			},
+			ReplCommand::Ocr(path) => pipeline.trigger(OcrSource::File(path)),
+			ReplCommand::Lang(src_lang, target_lang) => print_error(update_overrides(&config_source, &live_settings, &format!("Languages: {} -> {}", src_lang, target_lang), |overrides| {
+				overrides.src_lang = Some(src_lang);
+				overrides.target_lang = Some(target_lang);
+			})),
+			ReplCommand::Model(Some(model)) => print_error(update_overrides(&config_source, &live_settings, &format!("Model: {}", model), |overrides| {
+				overrides.translation.model = Some(model);
+			})),
+			ReplCommand::GlossaryAdd(term, translation) => print_error(update_overrides(&config_source, &live_settings, &format!("Glossary: {} = {}", term, translation), |overrides| {
+				overrides.glossary.insert(term, translation);
+			})),
+			ReplCommand::Help => println!("{}", repl::HELP),
+			ReplCommand::Quit => break,
 		}
 	}
+	let _ = editor.save_history(repl::HISTORY_FILE);
+	Ok(())
 }
 
-fn new_display_buffer(
-	input_channel: std::sync::mpsc::Receiver<String>,
-	output_channel: std::sync::mpsc::Sender<String>,
-	max_len: usize,
-	min_interval: Duration, // The minimum time interval between consecutive sends
-) -> std::thread::JoinHandle<()> {
-	std::thread::spawn(move || {
-		let mut buffer: String = String::new();
-		let mut last_send_time = Instant::now(); // Record the last send time
-
-		loop {
-			match input_channel.recv() {
-				Ok(input) => {
-					if input == EMPTY_STRING_SIGNAL {
-						buffer.clear();
-						let _ = output_channel.send("".to_string()); 
-						continue;
-					};
-					buffer.push_str(&input);
-					while buffer.chars().count() > max_len {
-						buffer.remove(0);
-					}
-
-					// Calculate the elapsed time since the last send
-					let elapsed_time = last_send_time.elapsed();
-
-					// Check if the elapsed time is less than the minimum interval
-					if elapsed_time < min_interval {
-						// Sleep for the remaining time
-						std::thread::sleep(min_interval - elapsed_time);
-					}
-
-					// Send the buffer to the output channel
-					if output_channel.send(buffer.clone()).is_err() {
-						eprintln!("output channel closed");
-						break;
-					}
-
-					// Update the last send time
-					last_send_time = Instant::now();
-				}
-				Err(_) => {
-					eprintln!("input channel closed");
-					break;
-				}
+fn session_command(command: SessionCommand, sessions_dir: Option<&Path>) -> Result<()> {
+	let Some(sessions_dir) = sessions_dir else {
+		return Err(anyhow!("Session recording is off, set outputs.sessions to the directory to record them to"));
+	};
+	match command {
+		SessionCommand::List => {
+			for path in session::list(sessions_dir)? {
+				let entries = session::load(&path)?;
+				println!("{}  {} translations", path.file_stem().unwrap_or_default().to_string_lossy(), entries.len());
 			}
+		},
+		SessionCommand::Export { session: name, format, output, shift } => {
+			let format = format.or_else(|| output.as_deref().and_then(session::ExportFormat::from_path))
+				.ok_or_else(|| anyhow!("Pass --format, it can't be taken from the output file name"))?;
+			let path = session::find(sessions_dir, name.as_deref())?;
+			let document = session::export(&session::load(&path)?, format, shift);
+			match output {
+				Some(output) => {
+					std::fs::write(&output, document).with_context(|| format!("Writing {} failed", output.display()))?;
+					println!("Exported {} to {}", path.display(), output.display());
+				},
+				None => print!("{}", document),
+			}
+		},
+	}
+	Ok(())
+}
+
+/// Step through the history on the overlay, the position is printed since a status line would replace the text
+fn show_history_entry(history: &History, display_tx: &DisplaySender, offset: isize) {
+	let Some((position, count, entry)) = history.step(offset) else {
+		let _ = display_tx.send(DisplayEvent::Notice("No translations yet".to_string()));
+		return;
+	};
+	println!("History {}/{}, {} UTC", position, count, history_time(&entry));
+	let _ = display_tx.send(DisplayEvent::Clear);
+	let _ = display_tx.send(DisplayEvent::Source(entry.source));
+	let _ = display_tx.send(DisplayEvent::Replace(entry.translation));
+}
+
+/// "12:34:56"
+fn history_time(entry: &HistoryEntry) -> String {
+	let time = entry.time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+	format!("{:02}:{:02}:{:02}", time / 3600 % 24, time / 60 % 60, time % 60)
+}
+
+/// Translate text again with the current settings, model and prompt preset replaced for this translation only
+async fn retranslate_entry(pipeline: &Pipeline, config_source: &Mutex<ConfigSource>, text: &str, model: Option<String>, prompt: Option<String>) {
+	let settings = {
+		let mut config_source = config_source.lock().unwrap().clone();
+		set_some(&mut config_source.overrides.translation.model, model);
+		set_some(&mut config_source.overrides.translation.prompt, prompt);
+		config_source.settings()
+	};
+	let translator_config = match settings {
+		Ok(settings) => settings.translator,
+		Err(e) => {
+			println!("{:#}", e);
+			return;
+		},
+	};
+	println!("Model: {}", translator_config.model);
+	translate_terminal_input(pipeline, text, Some(translator_config)).await;
+}
+
+/// Session changes typed in the terminal or sent to the control API go into the top layer so they survive profile switches and config reloads.
+/// Invalid changes are not applied
+fn update_overrides(config_source: &Mutex<ConfigSource>, live_settings: &LiveSettings, notice: &str, update: impl FnOnce(&mut Layer)) -> Result<()> {
+	let mut config_source = config_source.lock().unwrap();
+	let mut new_source = config_source.clone();
+	update(&mut new_source.overrides);
+	let new_settings = new_source.settings()?;
+	*config_source = new_source;
+	live_settings.apply(Ok(new_settings), notice);
+	Ok(())
+}
+
+fn set_some<T>(target: &mut Option<T>, value: Option<T>) {
+	if value.is_some() {
+		*target = value;
+	}
+}
+
+fn print_error(result: Result<()>) {
+	if let Err(e) = result {
+		println!("{:#}", e);
+	}
+}
+
+/// Translate a line typed in the terminal, streaming it to stdout. translator_config replaces the current translator settings
+async fn translate_terminal_input(pipeline: &Pipeline, text: &str, translator_config: Option<TranslatorConfig>) {
+	let (target_lang, translator_config) = {
+		let settings = pipeline.settings().read().unwrap();
+		(settings.target_lang.clone(), translator_config.unwrap_or_else(|| settings.translator.clone()))
+	};
+	println!("Streaming {} output> \n", target_lang);
+	let (streaming_output_tx, streaming_output_rx) = tokio::sync::mpsc::channel(10);
+	let (_, translate_result) = tokio::join!(
+		async_display_print(streaming_output_rx, false),
+		pipeline.translate_text_with(translator_config, text, Origin::Terminal, Some(streaming_output_tx)),
+	);
+	match translate_result {
+		Ok(_) => println!("\n\n/Streaming {} output done\n", target_lang),
+		// Already reported by the pipeline
+		Err(_) => println!(),
+	}
+}
+
+/// Everything a settings change while running(profile switch, config reload) has to reach
+#[derive(Clone)]
+struct LiveSettings {
+	settings: Arc<RwLock<Settings>>,
+	overlays: Vec<(std::sync::mpsc::Sender<WindowChannelMessage>, UpdateHandle)>,
+	keyboard_bindings: Arc<KeyboardBindings>,
+	/// None when the controller is disabled or failed to open
+	controller_combo_tx: Option<std::sync::mpsc::Sender<ControllerCombos>>,
+	display_tx: DisplaySender,
+}
+
+impl LiveSettings {
+	/// Swap in new settings and update only the subsystems whose part changed, announcing it with notice.
+	/// Invalid settings are reported and the current ones are kept
+	fn apply(&self, new_settings: Result<Settings>, notice: &str) {
+		let mut new_settings = match new_settings {
+			Ok(new_settings) => new_settings,
+			Err(e) => {
+				eprintln!("{}, keeping the current settings:\n{:#}", notice, e);
+				let _ = self.display_tx.send(DisplayEvent::Error(Stage::Settings, "invalid, see the terminal".to_string()));
+				return;
+			},
+		};
+		let mut settings = self.settings.write().unwrap();
+		// Keep the selected region if the list didn't change
+		if new_settings.screen_regions.regions() == settings.screen_regions.regions() {
+			new_settings.screen_regions = settings.screen_regions.clone();
 		}
-	})
+		if new_settings.key_bindings != settings.key_bindings {
+			if let Err(e) = self.keyboard_bindings.replace(new_settings.key_bindings.clone()) {
+				eprintln!("{}, keeping the current hotkeys", e);
+				let _ = self.display_tx.send(DisplayEvent::Error(Stage::Settings, "hotkeys not updated, see the terminal".to_string()));
+				new_settings.key_bindings = settings.key_bindings.clone();
+			}
+		}
+		if new_settings.controller.combos != settings.controller.combos {
+			if let Some(controller_combo_tx) = &self.controller_combo_tx {
+				let _ = controller_combo_tx.send(new_settings.controller.combos.clone());
+			}
+		}
+		if new_settings.overlay != settings.overlay {
+			send_overlay_message(&self.overlays, WindowChannelMessage::Behaviour(new_settings.overlay.clone()));
+		}
+		let restart_needed: Vec<&str> = [
+			("in_place", new_settings.in_place != settings.in_place),
+			("hide_on_region_change", new_settings.hide_on_region_change != settings.hide_on_region_change),
+			("word_per_sec", new_settings.word_per_sec != settings.word_per_sec),
+			("ocr.workers and ocr.queue_size", new_settings.ocr_pool != settings.ocr_pool),
+			("controller.enabled", new_settings.controller.enabled != settings.controller.enabled),
+			("controller.index", new_settings.controller.index != settings.controller.index),
+			("controller.poll_stats", new_settings.controller.poll_stats != settings.controller.poll_stats),
+			("server", new_settings.server != settings.server),
+			("outputs", new_settings.outputs != settings.outputs),
+			("notifications", new_settings.notifications != settings.notifications),
+		].into_iter().filter(|(_, changed)| *changed).map(|(name, _)| name).collect();
+		if !restart_needed.is_empty() {
+			println!("Restart to apply: {}", restart_needed.join(", "));
+		}
+		*settings = new_settings;
+		let _ = self.display_tx.send(DisplayEvent::Notice(notice.to_string()));
+	}
 }
 
 async fn async_display_print(mut content_channel: tokio::sync::mpsc::Receiver<String>, newline: bool) {
@@ -247,13 +816,9 @@ async fn async_display_print(mut content_channel: tokio::sync::mpsc::Receiver<St
 	}
 }
 
-/// WARNING: Blocking function until input_channel is closed
-fn async_window_text_update(window_refresh: UpdateHandle, window_content_output: std::sync::mpsc::Sender<WindowChannelMessage>, input_channel: std::sync::mpsc::Receiver<String>) {
-	while let Ok(content) = input_channel.recv() {
-		let _ = window_content_output.send(WindowChannelMessage {
-			text: content,
-			screen_dimension: None
-		});
+fn send_overlay_message(overlays: &[(std::sync::mpsc::Sender<WindowChannelMessage>, UpdateHandle)], message: WindowChannelMessage) {
+	for (message_channel, window_refresh) in overlays {
+		let _ = message_channel.send(message.clone());
 		let _ = window_refresh.update_window();
 	}
 }
@@ -1,20 +1,262 @@
-use image::{imageops::crop_imm, DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Pixel};
-use xcap::Monitor;
-use anyhow::{anyhow, Result};
-use reqwest::blocking::multipart;
+use image::{imageops::crop_imm, DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, RgbaImage};
+use xcap::{Monitor, XCapError};
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use reqwest::multipart;
+use reqwest::StatusCode;
+use thiserror::Error;
 use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::display::Stage;
+use crate::language::Language;
+use crate::retry::{self, RetryPolicy};
+
+const FINGERPRINT_SIZE: (u32, u32) = (32, 8);
+const REGION_CHANGE_THRESHOLD: f64 = 12.0; // Mean absolute luma difference of the fingerprints
+
+/// What the OCR server returned
+#[derive(Deserialize, Debug)]
+pub struct OcrResponse {
+    pub extracted_text: String,
+	/// Optional per line/block results, only returned by OCR servers that support bounding boxes
+	#[serde(default)]
+	pub blocks: Vec<ResponseBlock>,
+}
 
 #[derive(Deserialize, Debug)]
-struct Response {
-    extracted_text: String,
+pub struct ResponseBlock {
+	pub text: String,
+	/// [x, y, width, height] in pixels of the submitted image
+	#[serde(rename = "box")]
+	pub bbox: [f64; 4],
+}
+
+/// Why a capture or OCR request failed, the messages are short enough for the overlay
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OcrError {
+	#[error("No screen detected")]
+	NoScreen,
+	#[error("Invalid screen region: {0}")]
+	InvalidRegion(String),
+	#[error("Screen capture failed: {0}")]
+	Capture(XCapError),
+	#[error("Screen region is empty")]
+	EmptyImage,
+	#[error("Opening image {0} failed: {1}")]
+	OpenImage(PathBuf, image::ImageError),
+	#[error("Invalid image: {0}")]
+	DecodeImage(image::ImageError),
+	#[error("PNG encoding failed: {0}")]
+	Encode(image::ImageError),
+	#[error("OCR server unreachable: {0}")]
+	Unreachable(reqwest::Error),
+	#[error("OCR server returned {0}")]
+	ServerStatus(StatusCode, Option<Duration>),
+	#[error("OCR response read failed: {0}")]
+	ReadResponse(reqwest::Error),
+	#[error("Invalid OCR response: {0}")]
+	InvalidResponse(serde_json::Error),
+	#[error("OCR worker failed: {0}")]
+	Worker(tokio::task::JoinError),
+}
+
+impl OcrError {
+	pub fn stage(&self) -> Stage {
+		match self {
+			OcrError::NoScreen | OcrError::InvalidRegion(_) | OcrError::Capture(_) | OcrError::EmptyImage | OcrError::OpenImage(..) | OcrError::DecodeImage(_) => Stage::Capture,
+			_ => Stage::Ocr,
+		}
+	}
+}
+
+/// A recognized line/block placed in screen coordinates(same unit as the overlay window)
+#[derive(Debug, Clone)]
+pub struct TextBlock {
+	pub text: String,
+	pub rect: (i32, i32, u32, u32),
+	/// Average color around the block, used to paint over the original text
+	pub background: [u8; 3],
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct OcrResult {
+	pub text: String,
+	pub blocks: Vec<TextBlock>,
+	/// Screen region it was captured from, None for images
+	pub region: Option<String>,
+	/// Detected when src_lang is "auto", see OcrConfig::detect_language
+	pub language: Option<&'static Language>,
+}
+
+/// Configured capture regions, cloned handles share the selected one
+#[derive(Clone, Debug)]
+pub struct ScreenRegions {
+	regions: Arc<Vec<String>>,
+	selected: Arc<AtomicUsize>,
+}
+
+impl ScreenRegions {
+	pub fn new(regions: Vec<String>) -> Result<Self> {
+		if regions.is_empty() {
+			return Err(anyhow!("No screen region set"));
+		}
+		for region in &regions {
+			convert_screen_region((1, 1), region).with_context(|| format!("Invalid screen region \"{}\"", region))?;
+		}
+		Ok(Self {
+			regions: Arc::new(regions),
+			selected: Arc::new(AtomicUsize::new(0)),
+		})
+	}
+
+	pub fn regions(&self) -> &[String] {
+		&self.regions
+	}
+
+	/// index starts at 0
+	pub fn select(&self, index: usize) -> Result<String> {
+		let region = self.regions.get(index).ok_or_else(|| anyhow!("There are only {} screen regions", self.regions.len()))?;
+		self.selected.store(index, Ordering::Relaxed);
+		Ok(region.clone())
+	}
+
+	/// Index of the selected region
+	pub fn selected(&self) -> usize {
+		self.selected.load(Ordering::Relaxed) % self.regions.len()
+	}
+
+	pub fn current(&self) -> String {
+		self.regions[self.selected.load(Ordering::Relaxed) % self.regions.len()].clone()
+	}
+
+	/// Select the next region, returns its index and value
+	pub fn cycle(&self) -> (usize, String) {
+		let index = (self.selected.fetch_add(1, Ordering::Relaxed) + 1) % self.regions.len();
+		(index, self.regions[index].clone())
+	}
+}
+
+/// Image processing applied to the capture before it is sent to the OCR server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preprocessing {
+	/// Resize factor, small text needs a larger one
+	pub scale: f32,
+	pub contrast: f32,
+	/// Grayscale pixels at or below this become black, only bright text is kept
+	pub threshold: u8,
+}
+
+impl Default for Preprocessing {
+	fn default() -> Self {
+		Self {
+			scale: 1.0 / 3.0,
+			contrast: 25.0,
+			threshold: 220,
+		}
+	}
+}
+
+/// What to read the text from
+#[derive(Clone, Debug, PartialEq)]
+pub enum OcrSource {
+	/// Screen region, see ScreenRegions
+	Screen(String),
+	/// Image file, e.g. a screenshot taken earlier
+	File(PathBuf),
+	/// Encoded image(PNG, JPEG...), e.g. sent to the control API
+	Image(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolSize {
+	/// Captures read at the same time
+	pub workers: usize,
+	/// Triggers waiting for a free worker, more are dropped
+	pub queue: usize,
+}
+
+impl Default for PoolSize {
+	fn default() -> Self {
+		Self {
+			workers: 2,
+			queue: 4,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcrConfig {
+	pub endpoint: String,
+	/// Tried in order when endpoint keeps failing
+	pub fallback_endpoints: Vec<String>,
+	pub preprocessing: Preprocessing,
+	pub retry: RetryPolicy,
+	/// Tesseract language pack sent as the "lang" form field, None lets the server pick
+	pub language: Option<String>,
+	/// Pick language from the text read, see the language module. The pipeline reads again with the detected pack
+	/// when it differs and keeps it for the next captures
+	pub detect_language: bool,
+}
+
+/// Image to read and where it was taken from
+pub struct Capture {
+	pub image: RgbaImage,
+	/// None for image files, their blocks can't be placed on screen
+	pub placement: Option<Placement>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+	/// Top left of the image on screen, in physical pixels
+	pub origin: (u32, u32),
+	/// Physical to overlay pixels, the capture is in physical pixels but the overlay window is not DPI aware
+	pub screen_scale: f64,
+}
+
+/// Capture stage, where the images come from. Blocking, the pipeline calls it from the runtime's blocking pool
+pub trait CaptureSource: Send + Sync {
+	fn capture(&self, source: &OcrSource) -> Result<Capture, OcrError>;
+}
+
+/// Screen regions of the first screen, see capture_screen(), and images
+pub struct ScreenCapture;
+
+impl CaptureSource for ScreenCapture {
+	fn capture(&self, source: &OcrSource) -> Result<Capture, OcrError> {
+		match source {
+			OcrSource::Screen(screen_region) => capture_screen(screen_region),
+			OcrSource::File(path) => open_image(path),
+			OcrSource::Image(bytes) => decode_image(bytes),
+		}
+	}
+}
+
+/// OCR stage, reads the preprocessed PNG
+pub trait OcrEngine: Send + Sync {
+	fn recognize<'a>(&'a self, png: Vec<u8>, ocr_config: &'a OcrConfig) -> BoxFuture<'a, Result<OcrResponse, OcrError>>;
+}
+
+/// OCR server taking the image as multipart form data, see recognize()
+pub struct HttpOcr;
+
+impl OcrEngine for HttpOcr {
+	fn recognize<'a>(&'a self, png: Vec<u8>, ocr_config: &'a OcrConfig) -> BoxFuture<'a, Result<OcrResponse, OcrError>> {
+		Box::pin(recognize(png, ocr_config))
+	}
 }
 
-pub fn screenshot_and_ocr(screen_region: &str, output_channel: std::sync::mpsc::SyncSender<String>, ocr_api_endpoint: &str) {
+/// Capture stage for screen regions. Blocking
+pub fn capture_screen(screen_region: &str) -> Result<Capture, OcrError> {
 	let screen = {
 		let screens = Monitor::all().unwrap_or_default();
 		if screens.is_empty() {
-			println!("No screen detected");
-			return;
+			return Err(OcrError::NoScreen);
 		}
 		if screens.len() >= 2 {
 			println!("Multiple screens detected, only first screen will be used.");
@@ -24,36 +266,209 @@ pub fn screenshot_and_ocr(screen_region: &str, output_channel: std::sync::mpsc::
 	let screen = &screen[0];
 	// println!("Capturing screen info: {screen:?}");
 	let real_resoltion = (screen.width(), screen.height());
-	let Ok(ocr_screen_region) = convert_screen_region(real_resoltion, screen_region) else {
-		eprintln!("Error: Screen region parsing failed");
-		return;
-	};
-	let image = screen.capture_image().unwrap();
-	let image = crop_imm(&image, ocr_screen_region.0, ocr_screen_region.1, ocr_screen_region.2, ocr_screen_region.3).to_image();
+	let ocr_screen_region = convert_screen_region(real_resoltion, screen_region).map_err(|e| OcrError::InvalidRegion(format!("{:#}", e)))?;
+	let image = screen.capture_image().map_err(OcrError::Capture)?;
+	let cropped_image = crop_imm(&image, ocr_screen_region.0, ocr_screen_region.1, ocr_screen_region.2, ocr_screen_region.3).to_image();
+	if cropped_image.width() == 0 || cropped_image.height() == 0 {
+		return Err(OcrError::EmptyImage);
+	}
+	Ok(Capture {
+		image: cropped_image,
+		placement: Some(Placement {
+			origin: (ocr_screen_region.0, ocr_screen_region.1),
+			screen_scale: 1.0 / screen.scale_factor() as f64,
+		}),
+	})
+}
+
+/// Capture stage for image files, e.g. a screenshot taken earlier. Blocking
+pub fn open_image(path: &Path) -> Result<Capture, OcrError> {
+	let image = image::open(path).map_err(|e| OcrError::OpenImage(path.to_path_buf(), e))?;
+	Ok(Capture {
+		image: image.to_rgba8(),
+		placement: None,
+	})
+}
 
-	let image = DynamicImage::ImageRgba8(image);
+/// Capture stage for images in memory
+pub fn decode_image(bytes: &[u8]) -> Result<Capture, OcrError> {
+	let image = image::load_from_memory(bytes).map_err(OcrError::DecodeImage)?;
+	Ok(Capture {
+		image: image.to_rgba8(),
+		placement: None,
+	})
+}
+
+/// Preprocess stage, returns the PNG sent to the OCR server. CPU bound
+pub fn preprocess(image: &RgbaImage, preprocessing: &Preprocessing) -> Result<Vec<u8>, OcrError> {
+	let image = DynamicImage::ImageRgba8(image.clone());
 	let (original_width, original_height) = image.dimensions();
-	let scaling_factor = 1.0 / 3.0;
-    let new_width = (original_width as f32 * scaling_factor).round() as u32;
-    let new_height = (original_height as f32 * scaling_factor).round() as u32;
+	let scaling_factor = preprocessing.scale;
+    let new_width = ((original_width as f32 * scaling_factor).round() as u32).max(1);
+    let new_height = ((original_height as f32 * scaling_factor).round() as u32).max(1);
 	let image = image.resize_exact(new_width, new_height, image::imageops::FilterType::Nearest);
-	let image = image.adjust_contrast(25.0);
+	let image = image.adjust_contrast(preprocessing.contrast);
 	let image = image.grayscale();
-	let image = filter_pixels(&image, Luma([0]), |x| { x.0[0] > 220 });
+	let image = filter_pixels(&image, Luma([0]), |x| { x.0[0] > preprocessing.threshold });
 
-	let _ = image.save("last_ocr_screenshot.png");
     let mut buffer = Vec::new();
-    image.write_to(&mut std::io::Cursor::new(&mut buffer), image::ImageFormat::Png).unwrap();
+    image.write_to(&mut std::io::Cursor::new(&mut buffer), image::ImageFormat::Png).map_err(OcrError::Encode)?;
+	Ok(buffer)
+}
+
+/// OCR stage: send the PNG to each endpoint in order, retrying rate limits, server errors and network failures with back-off.
+/// The error of the last endpoint is returned when all of them fail
+pub async fn recognize(png: Vec<u8>, ocr_config: &OcrConfig) -> Result<OcrResponse, OcrError> {
+	let retry_policy = &ocr_config.retry;
+	let client = reqwest::Client::builder()
+		.connect_timeout(retry_policy.connect_timeout)
+		.timeout(retry_policy.read_timeout)
+		.build().map_err(OcrError::Unreachable)?;
+	let mut last_error = None;
+	for endpoint in std::iter::once(&ocr_config.endpoint).chain(&ocr_config.fallback_endpoints) {
+		if let Some(e) = &last_error {
+			eprintln!("OCR failed ({}), trying fallback server {}", e, endpoint);
+		}
+		let mut retries = 0;
+		let error = loop {
+			let (error, retry_after) = match post_image(&client, endpoint, &png, ocr_config.language.as_deref()).await {
+				Ok(response) => return Ok(response),
+				Err(e @ (OcrError::Unreachable(_) | OcrError::ReadResponse(_))) => (e, None),
+				Err(OcrError::ServerStatus(status, retry_after)) if retry::is_retryable(status) => (OcrError::ServerStatus(status, retry_after), retry_after),
+				Err(e) => break e,
+			};
+			retries += 1;
+			let Some(delay) = retry_policy.retry_delay(retries, retry_after) else {
+				break error;
+			};
+			eprintln!("OCR failed ({}), retrying in {:.1}s", error, delay.as_secs_f32());
+			tokio::time::sleep(delay).await;
+		};
+		last_error = Some(error);
+	}
+	Err(last_error.expect("the main OCR endpoint is always tried"))
+}
 
-	let form_for_ocrserver = multipart::Form::new().part("image", multipart::Part::bytes(buffer).file_name("image.png"));
-	let response = reqwest::blocking::Client::new()
-		.post(ocr_api_endpoint)
+async fn post_image(client: &reqwest::Client, endpoint: &str, png: &[u8], language: Option<&str>) -> Result<OcrResponse, OcrError> {
+	let mut form_for_ocrserver = multipart::Form::new().part("image", multipart::Part::bytes(png.to_vec()).file_name("image.png"));
+	if let Some(language) = language {
+		form_for_ocrserver = form_for_ocrserver.text("lang", language.to_string());
+	}
+	let response = client
+		.post(endpoint)
 		.multipart(form_for_ocrserver)
-		.send().unwrap()
-		.text().unwrap();
-	let extracted_response: Response = serde_json::from_str(&response).unwrap();
-	println!("OCR extracted text:\n{}", extracted_response.extracted_text);
-	let _ = output_channel.send(extracted_response.extracted_text);
+		.send().await.map_err(OcrError::Unreachable)?;
+	if !response.status().is_success() {
+		return Err(OcrError::ServerStatus(response.status(), retry::retry_after(response.headers())));
+	}
+	let response = response.text().await.map_err(OcrError::ReadResponse)?;
+	serde_json::from_str(&response).map_err(OcrError::InvalidResponse)
+}
+
+/// Normalize stage: drop empty blocks and place the others on screen, ocr_scale is the preprocessing scale
+pub fn normalize(response: OcrResponse, capture: &Capture, ocr_scale: f32) -> OcrResult {
+	println!("OCR extracted text:\n{}", response.extracted_text);
+	let blocks = match capture.placement {
+		Some(placement) => response.blocks.into_iter()
+			.filter(|block| !block.text.trim().is_empty())
+			.map(|block| place_block(block, &capture.image, placement.origin, ocr_scale, placement.screen_scale))
+			.collect(),
+		None => Vec::new(),
+	};
+	OcrResult {
+		text: response.extracted_text,
+		blocks,
+		region: None,
+		language: None,
+	}
+}
+
+/// Map a block from OCR image pixels back to screen coordinates and sample its background
+fn place_block(block: ResponseBlock, cropped_image: &RgbaImage, crop_origin: (u32, u32), ocr_scale: f32, screen_scale: f64) -> TextBlock {
+	let [x, y, width, height] = block.bbox.map(|v| (v.max(0.0) / ocr_scale as f64).round() as u32);
+	let x = x.min(cropped_image.width().saturating_sub(1));
+	let y = y.min(cropped_image.height().saturating_sub(1));
+	let width = width.clamp(1, cropped_image.width() - x);
+	let height = height.clamp(1, cropped_image.height() - y);
+	TextBlock {
+		text: block.text,
+		rect: (
+			((crop_origin.0 + x) as f64 * screen_scale).round() as i32,
+			((crop_origin.1 + y) as f64 * screen_scale).round() as i32,
+			(width as f64 * screen_scale).round() as u32,
+			(height as f64 * screen_scale).round() as u32,
+		),
+		background: border_average_color(cropped_image, (x, y, width, height)),
+	}
+}
+
+/// Average color of the pixels on the rectangle border, text rarely touches the bounding box edge so this approximates the background
+fn border_average_color(image: &RgbaImage, rect: (u32, u32, u32, u32)) -> [u8; 3] {
+	let (x, y, width, height) = rect;
+	let mut sum = [0u64; 3];
+	let mut count = 0u64;
+	for py in y..y + height {
+		for px in x..x + width {
+			if px != x && px != x + width - 1 && py != y && py != y + height - 1 {
+				continue;
+			}
+			let pixel = image.get_pixel(px, py);
+			for (sum_channel, pixel_channel) in sum.iter_mut().zip(pixel.0) {
+				*sum_channel += pixel_channel as u64;
+			}
+			count += 1;
+		}
+	}
+	if count == 0 {
+		return [0, 0, 0];
+	}
+	sum.map(|channel| (channel / count) as u8)
+}
+
+/// WARNING: Blocking function until arm_channel is closed
+///
+/// Each message on arm_channel snapshots the region given by current_region(), on_change() is then called once when the region stops matching that snapshot
+pub fn region_change_watcher(current_region: impl Fn() -> String, arm_channel: Receiver<()>, poll_interval: Duration, mut on_change: impl FnMut()) {
+	while arm_channel.recv().is_ok() {
+		let screen_region = current_region();
+		let Some(mut reference) = region_fingerprint(&screen_region) else {
+			continue;
+		};
+		loop {
+			std::thread::sleep(poll_interval);
+			if arm_channel.try_iter().count() > 0 {
+				match region_fingerprint(&screen_region) {
+					Some(fingerprint) => reference = fingerprint,
+					None => break,
+				}
+				continue;
+			}
+			let Some(current) = region_fingerprint(&screen_region) else {
+				break;
+			};
+			if fingerprint_distance(&reference, &current) > REGION_CHANGE_THRESHOLD {
+				on_change();
+				break;
+			}
+		}
+	}
+}
+
+/// Tiny grayscale thumbnail of the region, cheap to compare and tolerant to noise
+fn region_fingerprint(screen_region: &str) -> Option<GrayImage> {
+	let screen = Monitor::all().ok()?.into_iter().next()?;
+	let region = convert_screen_region((screen.width(), screen.height()), screen_region).ok()?;
+	let image = screen.capture_image().ok()?;
+	let image = crop_imm(&image, region.0, region.1, region.2, region.3).to_image();
+	let image = DynamicImage::ImageRgba8(image).resize_exact(FINGERPRINT_SIZE.0, FINGERPRINT_SIZE.1, image::imageops::FilterType::Triangle);
+	Some(image.to_luma8())
+}
+
+fn fingerprint_distance(a: &GrayImage, b: &GrayImage) -> f64 {
+	let total: u64 = a.pixels().zip(b.pixels())
+		.map(|(pa, pb)| pa.0[0].abs_diff(pb.0[0]) as u64)
+		.sum();
+	total as f64 / (FINGERPRINT_SIZE.0 * FINGERPRINT_SIZE.1) as f64
 }
 
 fn convert_screen_region(resolution: (u32, u32), target_region: &str) -> Result<(u32, u32, u32, u32)> {
@@ -118,3 +533,61 @@ where
 
 	filtered_img
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::retry::mock_server::{MockResponse, MockServer};
+
+	const OCR_RESPONSE: &str = r#"{"extracted_text": "こんにちは"}"#;
+
+	fn ocr_config(endpoint: &str, fallback_endpoints: &[&str]) -> OcrConfig {
+		OcrConfig {
+			endpoint: format!("{}/extract_text", endpoint),
+			fallback_endpoints: fallback_endpoints.iter().map(|endpoint| format!("{}/extract_text", endpoint)).collect(),
+			preprocessing: Preprocessing::default(),
+			retry: RetryPolicy {
+				read_timeout: Duration::from_millis(500),
+				backoff: Duration::from_millis(10),
+				..Default::default()
+			},
+			language: None,
+			detect_language: false,
+		}
+	}
+
+	#[tokio::test]
+	async fn retries_server_errors() {
+		let server = MockServer::start(vec![
+			MockResponse::new(503, "busy"),
+			MockResponse::new(429, "slow down").header("Retry-After", "0"),
+			MockResponse::new(200, OCR_RESPONSE),
+		]);
+		let response = recognize(b"png".to_vec(), &ocr_config(&server.url, &[])).await.unwrap();
+		assert_eq!(response.extracted_text, "こんにちは");
+		assert_eq!(server.requests().len(), 3);
+	}
+
+	#[tokio::test]
+	async fn falls_back_on_client_errors_and_timeouts() {
+		let not_found = MockServer::start(vec![MockResponse::new(404, "")]);
+		let stalled = MockServer::start(vec![MockResponse::new(200, OCR_RESPONSE).delay(Duration::from_secs(2))]);
+		let fallback = MockServer::start(vec![MockResponse::new(200, OCR_RESPONSE)]);
+		let mut config = ocr_config(&not_found.url, &[&stalled.url, &fallback.url]);
+		config.retry.retries = 0;
+		assert!(recognize(b"png".to_vec(), &config).await.is_ok());
+		assert_eq!(not_found.requests().len(), 1);
+		assert_eq!(stalled.requests().len(), 1);
+		assert!(fallback.requests()[0].starts_with("POST /extract_text"));
+	}
+
+	#[tokio::test]
+	async fn long_retry_after_moves_to_fallback() {
+		let rate_limited = MockServer::start(vec![MockResponse::new(429, "").header("Retry-After", "120")]);
+		let fallback = MockServer::start(vec![MockResponse::new(500, ""), MockResponse::new(500, ""), MockResponse::new(500, "")]);
+		let error = recognize(b"png".to_vec(), &ocr_config(&rate_limited.url, &[&fallback.url])).await.unwrap_err();
+		assert!(matches!(error, OcrError::ServerStatus(StatusCode::INTERNAL_SERVER_ERROR, None)), "{:?}", error);
+		assert_eq!(rate_limited.requests().len(), 1);
+		assert_eq!(fallback.requests().len(), 3);
+	}
+}
@@ -1,4 +1,137 @@
+// What the overlay windows show and how. The windows themselves are only implemented on Windows,
+// elsewhere create_window() and create_inplace_window() report it and return without a handle
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+use anyhow::Result;
+
 #[cfg(target_os = "windows")]
-pub mod windows;
-#[cfg(target_os = "windows")]
-pub use windows::*;
+mod windows;
+
+/// Everything the overlay windows can be told to do, send it then call UpdateHandle::update_window()
+#[derive(Clone)]
+pub enum WindowChannelMessage {
+	/// Replace the text of the bar overlay, shows the window if it was hidden
+	Text(String),
+	/// Replace the blocks of the in-place overlay, shows the window if it was hidden
+	Blocks(Vec<OverlayBlock>),
+	/// OCR text of the current translation, shown above it while show_source is on
+	Source(String),
+	/// Progress or problem shown instead of the text until the next Text/Clear
+	Status(String, StatusKind),
+	/// Empty the overlay without changing its visibility
+	Clear,
+	Show,
+	/// Automatic dismissal, ignored while pinned
+	Hide,
+	/// User requested show/hide, works even while pinned
+	ToggleVisibility,
+	/// Pinned overlays are never auto hidden
+	TogglePin,
+	ToggleSource,
+	Behaviour(OverlayBehaviour),
+}
+
+/// Decides the styling of a status line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusKind {
+	Busy,
+	Warning,
+	Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverlayBehaviour {
+	/// Fade out this long after the last update, None keeps the overlay forever
+	pub auto_hide: Option<Duration>,
+	/// Duration of fade-in and fade-out, zero to show/hide instantly
+	pub fade: Duration,
+	/// Add a line with the OCR text above the translation so misreads are easy to spot
+	pub show_source: bool,
+	pub theme: OverlayTheme,
+}
+
+impl Default for OverlayBehaviour {
+	fn default() -> Self {
+		Self {
+			auto_hide: None,
+			fade: Duration::from_millis(200),
+			show_source: false,
+			theme: OverlayTheme::default(),
+		}
+	}
+}
+
+/// Fonts and colors of the bar overlay, colors are RGB. The in-place overlay only uses the font
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverlayTheme {
+	pub font: String,
+	pub font_size: i32,
+	pub text_color: [u8; 3],
+	/// Should cover Japanese, Windows font linking covers other scripts
+	pub source_font: String,
+	pub source_font_size: i32,
+	pub source_color: [u8; 3],
+	pub background_color: [u8; 3],
+	pub opacity: u8,
+}
+
+impl Default for OverlayTheme {
+	fn default() -> Self {
+		Self {
+			font: "Adagio Sans".to_string(),
+			font_size: 42,
+			text_color: [0xFF, 0xFF, 0xFF],
+			source_font: "Yu Gothic UI".to_string(),
+			source_font_size: 30,
+			source_color: [0xB0, 0xB0, 0xB0],
+			background_color: [0x10, 0x10, 0x10],
+			opacity: 200,
+		}
+	}
+}
+
+/// A translated line drawn over its original position by the in-place window
+#[derive(Clone)]
+pub struct OverlayBlock {
+	pub text: String,
+	/// x, y, width, height in screen coordinates
+	pub rect: (i32, i32, u32, u32),
+	/// RGB fill painted under the text to cover the original
+	pub background: [u8; 3],
+}
+
+/// Wakes an overlay window up to read its message channel, sent back by create_window() and create_inplace_window()
+#[derive(Clone, Copy)]
+pub struct UpdateHandle {
+	#[cfg(target_os = "windows")]
+	hwnd: ::windows::Win32::Foundation::HWND,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl UpdateHandle {
+	pub fn update_window(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// WARNING: Blocking function, runs the bar overlay window until the process exits. The UpdateHandle is sent on hwnd_return once it is created
+pub fn create_window(message_channel: Receiver<WindowChannelMessage>, hwnd_return: Sender<UpdateHandle>) {
+	#[cfg(target_os = "windows")]
+	windows::create_window(message_channel, hwnd_return);
+	#[cfg(not(target_os = "windows"))]
+	unsupported(message_channel, hwnd_return);
+}
+
+/// Same as create_window() for the click-through window drawing the blocks over their source text
+pub fn create_inplace_window(message_channel: Receiver<WindowChannelMessage>, hwnd_return: Sender<UpdateHandle>) {
+	#[cfg(target_os = "windows")]
+	windows::create_inplace_window(message_channel, hwnd_return);
+	#[cfg(not(target_os = "windows"))]
+	unsupported(message_channel, hwnd_return);
+}
+
+/// Dropping hwnd_return tells the caller no window was created
+#[cfg(not(target_os = "windows"))]
+fn unsupported(_message_channel: Receiver<WindowChannelMessage>, _hwnd_return: Sender<UpdateHandle>) {
+	eprintln!("The overlay is only supported on Windows");
+}
@@ -1,5 +1,6 @@
 use std::sync::mpsc::{Receiver, Sender};
 use anyhow::Result;
+use super::{OverlayBehaviour, OverlayBlock, StatusKind, UpdateHandle, WindowChannelMessage};
 use windows::{
 	core::*,
 	Win32::{
@@ -13,43 +14,97 @@ use windows::{
 use xcap::Monitor;
 
 const WM_UPDATE_TEXT: u32 = WM_USER + 1;
-const BG_COLOR: u32 = 0x00101010;
-const BG_ALPHA: u8 = 200;
 const OVERLAY_HEIGHT: i32 = 52; // (1600-1440) / 2 / 1.5, this targets 1.5x scale on a 2560x1600 screen while not overlay with 16:9 content
+const INPLACE_COLOR_KEY: u32 = 0x00FF00FF; // Painted pixels of this color are see-through in the in-place window
+const INPLACE_MIN_FONT_SIZE: i32 = 10;
+const AUTO_HIDE_TIMER_ID: usize = 1;
+const FADE_TIMER_ID: usize = 2;
+const FADE_STEP_MS: u32 = 15;
+const STATUS_ACCENT_WIDTH: i32 = 8;
 
-pub struct WindowChannelMessage {
-	pub text: String,
-	pub screen_dimension: Option<(u32, u32, f64)>,
+impl StatusKind {
+	/// (text color, accent color) as COLORREF
+	fn colors(&self) -> (u32, u32) {
+		match self {
+			StatusKind::Busy => (0x00C0C0C0, 0x00D08040),
+			StatusKind::Warning => (0x0040C0FF, 0x0000A0FF),
+			StatusKind::Error => (0x006060FF, 0x002020E0),
+		}
+	}
+}
+
+fn rgb(color: [u8; 3]) -> COLORREF {
+	let [r, g, b] = color;
+	COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+#[derive(PartialEq)]
+enum Fade {
+	In,
+	Out,
 }
 
-pub struct UpdateHandle {
-	inner: HWND
+/// Per window state, owned by the window through GWLP_USERDATA
+struct WindowData {
+	message_channel: Receiver<WindowChannelMessage>,
+	/// None until the first text arrives
+	text: Option<String>,
+	status: Option<(String, StatusKind)>,
+	source: String,
+	blocks: Vec<OverlayBlock>,
+	visible: bool,
+	pinned: bool,
+	alpha: u8,
+	max_alpha: u8,
+	color_key: Option<u32>,
+	behaviour: OverlayBehaviour,
+	fading: Option<Fade>,
+}
+
+impl WindowData {
+	fn new(message_channel: Receiver<WindowChannelMessage>, max_alpha: u8, color_key: Option<u32>) -> Self {
+		Self {
+			message_channel,
+			text: None,
+			status: None,
+			source: String::new(),
+			blocks: Vec::new(),
+			visible: true,
+			pinned: false,
+			alpha: max_alpha,
+			max_alpha,
+			color_key,
+			behaviour: OverlayBehaviour::default(),
+			fading: None,
+		}
+	}
 }
 
 impl UpdateHandle {
-	fn new(inner: HWND) -> Self {
+	fn new(hwnd: HWND) -> Self {
 		Self {
-			inner
+			hwnd
 		}
 	}
+
 	pub fn update_window(&self) -> Result<()> {
 		unsafe {
-			PostMessageW(self.inner, WM_UPDATE_TEXT, WPARAM(0), LPARAM(0))?;
+			PostMessageW(self.hwnd, WM_UPDATE_TEXT, WPARAM(0), LPARAM(0))?;
 			Ok(())
 		}
 	}
 }
 
 /// create_window() launch example
-/// ```
+/// ```ignore
 /// let (text_tx, text_rx) = sync_channel(10);
 /// let (window_handle_tx, window_handle_rx) = sync_channel(10);
 /// std::thread::spawn(move || create_window(text_rx, window_handle_tx));
 /// let window_refresh = window_handle_rx.recv().unwrap();
 /// ```
 /// Then
-/// ```
-/// let _ = text_tx.send(result);
+/// ```ignore
+/// let _ = text_tx.send(WindowChannelMessage::Text(result));
 /// let _ = window_refresh.update_window();
 /// ```
 pub fn create_window(message_channel: Receiver<WindowChannelMessage>, hwnd_return: Sender<UpdateHandle>) {
@@ -85,10 +140,10 @@ pub fn create_window(message_channel: Receiver<WindowChannelMessage>, hwnd_retur
 		);
 
 		// Set the window as layered with 50% opacity (128 out of 255)
-		SetLayeredWindowAttributes(hwnd, COLORREF(BG_COLOR), BG_ALPHA, LWA_ALPHA).unwrap();
+		SetLayeredWindowAttributes(hwnd, rgb(OverlayTheme::default().background_color), OverlayTheme::default().opacity, LWA_ALPHA).unwrap();
 
-		
-		let window_data_ptr = Box::into_raw(Box::new(message_channel));
+
+		let window_data_ptr = Box::into_raw(Box::new(WindowData::new(message_channel, OverlayTheme::default().opacity, None)));
 		SetWindowLongPtrW(hwnd, GWLP_USERDATA, window_data_ptr as isize);
 
 		ShowWindow(hwnd, SW_SHOW);
@@ -107,31 +162,395 @@ pub fn create_window(message_channel: Receiver<WindowChannelMessage>, hwnd_retur
 	}
 }
 
+/// Full screen click-through window which paints each translated block over its source text,
+/// driven by WindowChannelMessage::Blocks. Launched the same way as create_window()
+pub fn create_inplace_window(message_channel: Receiver<WindowChannelMessage>, hwnd_return: Sender<UpdateHandle>) {
+	unsafe {
+		let instance = GetModuleHandleW(None).unwrap().into();
+		let class_name = w!("Inplace Window");
+
+		let wc = WNDCLASSW {
+			hCursor: LoadCursorW(None, IDC_ARROW).unwrap(),
+			hInstance: instance,
+			lpszClassName: class_name,
+			style: CS_HREDRAW | CS_VREDRAW,
+			lpfnWndProc: Some(inplace_window_proc),
+			..Default::default()
+		};
+
+		RegisterClassW(&wc);
+
+		let hwnd = CreateWindowExW(
+			WS_EX_LAYERED | WS_EX_TOPMOST | WS_EX_TRANSPARENT | WS_EX_TOOLWINDOW,
+			class_name,
+			None,
+			WS_POPUP,
+			0,
+			0,
+			GetSystemMetrics(SM_CXSCREEN),
+			GetSystemMetrics(SM_CYSCREEN),
+			None,
+			None,
+			instance,
+			None,
+		);
+
+		// Everything left in the color key is fully transparent, only the blocks are visible
+		SetLayeredWindowAttributes(hwnd, COLORREF(INPLACE_COLOR_KEY), 255, LWA_COLORKEY | LWA_ALPHA).unwrap();
+
+		let window_data_ptr = Box::into_raw(Box::new(WindowData::new(message_channel, 255, Some(INPLACE_COLOR_KEY))));
+		SetWindowLongPtrW(hwnd, GWLP_USERDATA, window_data_ptr as isize);
+
+		ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+		UpdateWindow(hwnd);
+
+		if hwnd_return.send(UpdateHandle::new(hwnd)).is_err() {
+			eprintln!("Can't return HWND handle");
+			std::process::exit(0);
+		};
+
+		let mut msg = MSG::default();
+		while GetMessageW(&mut msg, None, 0, 0).into() {
+			TranslateMessage(&msg);
+			DispatchMessageW(&msg);
+		}
+	}
+}
+
+unsafe fn window_data<'a>(hwnd: HWND) -> &'a mut WindowData {
+	&mut *(GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut WindowData)
+}
+
+/// Apply every pending WindowChannelMessage, shared by both overlay windows
+unsafe fn process_messages(hwnd: HWND, data: &mut WindowData) {
+	while let Ok(message) = data.message_channel.try_recv() {
+		match message {
+			WindowChannelMessage::Text(text) => {
+				let is_empty = text.is_empty();
+				data.text = Some(text);
+				data.status = None;
+				if !is_empty {
+					show(hwnd, data);
+					arm_auto_hide(hwnd, data);
+				}
+			},
+			WindowChannelMessage::Blocks(blocks) => {
+				let is_empty = blocks.is_empty();
+				data.blocks = blocks;
+				if !is_empty {
+					show(hwnd, data);
+					arm_auto_hide(hwnd, data);
+				}
+			},
+			WindowChannelMessage::Source(source) => {
+				// The overlay is a single line per text, furigana and line breaks from OCR are joined
+				data.source = source.split_whitespace().collect::<Vec<_>>().join(" ");
+				resize_for_source(hwnd, data);
+			},
+			WindowChannelMessage::Status(status, kind) => {
+				data.status = Some((status, kind));
+				show(hwnd, data);
+				arm_auto_hide(hwnd, data);
+			},
+			WindowChannelMessage::Clear => {
+				data.text = Some(String::new());
+				data.status = None;
+				data.source.clear();
+				data.blocks.clear();
+				resize_for_source(hwnd, data);
+			},
+			WindowChannelMessage::Show => show(hwnd, data),
+			WindowChannelMessage::Hide => {
+				if !data.pinned {
+					hide(hwnd, data);
+				}
+			},
+			WindowChannelMessage::ToggleVisibility => {
+				if data.visible && data.fading != Some(Fade::Out) {
+					hide(hwnd, data);
+				} else {
+					show(hwnd, data);
+				}
+			},
+			WindowChannelMessage::TogglePin => {
+				data.pinned = !data.pinned;
+				if data.pinned {
+					let _ = KillTimer(hwnd, AUTO_HIDE_TIMER_ID);
+					show(hwnd, data);
+				} else {
+					arm_auto_hide(hwnd, data);
+				}
+			},
+			WindowChannelMessage::ToggleSource => {
+				data.behaviour.show_source = !data.behaviour.show_source;
+				resize_for_source(hwnd, data);
+			},
+			WindowChannelMessage::Behaviour(behaviour) => {
+				if data.color_key.is_none() {
+					data.max_alpha = behaviour.theme.opacity;
+					if data.visible && data.fading.is_none() {
+						data.alpha = data.max_alpha;
+						apply_alpha(hwnd, data);
+					}
+				}
+				data.behaviour = behaviour;
+				resize_for_source(hwnd, data);
+				arm_auto_hide(hwnd, data);
+			},
+		}
+	}
+}
+
+fn shows_source(data: &WindowData) -> bool {
+	data.behaviour.show_source && !data.source.is_empty()
+}
+
+/// The bar overlay doubles its height while the source line is displayed, the in-place window is left alone
+unsafe fn resize_for_source(hwnd: HWND, data: &WindowData) {
+	if data.color_key.is_some() {
+		return;
+	}
+	let height = if shows_source(data) { OVERLAY_HEIGHT * 2 } else { OVERLAY_HEIGHT };
+	let _ = SetWindowPos(hwnd, None, 0, 0, get_screen_width_sf().0, height, SWP_NOZORDER | SWP_NOMOVE | SWP_NOACTIVATE);
+}
+
+unsafe fn show(hwnd: HWND, data: &mut WindowData) {
+	if !data.visible {
+		data.visible = true;
+		data.alpha = 0;
+		apply_alpha(hwnd, data);
+		ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+	}
+	start_fade(hwnd, data, Fade::In);
+}
+
+unsafe fn hide(hwnd: HWND, data: &mut WindowData) {
+	let _ = KillTimer(hwnd, AUTO_HIDE_TIMER_ID);
+	if data.visible {
+		start_fade(hwnd, data, Fade::Out);
+	}
+}
+
+/// (Re)start the countdown to auto hide, every new text restarts it
+unsafe fn arm_auto_hide(hwnd: HWND, data: &WindowData) {
+	let _ = KillTimer(hwnd, AUTO_HIDE_TIMER_ID);
+	if let Some(auto_hide) = data.behaviour.auto_hide {
+		if !data.pinned && data.visible {
+			SetTimer(hwnd, AUTO_HIDE_TIMER_ID, auto_hide.as_millis().clamp(1, u32::MAX as u128) as u32, None);
+		}
+	}
+}
+
+unsafe fn start_fade(hwnd: HWND, data: &mut WindowData, direction: Fade) {
+	if data.behaviour.fade.is_zero() {
+		let _ = KillTimer(hwnd, FADE_TIMER_ID);
+		data.fading = None;
+		finish_fade(hwnd, data, direction);
+		return;
+	}
+	data.fading = Some(direction);
+	SetTimer(hwnd, FADE_TIMER_ID, FADE_STEP_MS, None);
+}
+
+unsafe fn step_fade(hwnd: HWND, data: &mut WindowData) {
+	let Some(direction) = data.fading.take() else {
+		let _ = KillTimer(hwnd, FADE_TIMER_ID);
+		return;
+	};
+	let fade_ms = data.behaviour.fade.as_millis().max(1) as u32;
+	let step = (data.max_alpha as u32 * FADE_STEP_MS / fade_ms).clamp(1, 255) as u8;
+	let done = match direction {
+		Fade::In => {
+			data.alpha = data.alpha.saturating_add(step).min(data.max_alpha);
+			data.alpha == data.max_alpha
+		},
+		Fade::Out => {
+			data.alpha = data.alpha.saturating_sub(step);
+			data.alpha == 0
+		},
+	};
+	if done {
+		let _ = KillTimer(hwnd, FADE_TIMER_ID);
+		finish_fade(hwnd, data, direction);
+	} else {
+		apply_alpha(hwnd, data);
+		data.fading = Some(direction);
+	}
+}
+
+unsafe fn finish_fade(hwnd: HWND, data: &mut WindowData, direction: Fade) {
+	match direction {
+		Fade::In => {
+			data.alpha = data.max_alpha;
+			apply_alpha(hwnd, data);
+		},
+		Fade::Out => {
+			data.alpha = 0;
+			data.visible = false;
+			ShowWindow(hwnd, SW_HIDE);
+		},
+	}
+}
+
+unsafe fn apply_alpha(hwnd: HWND, data: &WindowData) {
+	let _ = match data.color_key {
+		Some(color_key) => SetLayeredWindowAttributes(hwnd, COLORREF(color_key), data.alpha, LWA_COLORKEY | LWA_ALPHA),
+		None => SetLayeredWindowAttributes(hwnd, rgb(data.behaviour.theme.background_color), data.alpha, LWA_ALPHA),
+	};
+}
+
+/// WM_UPDATE_TEXT and WM_TIMER handling common to both overlay windows
+unsafe fn handle_common_message(hwnd: HWND, message: u32, wparam: WPARAM) -> Option<LRESULT> {
+	match message {
+		WM_UPDATE_TEXT => {
+			process_messages(hwnd, window_data(hwnd));
+			InvalidateRect(hwnd, None, true); // Invalidate the window to trigger a redraw
+			Some(LRESULT(0))
+		},
+		WM_TIMER => {
+			let data = window_data(hwnd);
+			match wparam.0 {
+				AUTO_HIDE_TIMER_ID => {
+					let _ = KillTimer(hwnd, AUTO_HIDE_TIMER_ID);
+					if !data.pinned {
+						hide(hwnd, data);
+					}
+				},
+				FADE_TIMER_ID => step_fade(hwnd, data),
+				_ => return None,
+			}
+			Some(LRESULT(0))
+		},
+		_ => None,
+	}
+}
+
+extern "system" fn inplace_window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+	unsafe {
+		if let Some(result) = handle_common_message(hwnd, message, wparam) {
+			return result;
+		}
+		match message {
+			WM_DESTROY => {
+				PostQuitMessage(0);
+				LRESULT(0)
+			}
+			WM_PAINT => {
+				let window_data = window_data(hwnd);
+
+				let mut ps = PAINTSTRUCT::default();
+				let hdc_window = BeginPaint(hwnd, &mut ps);
+
+				let key_brush = CreateSolidBrush(COLORREF(INPLACE_COLOR_KEY));
+				FillRect(hdc_window, &ps.rcPaint, key_brush);
+				DeleteObject(key_brush);
+				SetBkMode(hdc_window, TRANSPARENT);
+
+				for block in &window_data.blocks {
+					let mut block_rect = RECT {
+						left: block.rect.0,
+						top: block.rect.1,
+						right: block.rect.0 + block.rect.2 as i32,
+						bottom: block.rect.1 + block.rect.3 as i32,
+					};
+					let [r, g, b] = block.background;
+					let mut background = r as u32 | (g as u32) << 8 | (b as u32) << 16;
+					if background == INPLACE_COLOR_KEY {
+						background ^= 1; // Keep the fill visible
+					}
+					let brush = CreateSolidBrush(COLORREF(background));
+					FillRect(hdc_window, &block_rect, brush);
+					DeleteObject(brush);
+
+					// Pick black or white text depending on the perceived brightness of the fill
+					let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+					SetTextColor(hdc_window, COLORREF(if luminance > 140.0 { 0x00000000 } else { 0x00FFFFFF }));
+
+					let mut display_text = Vec::from(HSTRING::from(block.text.as_str()).as_wide());
+					let h_font = create_fitting_font(hdc_window, &window_data.behaviour.theme.font, &display_text, block.rect.2 as i32, block.rect.3 as i32);
+					SelectObject(hdc_window, h_font);
+					DrawTextW(hdc_window, &mut display_text, &mut block_rect, DT_SINGLELINE | DT_VCENTER | DT_CENTER);
+					DeleteObject(h_font);
+				}
+
+				EndPaint(hwnd, &ps);
+				LRESULT(0)
+			},
+			_ => DefWindowProcW(hwnd, message, wparam, lparam),
+		}
+	}
+}
+
+/// Largest font not exceeding the box height whose rendered text still fits the box width
+unsafe fn create_fitting_font(hdc: HDC, font: &str, text: &[u16], max_width: i32, max_height: i32) -> HFONT {
+	let mut font_size = (max_height * 4 / 5).max(INPLACE_MIN_FONT_SIZE);
+	loop {
+		let h_font = create_overlay_font(font, font_size);
+		let old_font = SelectObject(hdc, h_font);
+		let mut size = SIZE::default();
+		let measured = GetTextExtentPoint32W(hdc, text, &mut size).as_bool();
+		SelectObject(hdc, old_font);
+		if !measured || size.cx <= max_width || font_size <= INPLACE_MIN_FONT_SIZE {
+			return h_font;
+		}
+		DeleteObject(h_font);
+		font_size = (font_size * 9 / 10).max(INPLACE_MIN_FONT_SIZE);
+	}
+}
+
+/// Font for OCR source text, SHIFTJIS_CHARSET so kana and kanji render properly
+unsafe fn create_source_font(font: &str, font_size: i32) -> HFONT {
+	CreateFontW(
+		font_size,
+		0,
+		0,
+		0,
+		FW_NORMAL.0 as i32,
+		false.into(),
+		false.into(),
+		false.into(),
+		SHIFTJIS_CHARSET.0 as u32,
+		OUT_OUTLINE_PRECIS.0 as u32,
+		CLIP_DEFAULT_PRECIS.0 as u32,
+		ANTIALIASED_QUALITY.0 as u32,
+		VARIABLE_PITCH.0 as u32,
+		&HSTRING::from(font),
+	)
+}
+
+unsafe fn create_overlay_font(font: &str, font_size: i32) -> HFONT {
+	CreateFontW(
+		font_size,
+		0,
+		0,
+		0,
+		FW_NORMAL.0 as i32,
+		false.into(),
+		false.into(),
+		false.into(),
+		DEFAULT_CHARSET.0 as u32,
+		OUT_OUTLINE_PRECIS.0 as u32,
+		CLIP_DEFAULT_PRECIS.0 as u32,
+		ANTIALIASED_QUALITY.0 as u32,
+		VARIABLE_PITCH.0 as u32,
+		&HSTRING::from(font),
+	)
+}
+
 extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
 	unsafe {
+		if let Some(result) = handle_common_message(hwnd, message, wparam) {
+			return result;
+		}
 		match message {
 			WM_DESTROY => {
 				PostQuitMessage(0);
 				LRESULT(0)
 			}
 			WM_PAINT => {
-				let font_size = 42;
-				let h_font = CreateFontW(
-					font_size,
-					0,
-					0,
-					0,
-					FW_NORMAL.0 as i32,
-					false.into(),
-					false.into(),
-					false.into(),
-					DEFAULT_CHARSET.0 as u32,
-					OUT_OUTLINE_PRECIS.0 as u32,
-					CLIP_DEFAULT_PRECIS.0 as u32,
-					ANTIALIASED_QUALITY.0 as u32,
-					VARIABLE_PITCH.0 as u32,
-					w!("Adagio Sans"),
-				);
+				let window_data = window_data(hwnd);
+				let theme = &window_data.behaviour.theme;
+				let h_font = create_overlay_font(&theme.font, theme.font_size);
 
 				let mut ps = PAINTSTRUCT::default();
 				let hdc_window = BeginPaint(hwnd, &mut ps);
@@ -148,48 +567,72 @@ extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam:
 					text_rect.left = (*window_rect).left + 30;
 					text_rect.right = (*window_rect).right - 30;
 				}
+				let mut source_rect = None;
+				if shows_source(window_data) {
+					// Source on the upper half, translation on the lower half
+					let mut upper_rect = text_rect;
+					upper_rect.bottom = upper_rect.top + OVERLAY_HEIGHT;
+					text_rect.top = upper_rect.bottom;
+					source_rect = Some(upper_rect);
+				}
 				let text_rect: *mut RECT = &mut text_rect;
 
-				let data_channel_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Receiver<WindowChannelMessage>;
-				let display_text: String = match (*data_channel_ptr).try_recv() {
-					Ok(data) => {
-						if data.text.is_empty() {
-							" ".to_string() // "" will cause Win32 to crash...?
-						} else {
-							data.text
-						}
+				let mut text_color = rgb(theme.text_color);
+				let mut accent_color = None;
+				let display_text: String = match (&window_data.status, &window_data.text) {
+					(Some((status, kind)), _) => {
+						text_color = COLORREF(kind.colors().0);
+						accent_color = Some(kind.colors().1);
+						status.clone()
 					},
-					Err(_) => {
-						// eprintln!("windows::(*data_channel_ptr).try_recv(): {}", e);
-						"Waiting for input".to_string()
-					}
+					(None, Some(text)) if text.is_empty() => " ".to_string(), // "" will cause Win32 to crash...?
+					(None, Some(text)) => text.clone(),
+					(None, None) => "Waiting for input".to_string(),
 				};
 				let mut display_text = Vec::from(HSTRING::from(display_text).as_wide());
 				let display_text = &mut display_text[..];
 
-				let bk_color = COLORREF(BG_COLOR);
-				let brush = CreateSolidBrush(bk_color);
+				let brush = CreateSolidBrush(rgb(theme.background_color));
 				FillRect(hdc_window, &ps.rcPaint, brush); // Fill the background
+				if let Some(accent_color) = accent_color {
+					// Colored bar on the left edge of the translation line so the state is readable at a glance
+					let accent_brush = CreateSolidBrush(COLORREF(accent_color));
+					let accent_rect = RECT {
+						left: 0,
+						top: (*text_rect).top,
+						right: STATUS_ACCENT_WIDTH,
+						bottom: (*text_rect).bottom,
+					};
+					FillRect(hdc_window, &accent_rect, accent_brush);
+					DeleteObject(accent_brush);
+				}
 
 				SelectObject(hdc_window, h_font);
-				SetTextColor(hdc_window, COLORREF(0x00FFFFFF));
+				SetTextColor(hdc_window, text_color);
 				SetBkMode(hdc_window, TRANSPARENT);
 
 				DrawTextW(hdc_window, display_text, text_rect, DT_SINGLELINE | DT_VCENTER | DT_RIGHT);
 
+				if let Some(mut source_rect) = source_rect {
+					let h_source_font = create_source_font(&theme.source_font, theme.source_font_size);
+					SelectObject(hdc_window, h_source_font);
+					SetTextColor(hdc_window, rgb(theme.source_color));
+					let mut source_text = Vec::from(HSTRING::from(window_data.source.as_str()).as_wide());
+					DrawTextW(hdc_window, &mut source_text, &mut source_rect, DT_SINGLELINE | DT_VCENTER | DT_RIGHT | DT_END_ELLIPSIS);
+					SelectObject(hdc_window, h_font);
+					DeleteObject(h_source_font);
+				}
+
 				DeleteObject(h_font);
 				DeleteObject(brush);
 				EndPaint(hwnd, &ps);
 				LRESULT(0)
 			},
-			WM_UPDATE_TEXT => {
-				InvalidateRect(hwnd, None, true); // Invalidate the window to trigger a redraw
-				LRESULT(0)
-			},
 			#[allow(unreachable_patterns)] // WM_SETTINGCHANGE and WM_WININICHANGE are both 26
 			WM_DISPLAYCHANGE | WM_SETTINGCHANGE | WM_WININICHANGE | WM_DPICHANGED => {
 				let width = get_screen_width_sf().0;
-				if SetWindowPos(hwnd, None, 0, 0, width, OVERLAY_HEIGHT, SWP_NOZORDER | SWP_NOMOVE).is_err() {
+				let height = if shows_source(window_data(hwnd)) { OVERLAY_HEIGHT * 2 } else { OVERLAY_HEIGHT };
+				if SetWindowPos(hwnd, None, 0, 0, width, height, SWP_NOZORDER | SWP_NOMOVE).is_err() {
 					LRESULT(-1)
 				} else {
 					LRESULT(0)
@@ -0,0 +1,201 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use crate::config::Settings;
+use tokio::sync::broadcast;
+use crate::display::{spawn_display_dispatcher, BroadcastSink, DisplayEvent, DisplaySender, DisplaySink, Origin, TranslationDone};
+use crate::language;
+use crate::ocr::{CaptureSource, HttpOcr, OcrEngine, OcrResult, OcrSource, ScreenCapture};
+use crate::overlay::{UpdateHandle, WindowChannelMessage};
+use crate::translator::{OpenAiTranslator, TranslateError, TranslateRequest, Translator, TranslatorConfig};
+use super::{ocr_stage, report_translate_error, translate_stage, translation_settings, trigger_channel, CaptureTranslator, TriggerSender};
+
+/// OCR results waiting for the translate stage
+const OCR_RESULT_QUEUE: usize = 10;
+/// Events kept for a slow subscriber, it skips ahead when it falls further behind
+const SUBSCRIBER_QUEUE: usize = 256;
+
+/// Configures a Pipeline, only the settings are required. The default parts capture the screen, read it with the
+/// OCR server(settings.ocr) and translate with the chat completions API(settings.translator)
+pub struct PipelineBuilder {
+	settings: Arc<RwLock<Settings>>,
+	capture_source: Arc<dyn CaptureSource>,
+	ocr_engine: Arc<dyn OcrEngine>,
+	translator: Arc<dyn Translator>,
+	sinks: Vec<Box<dyn DisplaySink>>,
+	inplace_window: Option<(std::sync::mpsc::Sender<WindowChannelMessage>, UpdateHandle)>,
+	region_change_arm_tx: Option<std::sync::mpsc::Sender<()>>,
+}
+
+impl PipelineBuilder {
+	pub fn capture_source(mut self, capture_source: impl CaptureSource + 'static) -> Self {
+		self.capture_source = Arc::new(capture_source);
+		self
+	}
+
+	pub fn ocr_engine(mut self, ocr_engine: impl OcrEngine + 'static) -> Self {
+		self.ocr_engine = Arc::new(ocr_engine);
+		self
+	}
+
+	pub fn translator(mut self, translator: impl Translator + 'static) -> Self {
+		self.translator = Arc::new(translator);
+		self
+	}
+
+	/// Every display event goes to each sink, in the order they were added
+	pub fn sink(mut self, sink: impl DisplaySink + 'static) -> Self {
+		self.sinks.push(Box::new(sink));
+		self
+	}
+
+	/// Translate captures block by block into this window, drawn over the original text
+	pub fn inplace_window(mut self, message_channel: std::sync::mpsc::Sender<WindowChannelMessage>, window_refresh: UpdateHandle) -> Self {
+		self.inplace_window = Some((message_channel, window_refresh));
+		self
+	}
+
+	/// Signaled after each capture translation is shown, see ocr::region_change_watcher()
+	pub fn region_change_arm(mut self, arm_tx: std::sync::mpsc::Sender<()>) -> Self {
+		self.region_change_arm_tx = Some(arm_tx);
+		self
+	}
+
+	/// Start the stages on the current tokio runtime
+	pub fn build(mut self) -> Pipeline {
+		let ocr_pool_size = self.settings.read().unwrap().ocr_pool;
+		let (event_broadcast, _) = broadcast::channel(SUBSCRIBER_QUEUE);
+		self.sinks.push(Box::new(BroadcastSink(event_broadcast.clone())));
+		let display_channel = spawn_display_dispatcher(self.sinks);
+		let (trigger_tx, trigger_rx) = trigger_channel(ocr_pool_size, display_channel.clone());
+		let (ocr_result_tx, ocr_result_rx) = mpsc::channel(OCR_RESULT_QUEUE);
+		tokio::spawn(ocr_stage(trigger_rx, self.capture_source, self.ocr_engine, ocr_result_tx.clone(), display_channel.clone()));
+		let last_ocr_result = Arc::new(Mutex::new(None));
+		let capture_translator = CaptureTranslator {
+			settings: self.settings.clone(),
+			translator: self.translator.clone(),
+			display_channel: display_channel.clone(),
+			inplace_window: self.inplace_window,
+			region_change_arm_tx: self.region_change_arm_tx,
+		};
+		tokio::spawn(translate_stage(ocr_result_rx, capture_translator, last_ocr_result.clone()));
+		Pipeline {
+			settings: self.settings,
+			translator: self.translator,
+			trigger_tx,
+			ocr_result_tx,
+			last_ocr_result,
+			display_channel,
+			event_broadcast,
+		}
+	}
+}
+
+/// Handle to the running stages, cloned handles share them. The stages end once every handle is dropped
+#[derive(Clone)]
+pub struct Pipeline {
+	settings: Arc<RwLock<Settings>>,
+	translator: Arc<dyn Translator>,
+	trigger_tx: TriggerSender,
+	/// Skips capture and OCR, for translating the last result again
+	ocr_result_tx: mpsc::Sender<OcrResult>,
+	last_ocr_result: Arc<Mutex<Option<OcrResult>>>,
+	display_channel: DisplaySender,
+	event_broadcast: broadcast::Sender<DisplayEvent>,
+}
+
+impl Pipeline {
+	pub fn builder(settings: Settings) -> PipelineBuilder {
+		PipelineBuilder {
+			settings: Arc::new(RwLock::new(settings)),
+			capture_source: Arc::new(ScreenCapture),
+			ocr_engine: Arc::new(HttpOcr),
+			translator: Arc::new(OpenAiTranslator),
+			sinks: Vec::new(),
+			inplace_window: None,
+			region_change_arm_tx: None,
+		}
+	}
+
+	/// Read by every trigger and translation, changes apply to the next one
+	pub fn settings(&self) -> &Arc<RwLock<Settings>> {
+		&self.settings
+	}
+
+	/// Send events to the sinks, e.g. a notice about something done outside the pipeline
+	pub fn display_channel(&self) -> &DisplaySender {
+		&self.display_channel
+	}
+
+	/// Every display event from now on, like a sink that can be added while running
+	pub fn subscribe(&self) -> broadcast::Receiver<DisplayEvent> {
+		self.event_broadcast.subscribe()
+	}
+
+	/// Read and translate source with the current OCR settings. Never blocks, see TriggerSender::trigger()
+	pub fn trigger(&self, source: OcrSource) {
+		let ocr_config = self.settings.read().unwrap().ocr.clone();
+		self.trigger_tx.trigger(source, ocr_config);
+	}
+
+	/// Read and translate the selected screen region
+	pub fn trigger_screen(&self) {
+		let screen_region = self.settings.read().unwrap().screen_regions.current();
+		self.trigger(OcrSource::Screen(screen_region));
+	}
+
+	/// Translate the last OCR result again, e.g. after changing the model. False if there is none
+	pub async fn retranslate(&self) -> bool {
+		let last_ocr_result = self.last_ocr_result.lock().unwrap().clone();
+		match last_ocr_result {
+			Some(ocr_result) => {
+				let _ = self.ocr_result_tx.send(ocr_result).await;
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Forget the last OCR result and clear the sinks
+	pub fn clear_context(&self) {
+		*self.last_ocr_result.lock().unwrap() = None;
+		let _ = self.display_channel.send(DisplayEvent::Clear);
+	}
+
+	/// Translate text without capturing it, the result goes to the sinks with origin.
+	/// The translation is also streamed to streaming_output, failures are reported to the sinks too
+	pub async fn translate_text(&self, text: &str, origin: Origin, streaming_output: Option<mpsc::Sender<String>>) -> Result<String, TranslateError> {
+		let translator_config = self.settings.read().unwrap().translator.clone();
+		self.translate_text_with(translator_config, text, origin, streaming_output).await
+	}
+
+	/// translate_text() with other translator settings than the current ones, e.g. another model or prompt for one translation
+	pub async fn translate_text_with(&self, translator_config: TranslatorConfig, text: &str, origin: Origin, streaming_output: Option<mpsc::Sender<String>>) -> Result<String, TranslateError> {
+		let (_, src_lang, target_lang, _) = translation_settings(&self.settings);
+		// Typed text is translated even when it is in the target language already, it was asked for
+		let detected_language = language::is_auto(&src_lang).then(|| language::detect(text)).flatten();
+		let src_lang = detected_language.map_or(src_lang, |detected| detected.name.to_string());
+		let translation_request = TranslateRequest::new(text, &src_lang, &target_lang);
+		let start_time = Instant::now();
+		let _ = self.display_channel.send(DisplayEvent::Clear);
+		let _ = self.display_channel.send(DisplayEvent::Source(text.to_string()));
+		match self.translator.translate(&translator_config, &translation_request, streaming_output, Some(self.display_channel.clone())).await {
+			Ok(result) => {
+				let _ = self.display_channel.send(DisplayEvent::Done(TranslationDone {
+					origin,
+					source: text.to_string(),
+					translation: result.clone(),
+					target_lang,
+					model: translator_config.model,
+					region: None,
+					latency: start_time.elapsed(),
+				}));
+				Ok(result)
+			},
+			Err(e) => {
+				report_translate_error(&self.display_channel, &e);
+				Err(e)
+			},
+		}
+	}
+}
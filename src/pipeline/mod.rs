@@ -0,0 +1,512 @@
+use futures::StreamExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use crate::config::{NewCapturePolicy, Settings};
+use crate::display::{DisplayEvent, DisplaySender, Origin, Stage, Status, TranslationDone};
+use crate::language::{self, Language};
+use crate::ocr::{self, CaptureSource, OcrConfig, OcrEngine, OcrError, OcrResult, OcrSource, PoolSize, TextBlock};
+use crate::overlay::{OverlayBlock, UpdateHandle, WindowChannelMessage};
+use crate::translator::{TranslateError, TranslateRequest, Translator, TranslatorConfig};
+
+mod builder;
+pub use builder::{Pipeline, PipelineBuilder};
+
+// The stages run as tasks on the one runtime: trigger -> capture -> preprocess -> OCR -> normalize -> translate -> display.
+// Triggers come from hotkeys and the terminal through TriggerSender, ocr_stage() runs capture to normalize,
+// translate_stage() sends everything to the display stage(see display::spawn_display_dispatcher()).
+// Pipeline wires them up, the stage functions are public for running them separately
+
+/// A capture to read, with the OCR settings at the time it was triggered
+#[derive(Clone, Debug)]
+pub struct Trigger {
+	pub source: OcrSource,
+	pub ocr_config: OcrConfig,
+}
+
+/// Queue in front of the OCR stage, triggering never blocks
+#[derive(Clone)]
+pub struct TriggerSender {
+	channel: mpsc::Sender<Trigger>,
+	/// Accepted triggers whose OCR result is not delivered yet, shared with the OCR stage
+	pending: Arc<AtomicUsize>,
+	workers: usize,
+	display_channel: DisplaySender,
+}
+
+pub struct TriggerReceiver {
+	channel: mpsc::Receiver<Trigger>,
+	pending: Arc<AtomicUsize>,
+	workers: usize,
+}
+
+/// size.queue triggers can wait while size.workers are read, the queue needs room for at least one
+pub fn trigger_channel(size: PoolSize, display_channel: DisplaySender) -> (TriggerSender, TriggerReceiver) {
+	let (trigger_tx, trigger_rx) = mpsc::channel(size.queue.max(1));
+	let pending = Arc::new(AtomicUsize::new(0));
+	let workers = size.workers.max(1);
+	(
+		TriggerSender { channel: trigger_tx, pending: pending.clone(), workers, display_channel },
+		TriggerReceiver { channel: trigger_rx, pending, workers },
+	)
+}
+
+impl TriggerSender {
+	/// The queue state is shown when the trigger has to wait and when it is dropped
+	pub fn trigger(&self, source: OcrSource, ocr_config: OcrConfig) {
+		let ahead = self.pending.fetch_add(1, Ordering::Relaxed);
+		match self.channel.try_send(Trigger { source, ocr_config }) {
+			Ok(()) if ahead >= self.workers => {
+				let _ = self.display_channel.send(DisplayEvent::Notice(format!("OCR queued, {} captures ahead", ahead)));
+			},
+			Ok(()) => (),
+			Err(mpsc::error::TrySendError::Full(_)) => {
+				self.pending.fetch_sub(1, Ordering::Relaxed);
+				let _ = self.display_channel.send(DisplayEvent::Notice(format!("OCR busy with {} captures, trigger dropped", ahead)));
+			},
+			Err(mpsc::error::TrySendError::Closed(_)) => {
+				self.pending.fetch_sub(1, Ordering::Relaxed);
+			},
+		}
+	}
+}
+
+/// Capture, preprocess, OCR and normalize up to workers triggers at once, the results keep the trigger order.
+/// Sending waits while the translate stage is behind, the trigger queue then fills up and new triggers are dropped
+pub async fn ocr_stage(
+	triggers: TriggerReceiver,
+	capture_source: Arc<dyn CaptureSource>,
+	ocr_engine: Arc<dyn OcrEngine>,
+	output: mpsc::Sender<OcrResult>,
+	display_channel: DisplaySender,
+) {
+	let TriggerReceiver { channel: mut trigger_rx, pending, workers } = triggers;
+	// Language of the last capture it could be told for, next captures are read with its language pack first
+	let detected_language = Arc::new(Mutex::new(None));
+	let results = futures::stream::poll_fn(move |cx| trigger_rx.poll_recv(cx))
+		.map(|trigger| read_text(trigger, capture_source.clone(), ocr_engine.clone(), display_channel.clone(), detected_language.clone()))
+		.buffered(workers);
+	let mut results = std::pin::pin!(results);
+	while let Some(ocr_result) = results.next().await {
+		if let Some(ocr_result) = ocr_result {
+			if output.send(ocr_result).await.is_err() {
+				return;
+			}
+		}
+		pending.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// One trigger from capture to normalize, failures are reported on display_channel
+async fn read_text(
+	trigger: Trigger,
+	capture_source: Arc<dyn CaptureSource>,
+	ocr_engine: Arc<dyn OcrEngine>,
+	display_channel: DisplaySender,
+	detected_language: Arc<Mutex<Option<&'static Language>>>,
+) -> Option<OcrResult> {
+	let Trigger { source, mut ocr_config } = trigger;
+	let region = match &source {
+		OcrSource::Screen(screen_region) => Some(screen_region.clone()),
+		_ => None,
+	};
+	let result = async {
+		let _ = display_channel.send(DisplayEvent::Status(Status::Capturing));
+		let preprocessing = ocr_config.preprocessing.clone();
+		// Capturing and image processing block, they get a thread from the runtime's blocking pool
+		let (capture, png) = tokio::task::spawn_blocking(move || {
+			let capture = capture_source.capture(&source)?;
+			let png = ocr::preprocess(&capture.image, &preprocessing)?;
+			Ok::<_, OcrError>((capture, png))
+		}).await.map_err(OcrError::Worker)??;
+		let _ = display_channel.send(DisplayEvent::Status(Status::OcrRunning));
+		// Read with the pack of the language detected last, and again with the right one when the text turns out to be in
+		// another. Without a detected language yet no pack is sent, the server's default one is kept
+		let previous_language = ocr_config.detect_language.then(|| *detected_language.lock().unwrap()).flatten();
+		if ocr_config.detect_language {
+			ocr_config.language = previous_language.map(|language| language.ocr_language.to_string());
+		}
+		let retry_png = previous_language.is_some().then(|| png.clone());
+		let mut response = ocr_engine.recognize(png, &ocr_config).await?;
+		let detected = ocr_config.detect_language.then(|| language::detect_after(&response.extracted_text, previous_language)).flatten();
+		if let (Some(detected), Some(png)) = (detected, retry_png) {
+			if ocr_config.language.as_deref() != Some(detected.ocr_language) {
+				ocr_config.language = Some(detected.ocr_language.to_string());
+				response = ocr_engine.recognize(png, &ocr_config).await?;
+			}
+		}
+		if detected.is_some() {
+			*detected_language.lock().unwrap() = detected;
+		}
+		let mut ocr_result = ocr::normalize(response, &capture, ocr_config.preprocessing.scale);
+		ocr_result.region = region;
+		ocr_result.language = detected;
+		Ok::<_, OcrError>(ocr_result)
+	}.await;
+	match result {
+		Ok(ocr_result) => Some(ocr_result),
+		Err(e) => {
+			eprintln!("OCR failed: {}", e);
+			let _ = display_channel.send(DisplayEvent::Error(e.stage(), e.to_string()));
+			None
+		},
+	}
+}
+
+/// Translate every OCR result, settings.on_new_capture decides whether a new result cancels the running translation or waits for it.
+/// Returns once ocr_results is closed and the last translation is done
+pub async fn translate_stage(mut ocr_results: mpsc::Receiver<OcrResult>, capture_translator: CaptureTranslator, last_ocr_result: Arc<Mutex<Option<OcrResult>>>) {
+	let mut translation: Option<tokio::task::JoinHandle<()>> = None;
+	while let Some(mut ocr_result) = ocr_results.recv().await {
+		let on_new_capture = capture_translator.settings.read().unwrap().on_new_capture;
+		if on_new_capture == NewCapturePolicy::Replace {
+			// Results queued meanwhile would be cancelled right away, only the newest one is worth translating
+			while let Ok(newer_result) = ocr_results.try_recv() {
+				ocr_result = newer_result;
+			}
+		}
+		if let Some(previous) = translation.take() {
+			if on_new_capture == NewCapturePolicy::Replace && !previous.is_finished() {
+				// Dropping the task drops its request, closing the connection
+				previous.abort();
+				let _ = capture_translator.display_channel.send(DisplayEvent::Notice("Translation cancelled by a new capture".to_string()));
+			}
+			// The previous task has sent all its display events once it ended, the Clear of the next one comes after them
+			let _ = previous.await;
+		}
+		*last_ocr_result.lock().unwrap() = Some(ocr_result.clone());
+		translation = Some(tokio::spawn(capture_translator.clone().translate(ocr_result)));
+	}
+	if let Some(translation) = translation {
+		let _ = translation.await;
+	}
+}
+
+/// What translating an OCR result needs, cloned into each translation task
+#[derive(Clone)]
+pub struct CaptureTranslator {
+	pub settings: Arc<RwLock<Settings>>,
+	pub translator: Arc<dyn Translator>,
+	pub display_channel: DisplaySender,
+	/// Set with --in-place
+	pub inplace_window: Option<(std::sync::mpsc::Sender<WindowChannelMessage>, UpdateHandle)>,
+	/// Set with --hide-on-region-change
+	pub region_change_arm_tx: Option<std::sync::mpsc::Sender<()>>,
+}
+
+impl CaptureTranslator {
+	async fn translate(self, ocr_result: OcrResult) {
+		let display_tx = &self.display_channel;
+		let (translator_config, src_lang, target_lang, skip_target_language) = translation_settings(&self.settings);
+		let start_time = Instant::now();
+		let _ = display_tx.send(DisplayEvent::Clear);
+		let _ = display_tx.send(DisplayEvent::Source(ocr_result.text.clone()));
+		let detected_language = language::is_auto(&src_lang).then(|| ocr_result.language.or_else(|| language::detect(&ocr_result.text))).flatten();
+		if skip_target_language && detected_language.is_some_and(|detected| language::find(&target_lang) == Some(detected)) {
+			if let Some((inplace_display_tx, inplace_refresh)) = &self.inplace_window {
+				let _ = inplace_display_tx.send(WindowChannelMessage::Clear);
+				let _ = inplace_refresh.update_window();
+			}
+			if let Some(arm_tx) = &self.region_change_arm_tx {
+				let _ = arm_tx.send(());
+			}
+			// Nothing is recorded, the text is shown as it is
+			let _ = display_tx.send(DisplayEvent::Replace(ocr_result.text.trim().to_string()));
+			let _ = display_tx.send(DisplayEvent::Notice(format!("Already in {}, not translated", target_lang)));
+			return;
+		}
+		let src_lang = detected_language.map_or(src_lang, |detected| detected.name.to_string());
+		let _ = display_tx.send(DisplayEvent::Status(Status::Translating));
+		if let Some((inplace_display_tx, inplace_refresh)) = &self.inplace_window {
+			let _ = inplace_display_tx.send(WindowChannelMessage::Clear);
+			let _ = inplace_refresh.update_window();
+			if !ocr_result.blocks.is_empty() {
+				let (overlay_blocks, block_error) = translate_blocks(self.translator.as_ref(), &translator_config, &ocr_result.blocks, &src_lang, &target_lang).await;
+				if let Some(e) = block_error {
+					report_translate_error(display_tx, &e);
+				}
+				let result = overlay_blocks.iter().map(|block| block.text.as_str()).collect::<Vec<_>>().join("\n");
+				let _ = inplace_display_tx.send(WindowChannelMessage::Blocks(overlay_blocks));
+				let _ = inplace_refresh.update_window();
+				if let Some(arm_tx) = &self.region_change_arm_tx {
+					let _ = arm_tx.send(());
+				}
+				let _ = display_tx.send(DisplayEvent::Done(TranslationDone {
+					origin: Origin::Capture,
+					source: ocr_result.text,
+					translation: result,
+					target_lang,
+					model: translator_config.model,
+					region: ocr_result.region,
+					latency: start_time.elapsed(),
+				}));
+				return;
+			}
+		}
+		let translation_request = TranslateRequest::new(&ocr_result.text, &src_lang, &target_lang);
+		match self.translator.translate(
+			&translator_config,
+			&translation_request,
+			None,
+			Some(display_tx.clone())
+		).await {
+			Ok(result) => {
+				if let Some(arm_tx) = &self.region_change_arm_tx {
+					let _ = arm_tx.send(());
+				}
+				// Streaming only shows the tail of the text, settle on the complete translation
+				let _ = display_tx.send(DisplayEvent::Replace(result.trim().to_string()));
+				let _ = display_tx.send(DisplayEvent::Done(TranslationDone {
+					origin: Origin::Capture,
+					source: ocr_result.text,
+					translation: result,
+					target_lang,
+					model: translator_config.model,
+					region: ocr_result.region,
+					latency: start_time.elapsed(),
+				}));
+			},
+			Err(e) => report_translate_error(display_tx, &e),
+		};
+	}
+}
+
+/// Read for every translation so a profile switch applies to the next one
+fn translation_settings(settings: &RwLock<Settings>) -> (TranslatorConfig, String, String, bool) {
+	let settings = settings.read().unwrap();
+	(settings.translator.clone(), settings.src_lang.clone(), settings.target_lang.clone(), settings.skip_target_language)
+}
+
+/// Translate every OCR block concurrently, blocks that failed to translate keep their source text and the first error is returned
+async fn translate_blocks(translator: &dyn Translator, translator_config: &TranslatorConfig, blocks: &[TextBlock], src_lang: &str, target_lang: &str) -> (Vec<OverlayBlock>, Option<TranslateError>) {
+	let translations = futures::future::join_all(blocks.iter().map(|block| async move {
+		let translation_request = TranslateRequest::new(&block.text, src_lang, target_lang);
+		translator.translate(translator_config, &translation_request, None, None).await
+	})).await;
+	let mut first_error = None;
+	let overlay_blocks = blocks.iter().zip(translations).map(|(block, translation)| OverlayBlock {
+		text: match translation {
+			Ok(text) => text.trim().to_string(),
+			Err(e) => {
+				eprintln!("Block translation failed: {}", e);
+				first_error.get_or_insert(e);
+				block.text.clone()
+			}
+		},
+		rect: block.rect,
+		background: block.background,
+	}).collect();
+	(overlay_blocks, first_error)
+}
+
+/// Rate limiting gets its own status, anything else is shown as a short error
+fn report_translate_error(display_tx: &DisplaySender, e: &TranslateError) {
+	match e {
+		TranslateError::RateLimited(_) => {
+			let _ = display_tx.send(DisplayEvent::Status(Status::RateLimited));
+		},
+		e => {
+			let _ = display_tx.send(DisplayEvent::Error(Stage::Translation, e.to_string()));
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+	use std::time::Duration;
+	use tokio::sync::mpsc::UnboundedReceiver;
+	use crate::config::Layer;
+	use crate::ocr::Preprocessing;
+	use crate::retry::mock_server::{MockResponse, MockServer};
+	use crate::retry::RetryPolicy;
+	use crate::translator::OpenAiTranslator;
+	use crate::ocr::{HttpOcr, OcrResponse, ScreenCapture};
+	use futures::future::BoxFuture;
+	use std::collections::VecDeque;
+
+	fn ocr_config(endpoint: &str) -> OcrConfig {
+		OcrConfig {
+			endpoint: endpoint.to_string(),
+			fallback_endpoints: Vec::new(),
+			preprocessing: Preprocessing::default(),
+			retry: RetryPolicy::default(),
+			language: None,
+			detect_language: false,
+		}
+	}
+
+	/// Answers with the next text, recording the language pack of each request
+	#[derive(Default)]
+	struct ScriptedOcr {
+		texts: Mutex<VecDeque<&'static str>>,
+		languages: Mutex<Vec<Option<String>>>,
+	}
+
+	impl OcrEngine for ScriptedOcr {
+		fn recognize<'a>(&'a self, _png: Vec<u8>, ocr_config: &'a OcrConfig) -> BoxFuture<'a, Result<OcrResponse, OcrError>> {
+			self.languages.lock().unwrap().push(ocr_config.language.clone());
+			let text = self.texts.lock().unwrap().pop_front().unwrap_or_default();
+			Box::pin(async move { Ok(OcrResponse { extracted_text: text.to_string(), blocks: Vec::new() }) })
+		}
+	}
+
+	fn capture_translator(endpoint: &str, on_new_capture: NewCapturePolicy, display_channel: DisplaySender) -> CaptureTranslator {
+		let mut layer = Layer::defaults();
+		layer.screen_regions = Some(vec!["(0, 1, 0, 1)".to_string()]);
+		layer.translation.endpoint = Some(endpoint.to_string());
+		layer.translation.on_new_capture = Some(on_new_capture);
+		CaptureTranslator {
+			settings: Arc::new(RwLock::new(layer.resolve().unwrap())),
+			translator: Arc::new(OpenAiTranslator),
+			display_channel,
+			inplace_window: None,
+			region_change_arm_tx: None,
+		}
+	}
+
+	fn ocr_result(text: &str) -> OcrResult {
+		OcrResult {
+			text: text.to_string(),
+			blocks: Vec::new(),
+			region: None,
+			language: None,
+		}
+	}
+
+	fn finished_translations(display_rx: &mut UnboundedReceiver<DisplayEvent>) -> Vec<String> {
+		std::iter::from_fn(|| display_rx.try_recv().ok())
+			.filter_map(|event| match event {
+				DisplayEvent::Done(done) => Some(done.translation),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn ocr_results_keep_the_trigger_order() {
+		let slow = MockServer::start(vec![MockResponse::new(200, r#"{"extracted_text": "first"}"#).delay(Duration::from_millis(300))]);
+		let fast = MockServer::start(vec![MockResponse::new(200, r#"{"extracted_text": "second"}"#)]);
+		let image_path = std::env::temp_dir().join(format!("ocrtrans_pipeline_test_{}.png", std::process::id()));
+		image::RgbaImage::new(8, 8).save(&image_path).unwrap();
+		let (display_tx, mut display_rx) = mpsc::unbounded_channel();
+		let (trigger_tx, trigger_rx) = trigger_channel(PoolSize { workers: 2, queue: 4 }, display_tx.clone());
+		let (output_tx, mut output_rx) = mpsc::channel(10);
+		let stage = tokio::spawn(ocr_stage(trigger_rx, Arc::new(ScreenCapture), Arc::new(HttpOcr), output_tx, display_tx));
+		trigger_tx.trigger(OcrSource::File(image_path.clone()), ocr_config(&slow.url));
+		trigger_tx.trigger(OcrSource::File(PathBuf::from("missing.png")), ocr_config(&fast.url));
+		trigger_tx.trigger(OcrSource::File(image_path.clone()), ocr_config(&fast.url));
+		drop(trigger_tx);
+		stage.await.unwrap();
+		let _ = std::fs::remove_file(image_path);
+		let texts: Vec<String> = std::iter::from_fn(|| output_rx.try_recv().ok()).map(|ocr_result| ocr_result.text).collect();
+		assert_eq!(texts, ["first", "second"]);
+		assert!(std::iter::from_fn(|| display_rx.try_recv().ok()).any(|event| matches!(event, DisplayEvent::Error(Stage::Capture, _))));
+	}
+
+	#[tokio::test]
+	async fn new_result_cancels_the_running_translation() {
+		let server = MockServer::start(vec![
+			MockResponse::completion_stream("Old").delay(Duration::from_secs(5)),
+			MockResponse::completion_stream("New"),
+		]);
+		let (display_tx, mut display_rx) = mpsc::unbounded_channel();
+		let (ocr_tx, ocr_rx) = mpsc::channel(10);
+		let stage = tokio::spawn(translate_stage(ocr_rx, capture_translator(&server.url, NewCapturePolicy::Replace, display_tx), Arc::default()));
+		ocr_tx.send(ocr_result("古い")).await.unwrap();
+		while server.requests().is_empty() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		ocr_tx.send(ocr_result("新しい")).await.unwrap();
+		drop(ocr_tx);
+		tokio::time::timeout(Duration::from_secs(3), stage).await.unwrap().unwrap();
+		assert_eq!(finished_translations(&mut display_rx), ["New\n"]);
+	}
+
+	#[tokio::test]
+	async fn new_trigger_cancels_the_running_translation() {
+		let server = MockServer::start(vec![
+			MockResponse::completion_stream("Old").delay(Duration::from_secs(5)),
+			MockResponse::completion_stream("New"),
+		]);
+		let image_path = std::env::temp_dir().join(format!("ocrtrans_cancel_test_{}.png", std::process::id()));
+		image::RgbaImage::new(8, 8).save(&image_path).unwrap();
+		let ocr = ScriptedOcr { texts: Mutex::new(VecDeque::from(["古い", "新しい"])), ..Default::default() };
+		let (display_tx, mut display_rx) = mpsc::unbounded_channel();
+		let (trigger_tx, trigger_rx) = trigger_channel(PoolSize { workers: 1, queue: 4 }, display_tx.clone());
+		let (ocr_tx, ocr_rx) = mpsc::channel(10);
+		let ocr = tokio::spawn(ocr_stage(trigger_rx, Arc::new(ScreenCapture), Arc::new(ocr), ocr_tx, display_tx.clone()));
+		let translate = tokio::spawn(translate_stage(ocr_rx, capture_translator(&server.url, NewCapturePolicy::Replace, display_tx), Arc::default()));
+		trigger_tx.trigger(OcrSource::File(image_path.clone()), ocr_config("unused"));
+		while server.requests().is_empty() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		trigger_tx.trigger(OcrSource::File(image_path.clone()), ocr_config("unused"));
+		drop(trigger_tx);
+		ocr.await.unwrap();
+		tokio::time::timeout(Duration::from_secs(3), translate).await.unwrap().unwrap();
+		let _ = std::fs::remove_file(image_path);
+		assert_eq!(server.requests().len(), 2);
+		// Once the newest translation started, nothing of the cancelled one is displayed
+		let events: Vec<DisplayEvent> = std::iter::from_fn(|| display_rx.try_recv().ok()).collect();
+		assert!(events.iter().any(|event| matches!(event, DisplayEvent::Notice(notice) if notice == "Translation cancelled by a new capture")));
+		let last_clear = events.iter().rposition(|event| matches!(event, DisplayEvent::Clear)).unwrap();
+		let shown: Vec<String> = events[last_clear..].iter()
+			.filter_map(|event| match event {
+				DisplayEvent::Source(text) | DisplayEvent::Append(text) | DisplayEvent::Replace(text) => Some(text.clone()),
+				DisplayEvent::Done(done) => Some(done.translation.clone()),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(shown.first().map(String::as_str), Some("新しい"));
+		assert!(shown.iter().all(|text| !text.contains("Old") && text != "古い"));
+		assert_eq!(shown.last().map(String::as_str), Some("New\n"));
+	}
+
+	#[tokio::test]
+	async fn queued_results_are_all_translated_in_order() {
+		let server = MockServer::start(vec![
+			MockResponse::completion_stream("First").delay(Duration::from_millis(200)),
+			MockResponse::completion_stream("Second"),
+		]);
+		let (display_tx, mut display_rx) = mpsc::unbounded_channel();
+		let (ocr_tx, ocr_rx) = mpsc::channel(10);
+		let stage = tokio::spawn(translate_stage(ocr_rx, capture_translator(&server.url, NewCapturePolicy::Queue, display_tx), Arc::default()));
+		ocr_tx.send(ocr_result("一")).await.unwrap();
+		ocr_tx.send(ocr_result("二")).await.unwrap();
+		drop(ocr_tx);
+		stage.await.unwrap();
+		assert_eq!(finished_translations(&mut display_rx), ["First\n", "Second\n"]);
+	}
+
+	#[tokio::test]
+	async fn switches_the_ocr_language_pack() {
+		let image_path = std::env::temp_dir().join(format!("ocrtrans_language_test_{}.png", std::process::id()));
+		image::RgbaImage::new(8, 8).save(&image_path).unwrap();
+		let ocr_engine = Arc::new(ScriptedOcr::default());
+		ocr_engine.texts.lock().unwrap().extend([
+			"今日はいい天気ですね",
+			"東京都",
+			"안녕하세요", "안녕하세요, 반갑습니다",
+			"Where is the sword?", "Where is the sword?",
+		]);
+		let (display_tx, _display_rx) = mpsc::unbounded_channel();
+		let (trigger_tx, trigger_rx) = trigger_channel(PoolSize { workers: 1, queue: 4 }, display_tx.clone());
+		let (output_tx, mut output_rx) = mpsc::channel(10);
+		let stage = tokio::spawn(ocr_stage(trigger_rx, Arc::new(ScreenCapture), ocr_engine.clone(), output_tx, display_tx));
+		let ocr_config = OcrConfig { detect_language: true, ..ocr_config("") };
+		for _ in 0..4 {
+			trigger_tx.trigger(OcrSource::File(image_path.clone()), ocr_config.clone());
+		}
+		drop(trigger_tx);
+		stage.await.unwrap();
+		let _ = std::fs::remove_file(image_path);
+		let detected: Vec<_> = std::iter::from_fn(|| output_rx.try_recv().ok()).map(|ocr_result| ocr_result.language.map(|language| language.name)).collect();
+		assert_eq!(detected, [Some("Japanese"), Some("Japanese"), Some("Korean"), Some("English")]);
+		// Read once with the server's default pack, kanji only text stays Japanese, the others are read again
+		let languages = ocr_engine.languages.lock().unwrap().clone();
+		assert_eq!(languages, [None, Some("jpn"), Some("jpn"), Some("kor"), Some("kor"), Some("eng")].map(|language| language.map(str::to_string)));
+	}
+}
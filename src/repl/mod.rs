@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use ocrtrans::language;
+
+/// Terminal line editing history, kept in the working directory
+pub const HISTORY_FILE: &str = ".ocrtrans_history";
+
+pub const HELP: &str = "\
+Anything not starting with / is translated, start it with // to translate a line beginning with /
+/region [N|next]           list the screen regions, select one(counting from 1) or the next one
+/lang SRC TARGET           set the languages, names or codes like ja en, SRC auto detects it
+/model [NAME]              show or set the translation model
+/retry                     translate the last terminal input again, or the last capture if there is none
+/context clear             forget the last capture and input, clear the overlay
+/history [N]               show the last N translations, 10 by default, numbered from the latest
+/retranslate [N] [model=NAME] [prompt=NAME]
+                           translate history entry N(1 by default) again, with another model or prompt preset
+/glossary [add TERM = TRANSLATION]
+                           list the glossary or add a term to it
+/ocr FILE                  OCR an image file and translate it
+/help                      show this help
+/quit                      exit";
+
+/// A line typed in the terminal
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+	Translate(String),
+	Region(Option<RegionSelection>),
+	Lang(String, String),
+	Model(Option<String>),
+	Retry,
+	ContextClear,
+	History(usize),
+	/// History entry counting back from the latest one at 0, with a model and prompt preset for this translation only
+	Retranslate(usize, Option<String>, Option<String>),
+	Glossary,
+	GlossaryAdd(String, String),
+	Ocr(PathBuf),
+	Help,
+	Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionSelection {
+	/// Starts at 0
+	Index(usize),
+	Next,
+}
+
+/// Lines without a leading '/' are text to translate
+pub fn parse_command(line: &str) -> Result<Command> {
+	let Some(command_line) = line.strip_prefix('/') else {
+		return Ok(Command::Translate(line.to_string()));
+	};
+	if command_line.starts_with('/') {
+		return Ok(Command::Translate(command_line.to_string()));
+	}
+	let (name, rest) = command_line.split_once(char::is_whitespace).unwrap_or((command_line, ""));
+	let rest = rest.trim();
+	let arguments: Vec<&str> = rest.split_whitespace().collect();
+	let usage = |usage: &str| anyhow!("Usage: {}", usage);
+	Ok(match (name.to_ascii_lowercase().as_str(), arguments.as_slice()) {
+		("region", []) => Command::Region(None),
+		("region", ["next"]) => Command::Region(Some(RegionSelection::Next)),
+		("region", [index]) => match index.parse::<usize>() {
+			Ok(index) if index >= 1 => Command::Region(Some(RegionSelection::Index(index - 1))),
+			_ => return Err(usage("/region [N|next], N counts from 1")),
+		},
+		("region", _) => return Err(usage("/region [N|next]")),
+		("lang", [src_lang, target_lang]) => Command::Lang(language_name(src_lang), language_name(target_lang)),
+		("lang", _) => return Err(usage("/lang SRC TARGET, ex. /lang ja en")),
+		("model", []) => Command::Model(None),
+		("model", [model]) => Command::Model(Some(model.to_string())),
+		("model", _) => return Err(usage("/model [NAME]")),
+		("retry", []) => Command::Retry,
+		("context", ["clear"]) => Command::ContextClear,
+		("context", _) => return Err(usage("/context clear")),
+		("history", []) => Command::History(10),
+		("history", [count]) => Command::History(count.parse().map_err(|_| usage("/history [N]"))?),
+		("retranslate", arguments) => parse_retranslate(arguments).ok_or_else(|| usage("/retranslate [N] [model=NAME] [prompt=NAME], N counts from 1"))?,
+		("glossary", []) => Command::Glossary,
+		("glossary", ["add", ..]) => match parse_glossary_entry(rest[3..].trim()) {
+			Some((term, translation)) => Command::GlossaryAdd(term, translation),
+			None => return Err(usage("/glossary add TERM = TRANSLATION, quote the term if it has a '=' in it")),
+		},
+		("glossary", _) => return Err(usage("/glossary [add TERM = TRANSLATION]")),
+		("ocr", [_, ..]) => Command::Ocr(PathBuf::from(rest)),
+		("ocr", []) => return Err(usage("/ocr FILE")),
+		("help" | "?", _) => Command::Help,
+		("quit" | "exit" | "q", []) => Command::Quit,
+		_ => return Err(anyhow!("Unknown command /{}, type /help for the list", name)),
+	})
+}
+
+/// TERM = TRANSLATION, both sides can be quoted("Mr. = Mister" = ...) and are trimmed otherwise
+fn parse_glossary_entry(entry: &str) -> Option<(String, String)> {
+	let (term, translation) = match entry.strip_prefix('"') {
+		Some(quoted) => {
+			let (term, rest) = quoted.split_once('"')?;
+			(term, rest.trim_start().strip_prefix('=')?)
+		},
+		None => entry.split_once('=').map(|(term, translation)| (term.trim(), translation))?,
+	};
+	let translation = translation.trim();
+	let translation = translation.strip_prefix('"').and_then(|unquoted| unquoted.strip_suffix('"')).unwrap_or(translation);
+	(!term.trim().is_empty() && !translation.trim().is_empty()).then(|| (term.to_string(), translation.to_string()))
+}
+
+fn parse_retranslate(arguments: &[&str]) -> Option<Command> {
+	let (entry, options) = match arguments.split_first() {
+		Some((entry, options)) if !entry.contains('=') => (entry.parse::<usize>().ok().filter(|entry| *entry >= 1)?, options),
+		_ => (1, arguments),
+	};
+	let (mut model, mut prompt) = (None, None);
+	for option in options {
+		match option.split_once('=')? {
+			("model", value) if !value.is_empty() => model = Some(value.to_string()),
+			("prompt", value) if !value.is_empty() => prompt = Some(value.to_string()),
+			_ => return None,
+		}
+	}
+	Some(Command::Retranslate(entry - 1, model, prompt))
+}
+
+/// The prompt uses language names, known codes(see language::find()) are expanded and anything else is kept as typed
+fn language_name(language: &str) -> String {
+	language::find(language).map_or(language, |known| known.name).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(line: &str) -> Command {
+		parse_command(line).unwrap()
+	}
+
+	#[test]
+	fn text_and_slash_escape() {
+		assert_eq!(parse("こんにちは"), Command::Translate("こんにちは".to_string()));
+		assert_eq!(parse("//help me"), Command::Translate("/help me".to_string()));
+		assert_eq!(parse("/HELP"), Command::Help);
+		assert_eq!(parse("/lang ja en"), Command::Lang("Japanese".to_string(), "English".to_string()));
+		assert_eq!(parse("/lang auto Klingon"), Command::Lang("auto".to_string(), "Klingon".to_string()));
+		assert_eq!(parse("/region 2"), Command::Region(Some(RegionSelection::Index(1))));
+	}
+
+	#[test]
+	fn glossary_entries() {
+		assert_eq!(parse("/glossary add 勇者 = Hero"), Command::GlossaryAdd("勇者".to_string(), "Hero".to_string()));
+		assert_eq!(parse("/glossary add \"1 = 1\" = \"One is one \""), Command::GlossaryAdd("1 = 1".to_string(), "One is one ".to_string()));
+		assert_eq!(parse("/glossary add \"魔王 \"=Demon King"), Command::GlossaryAdd("魔王 ".to_string(), "Demon King".to_string()));
+		for invalid in ["/glossary add 勇者", "/glossary add = Hero", "/glossary add \"勇者 = Hero", "/glossary add \"\" = Hero"] {
+			assert!(parse_command(invalid).is_err(), "{}", invalid);
+		}
+	}
+
+	#[test]
+	fn retranslate_options() {
+		assert_eq!(parse("/retranslate"), Command::Retranslate(0, None, None));
+		assert_eq!(parse("/retranslate 3"), Command::Retranslate(2, None, None));
+		assert_eq!(parse("/retranslate model=gpt-4o-mini"), Command::Retranslate(0, Some("gpt-4o-mini".to_string()), None));
+		assert_eq!(parse("/retranslate 2 prompt=casual model=m"), Command::Retranslate(1, Some("m".to_string()), Some("casual".to_string())));
+		for invalid in ["/retranslate 0", "/retranslate x", "/retranslate model=", "/retranslate 1 temperature=2"] {
+			assert!(parse_command(invalid).is_err(), "{}", invalid);
+		}
+	}
+
+	#[test]
+	fn unknown_commands_and_usage() {
+		assert_eq!(parse_command("/frobnicate").unwrap_err().to_string(), "Unknown command /frobnicate, type /help for the list");
+		assert_eq!(parse_command("/lang ja").unwrap_err().to_string(), "Usage: /lang SRC TARGET, ex. /lang ja en");
+		assert!(parse_command("/history many").is_err());
+		assert!(parse_command("/ocr").is_err());
+	}
+}
@@ -0,0 +1,115 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Canned HTTP response, sent after delay
+pub(crate) struct MockResponse {
+	status: u16,
+	headers: Vec<(String, String)>,
+	body: String,
+	delay: Duration,
+}
+
+impl MockResponse {
+	pub fn new(status: u16, body: &str) -> Self {
+		Self {
+			status,
+			headers: Vec::new(),
+			body: body.to_string(),
+			delay: Duration::ZERO,
+		}
+	}
+
+	pub fn header(mut self, name: &str, value: &str) -> Self {
+		self.headers.push((name.to_string(), value.to_string()));
+		self
+	}
+
+	pub fn delay(mut self, delay: Duration) -> Self {
+		self.delay = delay;
+		self
+	}
+
+	/// Streamed chat completion answering text
+	pub fn completion_stream(text: &str) -> Self {
+		let chunk = serde_json::json!({
+			"id": "chatcmpl-mock",
+			"object": "chat.completion.chunk",
+			"created": 0,
+			"model": "mock",
+			"choices": [{"index": 0, "finish_reason": null, "delta": {"role": "assistant", "content": text}}],
+		});
+		Self::new(200, &format!("data: {}\n\ndata: [DONE]\n\n", chunk)).header("Content-Type", "text/event-stream")
+	}
+}
+
+/// HTTP server on a free local port answering each connection with the next response, in accept order
+pub(crate) struct MockServer {
+	pub url: String,
+	requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+	pub fn start(responses: Vec<MockResponse>) -> Self {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		let requests = Arc::new(Mutex::new(Vec::new()));
+		let received = requests.clone();
+		std::thread::spawn(move || {
+			for response in responses {
+				let Ok((mut stream, _)) = listener.accept() else {
+					return;
+				};
+				// A delayed response doesn't hold back the next connections
+				let received = received.clone();
+				std::thread::spawn(move || {
+					let head = read_request(&mut stream);
+					received.lock().unwrap().push(head);
+					std::thread::sleep(response.delay);
+					let mut reply = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+					for (name, value) in &response.headers {
+						reply.push_str(&format!("{}: {}\r\n", name, value));
+					}
+					reply.push_str("\r\n");
+					reply.push_str(&response.body);
+					let _ = stream.write_all(reply.as_bytes());
+				});
+			}
+		});
+		Self { url, requests }
+	}
+
+	/// Request line and headers of every request received so far
+	pub fn requests(&self) -> Vec<String> {
+		self.requests.lock().unwrap().clone()
+	}
+}
+
+/// Read the whole request so closing the connection doesn't reset it, returns the head
+fn read_request(stream: &mut TcpStream) -> String {
+	let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+	let mut data = Vec::new();
+	let mut buffer = [0u8; 4096];
+	loop {
+		if let Some(head_end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+			let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+			let lowercase_head = head.to_ascii_lowercase();
+			let content_length = lowercase_head.lines()
+				.find_map(|line| line.strip_prefix("content-length:"))
+				.and_then(|length| length.trim().parse::<usize>().ok());
+			let complete = match content_length {
+				Some(length) => data.len() >= head_end + 4 + length,
+				None if lowercase_head.contains("transfer-encoding: chunked") => data.ends_with(b"0\r\n\r\n"),
+				None => true,
+			};
+			if complete {
+				return head;
+			}
+		}
+		match stream.read(&mut buffer) {
+			Ok(0) | Err(_) => return String::from_utf8_lossy(&data).to_string(),
+			Ok(read) => data.extend_from_slice(&buffer[..read]),
+		}
+	}
+}
@@ -0,0 +1,106 @@
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+#[cfg(test)]
+pub(crate) mod mock_server;
+
+/// Timeouts and back-off of the OCR and translation requests, applied to each endpoint separately
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+	pub connect_timeout: Duration,
+	/// Longest wait for the next bytes of a response, for the OCR server it covers the whole request
+	pub read_timeout: Duration,
+	/// Attempts after the first one before moving on to the next endpoint
+	pub retries: u32,
+	/// Delay before the first retry, doubled for each following one
+	pub backoff: Duration,
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			connect_timeout: Duration::from_secs(5),
+			read_timeout: Duration::from_secs(30),
+			retries: 2,
+			backoff: Duration::from_millis(500),
+			max_backoff: Duration::from_secs(8),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Delay before retry number retry(starting at 1) of the same endpoint, the server's Retry-After replaces the back-off.
+	/// None when the retries are used up or the server asks to wait longer than max_backoff, the next endpoint should be tried instead
+	pub fn retry_delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+		if retry == 0 || retry > self.retries {
+			return None;
+		}
+		match retry_after {
+			Some(retry_after) if retry_after > self.max_backoff => None,
+			Some(retry_after) => Some(retry_after),
+			None => Some(self.backoff.saturating_mul(2u32.saturating_pow(retry - 1)).min(self.max_backoff)),
+		}
+	}
+}
+
+/// 429 and 5xx may succeed later, any other error status would fail the same way again
+pub fn is_retryable(status: StatusCode) -> bool {
+	status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// "retry-after-ms"(sent by OpenAI) or "Retry-After" in seconds, HTTP dates are not supported
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+	let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok().filter(|value| *value >= 0.0 && value.is_finite());
+	header("retry-after-ms").map(|ms| Duration::from_secs_f64(ms / 1000.0))
+		.or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reqwest::header::HeaderValue;
+
+	fn policy() -> RetryPolicy {
+		RetryPolicy {
+			retries: 4,
+			backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_millis(500),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn backoff_doubles_up_to_max() {
+		let delays: Vec<_> = (1..=5).map(|retry| policy().retry_delay(retry, None)).collect();
+		assert_eq!(delays, [100, 200, 400, 500].map(|ms| Some(Duration::from_millis(ms))).into_iter().chain([None]).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn retry_after_replaces_backoff() {
+		assert_eq!(policy().retry_delay(1, Some(Duration::from_millis(300))), Some(Duration::from_millis(300)));
+		assert_eq!(policy().retry_delay(1, Some(Duration::from_secs(60))), None);
+	}
+
+	#[test]
+	fn parses_retry_after_headers() {
+		let mut headers = HeaderMap::new();
+		assert_eq!(retry_after(&headers), None);
+		headers.insert("retry-after", HeaderValue::from_static("2"));
+		assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+		headers.insert("retry-after-ms", HeaderValue::from_static("150"));
+		assert_eq!(retry_after(&headers), Some(Duration::from_millis(150)));
+		headers.clear();
+		headers.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+		assert_eq!(retry_after(&headers), None);
+	}
+
+	#[test]
+	fn only_rate_limits_and_server_errors_are_retried() {
+		assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+		assert!(is_retryable(StatusCode::BAD_GATEWAY));
+		assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+		assert!(!is_retryable(StatusCode::NOT_FOUND));
+	}
+}
@@ -0,0 +1,72 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use crate::pipeline::Pipeline;
+use super::{is_local_authority, read_request, stream_events, write_response, Response};
+
+// Caption page for OBS browser sources, showing the current translation over a transparent background:
+//   GET /        the page
+//   GET /events  display events over WebSocket, like the control API's
+// No token so it works as a plain browser source URL, but the events carry every text shown: other web pages can
+// open WebSockets to localhost, so /events is only served to the page itself(or to clients that are not web pages)
+
+const PAGE: &str = include_str!("captions.html");
+
+/// Listen on localhost, returns the bound address once connections are accepted
+pub async fn start(port: u16, pipeline: Pipeline) -> std::io::Result<SocketAddr> {
+	let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+	let address = listener.local_addr()?;
+	tokio::spawn(async move {
+		loop {
+			match listener.accept().await {
+				Ok((stream, _)) => {
+					tokio::spawn(handle(stream, pipeline.clone(), address.port()));
+				},
+				Err(e) => {
+					eprintln!("Caption page connection failed: {}", e);
+					tokio::time::sleep(Duration::from_millis(100)).await;
+				},
+			}
+		}
+	});
+	Ok(address)
+}
+
+async fn handle(mut stream: TcpStream, pipeline: Pipeline, port: u16) {
+	let request = match read_request(&mut stream).await {
+		Ok(Some(request)) => request,
+		Ok(None) => return,
+		Err(response) => {
+			let _ = write_response(&mut stream, response).await;
+			return;
+		},
+	};
+	match (request.method.as_str(), request.path.as_str()) {
+		("GET", "/") => {
+			let _ = write_page(&mut stream).await;
+		},
+		("GET", "/events") if !request.header("origin").is_none_or(|origin| is_page_origin(origin, port)) => {
+			let _ = write_response(&mut stream, Response::error(403, "Only the caption page can receive the events")).await;
+		},
+		("GET", "/events") => stream_events(stream, &request, pipeline.subscribe()).await,
+		_ => {
+			let _ = write_response(&mut stream, Response::error(404, "Not found")).await;
+		},
+	}
+}
+
+/// http://127.0.0.1:PORT or http://localhost:PORT, where the page is served
+fn is_page_origin(origin: &str, port: u16) -> bool {
+	origin.strip_prefix("http://").is_some_and(|authority| is_local_authority(authority, port))
+}
+
+async fn write_page(stream: &mut TcpStream) -> std::io::Result<()> {
+	let head = format!(
+		"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		PAGE.len(),
+	);
+	stream.write_all(head.as_bytes()).await?;
+	stream.write_all(PAGE.as_bytes()).await?;
+	stream.shutdown().await
+}
@@ -0,0 +1,547 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use crate::config::ServerSettings;
+use crate::display::{DisplayEvent, Origin};
+use crate::ocr::{OcrSource, ScreenRegions};
+use crate::pipeline::Pipeline;
+
+mod captions;
+mod websocket;
+pub use captions::start as start_captions;
+
+// Control API, JSON over HTTP/1.1 on localhost. One request per connection:
+//   GET  /state      languages, model and screen regions
+//   POST /state      {"src_lang", "target_lang", "model", "region"}, every field optional, region counts from 1
+//   POST /capture    {"region": 2} or {"region": "(0, 1, 0.7, 1)"}, the selected region without a body
+//   POST /ocr        an encoded image(PNG, JPEG...) as the body
+//   POST /translate  {"text"}, answers with {"translation"} once it is done
+//   GET  /events     display events as {"type": ...} objects, over WebSocket when upgrading and Server-Sent Events otherwise
+// Captures and images are read and translated in the background, their results come through /events.
+// Every request needs the token, as "Authorization: Bearer TOKEN" or "?token=TOKEN". Web pages can reach localhost
+// too(simple requests and WebSockets need no CORS permission, DNS rebinding gets around the host), so requests
+// from them(with an Origin header) and for other hosts than 127.0.0.1 and localhost are rejected as well
+
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+/// Images are sent whole, a 4K PNG screenshot fits
+const MAX_BODY_SIZE: usize = 32 * 1024 * 1024;
+
+/// Language and model changes from POST /state, applied by the embedder(the CLI puts them in its overrides layer).
+/// An error is sent back to the client
+pub type SessionChangeHandler = Arc<dyn Fn(SessionChange) -> Result<()> + Send + Sync>;
+
+#[derive(Debug, Default)]
+pub struct SessionChange {
+	pub src_lang: Option<String>,
+	pub target_lang: Option<String>,
+	pub model: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StateChange {
+	src_lang: Option<String>,
+	target_lang: Option<String>,
+	model: Option<String>,
+	region: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CaptureRequest {
+	region: Option<RegionArgument>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RegionArgument {
+	/// Configured region, counting from 1
+	Index(usize),
+	/// Same format as --screen-region
+	Region(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TranslateTextRequest {
+	text: String,
+}
+
+/// Listen on localhost, returns the bound address and the token clients have to send once connections are accepted.
+/// The token is generated when server_settings has none
+pub async fn start(server_settings: &ServerSettings, pipeline: Pipeline, on_session_change: SessionChangeHandler) -> std::io::Result<(SocketAddr, String)> {
+	let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, server_settings.port)).await?;
+	let address = listener.local_addr()?;
+	let token = match &server_settings.token {
+		Some(token) => token.clone(),
+		None => random_token()?,
+	};
+	let server = Arc::new(ControlServer {
+		pipeline,
+		port: address.port(),
+		token: token.clone(),
+		on_session_change,
+	});
+	tokio::spawn(async move {
+		loop {
+			match listener.accept().await {
+				Ok((stream, _)) => {
+					tokio::spawn(server.clone().handle(stream));
+				},
+				Err(e) => {
+					// e.g. out of file descriptors, waiting a bit avoids spinning on it
+					eprintln!("Control API connection failed: {}", e);
+					tokio::time::sleep(Duration::from_millis(100)).await;
+				},
+			}
+		}
+	});
+	Ok((address, token))
+}
+
+/// 128 bits from the OS random source as 32 hex digits
+fn random_token() -> std::io::Result<String> {
+	let mut bytes = [0u8; 16];
+	getrandom::getrandom(&mut bytes).map_err(|e| std::io::Error::other(format!("Generating the token failed: {}", e)))?;
+	Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Takes as long whatever the first differing byte, so the token can't be guessed one byte at a time from the response times
+fn same_token(sent: &str, token: &str) -> bool {
+	sent.len() == token.len() && sent.bytes().zip(token.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+struct ControlServer {
+	pipeline: Pipeline,
+	/// Bound port, the one in the Host header
+	port: u16,
+	token: String,
+	on_session_change: SessionChangeHandler,
+}
+
+struct Request {
+	method: String,
+	path: String,
+	query: String,
+	/// Names in lowercase
+	headers: Vec<(String, String)>,
+	body: Vec<u8>,
+}
+
+impl Request {
+	fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+	}
+
+	fn query_parameter(&self, name: &str) -> Option<&str> {
+		self.query.split('&').find_map(|parameter| parameter.strip_prefix(name)?.strip_prefix('='))
+	}
+}
+
+struct Response {
+	status: u16,
+	body: serde_json::Value,
+}
+
+impl Response {
+	fn ok(body: serde_json::Value) -> Self {
+		Self { status: 200, body }
+	}
+
+	fn accepted() -> Self {
+		Self { status: 202, body: json!({ "status": "accepted" }) }
+	}
+
+	fn error(status: u16, error: impl std::fmt::Display) -> Self {
+		Self { status, body: json!({ "error": error.to_string() }) }
+	}
+}
+
+impl ControlServer {
+	async fn handle(self: Arc<Self>, mut stream: TcpStream) {
+		let request = match read_request(&mut stream).await {
+			Ok(Some(request)) => request,
+			Ok(None) => return,
+			Err(response) => {
+				let _ = write_response(&mut stream, response).await;
+				return;
+			},
+		};
+		if !request.header("host").is_some_and(|host| is_local_authority(host, self.port)) {
+			let _ = write_response(&mut stream, Response::error(403, "Unknown host, connect to 127.0.0.1 or localhost")).await;
+			return;
+		}
+		if request.header("origin").is_some() {
+			let _ = write_response(&mut stream, Response::error(403, "Requests from web pages are not allowed")).await;
+			return;
+		}
+		let bearer = request.header("authorization").and_then(|value| value.strip_prefix("Bearer "));
+		if !bearer.or(request.query_parameter("token")).is_some_and(|sent| same_token(sent, &self.token)) {
+			let _ = write_response(&mut stream, Response::error(401, "Missing or wrong token")).await;
+			return;
+		}
+		// Only read once the client is known, it can be large
+		let request = match read_body(&mut stream, request).await {
+			Ok(request) => request,
+			Err(response) => {
+				let _ = write_response(&mut stream, response).await;
+				return;
+			},
+		};
+		if request.path == "/events" && request.method == "GET" {
+			stream_events(stream, &request, self.pipeline.subscribe()).await;
+			return;
+		}
+		let response = self.respond(request).await;
+		let _ = write_response(&mut stream, response).await;
+	}
+
+	async fn respond(&self, request: Request) -> Response {
+		match (request.method.as_str(), request.path.as_str()) {
+			("GET", "/state") => self.state(),
+			("POST", "/state") => self.change_state(&request.body),
+			("POST", "/capture") => self.capture(&request.body),
+			("POST", "/ocr") => self.ocr(request.body),
+			("POST", "/translate") => self.translate(&request.body).await,
+			(_, "/state" | "/capture" | "/ocr" | "/translate" | "/events") => Response::error(405, "Method not allowed"),
+			_ => Response::error(404, "Not found"),
+		}
+	}
+
+	fn state(&self) -> Response {
+		let settings = self.pipeline.settings().read().unwrap();
+		Response::ok(json!({
+			"src_lang": settings.src_lang,
+			"target_lang": settings.target_lang,
+			"model": settings.translator.model,
+			"regions": settings.screen_regions.regions(),
+			"region": settings.screen_regions.selected() + 1,
+		}))
+	}
+
+	fn change_state(&self, body: &[u8]) -> Response {
+		let change: StateChange = match serde_json::from_slice(body) {
+			Ok(change) => change,
+			Err(e) => return Response::error(400, e),
+		};
+		if let Some(region) = change.region {
+			let select = match region.checked_sub(1) {
+				Some(index) => self.pipeline.settings().read().unwrap().screen_regions.select(index),
+				None => Err(anyhow::anyhow!("Screen regions count from 1")),
+			};
+			if let Err(e) = select {
+				return Response::error(400, e);
+			}
+		}
+		if change.src_lang.is_some() || change.target_lang.is_some() || change.model.is_some() {
+			let session_change = SessionChange {
+				src_lang: change.src_lang,
+				target_lang: change.target_lang,
+				model: change.model,
+			};
+			if let Err(e) = (self.on_session_change)(session_change) {
+				return Response::error(400, format!("{:#}", e));
+			}
+		}
+		self.state()
+	}
+
+	fn capture(&self, body: &[u8]) -> Response {
+		let capture_request: CaptureRequest = if body.is_empty() {
+			CaptureRequest::default()
+		} else {
+			match serde_json::from_slice(body) {
+				Ok(capture_request) => capture_request,
+				Err(e) => return Response::error(400, e),
+			}
+		};
+		let screen_region = match capture_request.region {
+			None => self.pipeline.settings().read().unwrap().screen_regions.current(),
+			Some(RegionArgument::Index(index)) => {
+				let settings = self.pipeline.settings().read().unwrap();
+				match index.checked_sub(1).and_then(|index| settings.screen_regions.regions().get(index)) {
+					Some(region) => region.clone(),
+					None => return Response::error(400, format!("There are {} screen regions, counting from 1", settings.screen_regions.regions().len())),
+				}
+			},
+			Some(RegionArgument::Region(region)) => match ScreenRegions::new(vec![region.clone()]) {
+				Ok(_) => region,
+				Err(e) => return Response::error(400, format!("{:#}", e)),
+			},
+		};
+		self.pipeline.trigger(OcrSource::Screen(screen_region));
+		Response::accepted()
+	}
+
+	fn ocr(&self, body: Vec<u8>) -> Response {
+		if body.is_empty() {
+			return Response::error(400, "Send the image as the request body");
+		}
+		self.pipeline.trigger(OcrSource::Image(body));
+		Response::accepted()
+	}
+
+	async fn translate(&self, body: &[u8]) -> Response {
+		let translate_request: TranslateTextRequest = match serde_json::from_slice(body) {
+			Ok(translate_request) => translate_request,
+			Err(e) => return Response::error(400, e),
+		};
+		match self.pipeline.translate_text(&translate_request.text, Origin::Api, None).await {
+			Ok(translation) => Response::ok(json!({ "translation": translation.trim() })),
+			Err(e) => Response::error(502, e),
+		}
+	}
+}
+
+/// "127.0.0.1:PORT" or "localhost:PORT", as in Host headers and Origin URLs
+fn is_local_authority(authority: &str, port: u16) -> bool {
+	["127.0.0.1", "localhost"].iter().any(|host| authority.eq_ignore_ascii_case(&format!("{}:{}", host, port)))
+}
+
+/// Keeps the connection until the client goes away
+async fn stream_events(mut stream: TcpStream, request: &Request, events: broadcast::Receiver<DisplayEvent>) {
+	if request.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) {
+		let Some(key) = request.header("sec-websocket-key") else {
+			let _ = write_response(&mut stream, Response::error(400, "Missing Sec-WebSocket-Key")).await;
+			return;
+		};
+		let handshake = format!(
+			"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+			websocket::accept_key(key),
+		);
+		if stream.write_all(handshake.as_bytes()).await.is_ok() {
+			websocket::forward_events(stream, events).await;
+		}
+	} else {
+		send_server_sent_events(stream, events).await;
+	}
+}
+
+async fn send_server_sent_events(mut stream: TcpStream, mut events: broadcast::Receiver<DisplayEvent>) {
+	let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+	if stream.write_all(head.as_bytes()).await.is_err() {
+		return;
+	}
+	loop {
+		let event = match events.recv().await {
+			Ok(event) => event,
+			Err(broadcast::error::RecvError::Lagged(_)) => continue,
+			Err(broadcast::error::RecvError::Closed) => return,
+		};
+		// A closed connection is only noticed when writing to it
+		if stream.write_all(format!("data: {}\n\n", event.to_json()).as_bytes()).await.is_err() {
+			return;
+		}
+	}
+}
+
+/// The request line and headers, the body is only what came with them until read_body().
+/// None when the client closed the connection without sending a request
+async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>, Response> {
+	let mut data = Vec::new();
+	let mut buffer = [0u8; 8192];
+	loop {
+		let mut headers = [httparse::EMPTY_HEADER; 32];
+		let mut parsed = httparse::Request::new(&mut headers);
+		match parsed.parse(&data) {
+			Ok(httparse::Status::Complete(head_length)) => {
+				let (path, query) = parsed.path.unwrap_or("/").split_once('?').unwrap_or((parsed.path.unwrap_or("/"), ""));
+				let request = Request {
+					method: parsed.method.unwrap_or_default().to_string(),
+					path: path.to_string(),
+					query: query.to_string(),
+					headers: parsed.headers.iter()
+						.map(|header| (header.name.to_ascii_lowercase(), String::from_utf8_lossy(header.value).to_string()))
+						.collect(),
+					body: data[head_length..].to_vec(),
+				};
+				return Ok(Some(request));
+			},
+			Ok(httparse::Status::Partial) if data.len() > MAX_HEAD_SIZE => return Err(Response::error(431, "Request head too large")),
+			Ok(httparse::Status::Partial) => (),
+			Err(e) => return Err(Response::error(400, e)),
+		}
+		match stream.read(&mut buffer).await {
+			Ok(0) if data.is_empty() => return Ok(None),
+			Ok(0) => return Err(Response::error(400, "Incomplete request")),
+			Ok(read) => data.extend_from_slice(&buffer[..read]),
+			Err(_) => return Ok(None),
+		}
+	}
+}
+
+async fn read_body(stream: &mut TcpStream, mut request: Request) -> Result<Request, Response> {
+	if request.header("transfer-encoding").is_some() {
+		return Err(Response::error(411, "Send a Content-Length, chunked bodies are not supported"));
+	}
+	let content_length = match request.header("content-length") {
+		Some(length) => length.trim().parse::<usize>().map_err(|_| Response::error(400, "Invalid Content-Length"))?,
+		None => 0,
+	};
+	if content_length > MAX_BODY_SIZE {
+		return Err(Response::error(413, format!("Request body larger than {} bytes", MAX_BODY_SIZE)));
+	}
+	let received = request.body.len().min(content_length);
+	request.body.resize(content_length, 0);
+	stream.read_exact(&mut request.body[received..]).await.map_err(|_| Response::error(400, "Incomplete request body"))?;
+	Ok(request)
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> std::io::Result<()> {
+	let body = response.body.to_string();
+	let head = format!(
+		"HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		response.status,
+		reason_phrase(response.status),
+		body.len(),
+	);
+	stream.write_all(head.as_bytes()).await?;
+	stream.write_all(body.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+	match status {
+		200 => "OK",
+		202 => "Accepted",
+		400 => "Bad Request",
+		401 => "Unauthorized",
+		403 => "Forbidden",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		411 => "Length Required",
+		413 => "Payload Too Large",
+		431 => "Request Header Fields Too Large",
+		502 => "Bad Gateway",
+		_ => "",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::future::BoxFuture;
+	use crate::config::Layer;
+	use crate::translator::{TranslateError, TranslateRequest, Translator, TranslatorConfig};
+	use crate::display::DisplaySender;
+
+	struct Uppercase;
+
+	impl Translator for Uppercase {
+		fn translate<'a>(
+			&'a self,
+			_config: &'a TranslatorConfig,
+			request: &'a TranslateRequest,
+			_steaming_output_async_channel: Option<tokio::sync::mpsc::Sender<String>>,
+			_steaming_output_sync_channel: Option<DisplaySender>,
+		) -> BoxFuture<'a, Result<String, TranslateError>> {
+			Box::pin(async move { Ok(request.content.to_uppercase()) })
+		}
+	}
+
+	/// (URL, token)
+	async fn start_server(token: Option<&str>) -> (String, String) {
+		let mut layer = Layer::defaults();
+		layer.screen_regions = Some(vec!["(0, 1, 0, 1)".to_string(), "(0, 1, 0.5, 1)".to_string()]);
+		let pipeline = Pipeline::builder(layer.resolve().unwrap()).translator(Uppercase).build();
+		let server_settings = ServerSettings {
+			port: 0,
+			token: token.map(str::to_string),
+		};
+		let (address, token) = start(&server_settings, pipeline, Arc::new(|_| Ok(()))).await.unwrap();
+		(format!("http://{}", address), token)
+	}
+
+	#[test]
+	fn websocket_accept_key() {
+		// Example from RFC 6455
+		assert_eq!(websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+	}
+
+	#[tokio::test]
+	async fn translates_and_streams_the_result() {
+		let (url, token) = start_server(None).await;
+		let client = reqwest::Client::new();
+		let mut events = client.get(format!("{}/events", url)).bearer_auth(&token).send().await.unwrap();
+		assert_eq!(events.headers()["content-type"], "text/event-stream");
+		let response: serde_json::Value = client.post(format!("{}/translate", url)).bearer_auth(&token).body(r#"{"text": "hello"}"#).send().await.unwrap().json().await.unwrap();
+		assert_eq!(response["translation"], "HELLO");
+		let mut received = String::new();
+		while !received.contains(r#""type":"done""#) {
+			let chunk = tokio::time::timeout(Duration::from_secs(3), events.chunk()).await.unwrap().unwrap().unwrap();
+			received.push_str(&String::from_utf8_lossy(&chunk));
+		}
+		assert!(received.contains(r#""origin":"api""#) && received.contains(r#""translation":"HELLO""#));
+	}
+
+	#[tokio::test]
+	async fn selects_regions_and_checks_the_token() {
+		let (url, _) = start_server(Some("secret")).await;
+		let client = reqwest::Client::new();
+		let unauthorized = client.get(format!("{}/state", url)).send().await.unwrap();
+		assert_eq!(unauthorized.status(), 401);
+		let state: serde_json::Value = client.post(format!("{}/state?token=secret", url)).body(r#"{"region": 2}"#).send().await.unwrap().json().await.unwrap();
+		assert_eq!(state["region"], 2);
+		let invalid = client.post(format!("{}/state", url)).bearer_auth("secret").body(r#"{"region": 3}"#).send().await.unwrap();
+		assert_eq!(invalid.status(), 400);
+		let missing = client.get(format!("{}/nothing", url)).bearer_auth("secret").send().await.unwrap();
+		assert_eq!(missing.status(), 404);
+	}
+
+	#[tokio::test]
+	async fn caption_events_only_go_to_the_caption_page() {
+		let mut layer = Layer::defaults();
+		layer.screen_regions = Some(vec!["(0, 1, 0, 1)".to_string()]);
+		let pipeline = Pipeline::builder(layer.resolve().unwrap()).translator(Uppercase).build();
+		let address = start_captions(0, pipeline).await.unwrap();
+		let client = reqwest::Client::new();
+		let url = format!("http://{}/events", address);
+		let from_page = client.get(&url).header("Origin", format!("http://localhost:{}", address.port())).send().await.unwrap();
+		assert_eq!(from_page.headers()["content-type"], "text/event-stream");
+		let from_other_page = client.get(&url).header("Origin", "https://example.com").send().await.unwrap();
+		assert_eq!(from_other_page.status(), 403);
+		let other_port = client.get(&url).header("Origin", format!("http://127.0.0.1:{}", address.port() + 1)).send().await.unwrap();
+		assert_eq!(other_port.status(), 403);
+	}
+
+	#[tokio::test]
+	async fn rejects_web_pages_and_other_hosts() {
+		let (url, token) = start_server(None).await;
+		assert_eq!(token.len(), 32);
+		let client = reqwest::Client::new();
+		let unauthorized = client.post(format!("{}/capture", url)).send().await.unwrap();
+		assert_eq!(unauthorized.status(), 401);
+		// A cross-site form post or WebSocket, even with the token
+		let from_page = client.post(format!("{}/capture", url)).bearer_auth(&token).header("Origin", "https://example.com").send().await.unwrap();
+		assert_eq!(from_page.status(), 403);
+		let events_from_page = client.get(format!("{}/events", url)).bearer_auth(&token).header("Origin", "null").send().await.unwrap();
+		assert_eq!(events_from_page.status(), 403);
+		// DNS rebinding, the page's host name points to 127.0.0.1
+		let port = url.rsplit(':').next().unwrap();
+		let rebound = client.get(format!("{}/state", url)).bearer_auth(&token).header("Host", format!("attacker.example:{}", port)).send().await.unwrap();
+		assert_eq!(rebound.status(), 403);
+		let local = client.get(format!("{}/state", url.replace("127.0.0.1", "localhost"))).bearer_auth(&token).send().await.unwrap();
+		assert_eq!(local.status(), 200);
+	}
+
+	#[tokio::test]
+	async fn checks_the_token_before_reading_the_body() {
+		let (url, _) = start_server(Some("secret")).await;
+		let address = url.trim_start_matches("http://");
+		let mut stream = TcpStream::connect(address).await.unwrap();
+		// Announces a large image but never sends it
+		let head = format!("POST /ocr HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer secreT\r\nContent-Length: {}\r\n\r\n", address, MAX_BODY_SIZE);
+		stream.write_all(head.as_bytes()).await.unwrap();
+		let mut response = String::new();
+		tokio::time::timeout(Duration::from_secs(3), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+		assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+		assert!(same_token("secret", "secret"));
+		assert!(!same_token("secreT", "secret") && !same_token("secret2", "secret") && !same_token("", "secret"));
+	}
+}
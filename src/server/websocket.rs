@@ -0,0 +1,107 @@
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc};
+use crate::display::DisplayEvent;
+
+// Just enough of RFC 6455 for pushing events: unfragmented server frames, client frames only read for ping and close
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+/// Clients only send control frames here, anything larger is not a client of this API
+const MAX_CLIENT_FRAME: u64 = 64 * 1024;
+
+/// Sec-WebSocket-Accept answering the client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+	let mut hasher = Sha1::new();
+	hasher.update(key.trim().as_bytes());
+	hasher.update(HANDSHAKE_GUID.as_bytes());
+	base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Send each event as a JSON text frame until the client closes the connection, the handshake is already done
+pub async fn forward_events(stream: TcpStream, mut events: broadcast::Receiver<DisplayEvent>) {
+	let (mut reader, mut writer) = stream.into_split();
+	let (control_tx, mut control_rx) = mpsc::channel(4);
+	let reader_task = tokio::spawn(async move {
+		while let Ok((opcode, payload)) = read_frame(&mut reader).await {
+			let reply = match opcode {
+				OPCODE_PING => (OPCODE_PONG, payload),
+				// Echoing the close frame(and its status code) completes the closing handshake
+				OPCODE_CLOSE => (OPCODE_CLOSE, payload),
+				_ => continue,
+			};
+			if control_tx.send(reply).await.is_err() || opcode == OPCODE_CLOSE {
+				return;
+			}
+		}
+	});
+	loop {
+		let (opcode, payload) = tokio::select! {
+			event = events.recv() => match event {
+				Ok(event) => (OPCODE_TEXT, event.to_json().to_string().into_bytes()),
+				Err(broadcast::error::RecvError::Lagged(_)) => continue,
+				Err(broadcast::error::RecvError::Closed) => (OPCODE_CLOSE, Vec::new()),
+			},
+			control = control_rx.recv() => match control {
+				Some(frame) => frame,
+				// Connection lost
+				None => break,
+			},
+		};
+		if write_frame(&mut writer, opcode, &payload).await.is_err() || opcode == OPCODE_CLOSE {
+			break;
+		}
+	}
+	reader_task.abort();
+}
+
+/// Returns the opcode and unmasked payload
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<(u8, Vec<u8>)> {
+	let mut head = [0u8; 2];
+	reader.read_exact(&mut head).await?;
+	let opcode = head[0] & 0x0f;
+	let masked = head[1] & 0x80 != 0;
+	let length = match head[1] & 0x7f {
+		126 => reader.read_u16().await? as u64,
+		127 => reader.read_u64().await?,
+		length => length as u64,
+	};
+	if length > MAX_CLIENT_FRAME {
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "WebSocket frame too large"));
+	}
+	let mut mask = [0u8; 4];
+	if masked {
+		reader.read_exact(&mut mask).await?;
+	}
+	let mut payload = vec![0u8; length as usize];
+	reader.read_exact(&mut payload).await?;
+	if masked {
+		for (i, byte) in payload.iter_mut().enumerate() {
+			*byte ^= mask[i % 4];
+		}
+	}
+	Ok((opcode, payload))
+}
+
+/// Unmasked and unfragmented, servers don't mask
+async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+	let mut frame = vec![0x80 | opcode];
+	match payload.len() {
+		length @ 0..=125 => frame.push(length as u8),
+		length @ 126..=0xffff => {
+			frame.push(126);
+			frame.extend_from_slice(&(length as u16).to_be_bytes());
+		},
+		length => {
+			frame.push(127);
+			frame.extend_from_slice(&(length as u64).to_be_bytes());
+		},
+	}
+	frame.extend_from_slice(payload);
+	writer.write_all(&frame).await
+}
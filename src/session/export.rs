@@ -0,0 +1,240 @@
+use anyhow::anyhow;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::display::utc_timestamp;
+use super::SessionEntry;
+
+/// Subtitles stay up this long per character of translation, within the bounds below, or until the next one
+const SUBTITLE_TIME_PER_CHAR: Duration = Duration::from_millis(60);
+const MIN_SUBTITLE_TIME: Duration = Duration::from_secs(2);
+const MAX_SUBTITLE_TIME: Duration = Duration::from_secs(8);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+	Srt,
+	Vtt,
+	Markdown,
+	/// Source and translation in side by side columns
+	Html,
+	Csv,
+}
+
+impl FromStr for ExportFormat {
+	type Err = anyhow::Error;
+
+	fn from_str(format: &str) -> Result<Self, Self::Err> {
+		match format.to_ascii_lowercase().as_str() {
+			"srt" => Ok(ExportFormat::Srt),
+			"vtt" | "webvtt" => Ok(ExportFormat::Vtt),
+			"md" | "markdown" => Ok(ExportFormat::Markdown),
+			"html" | "htm" => Ok(ExportFormat::Html),
+			"csv" => Ok(ExportFormat::Csv),
+			_ => Err(anyhow!("Unknown export format {}, expected srt, vtt, md, html or csv", format)),
+		}
+	}
+}
+
+impl ExportFormat {
+	/// From the file extension, e.g. "session.srt"
+	pub fn from_path(path: &Path) -> Option<Self> {
+		path.extension()?.to_str()?.parse().ok()
+	}
+}
+
+/// The whole document. Subtitles start when each translation was shown, counted from the first one and moved by
+/// shift seconds(negative to start earlier) to line them up with a recording, the ones moved before 0 are dropped
+pub fn export(entries: &[SessionEntry], format: ExportFormat, shift: f64) -> String {
+	match format {
+		ExportFormat::Srt => subtitles(entries, shift, false),
+		ExportFormat::Vtt => subtitles(entries, shift, true),
+		ExportFormat::Markdown => markdown(entries),
+		ExportFormat::Html => html(entries),
+		ExportFormat::Csv => csv(entries),
+	}
+}
+
+fn subtitles(entries: &[SessionEntry], shift: f64, vtt: bool) -> String {
+	let mut output = String::new();
+	if vtt {
+		output.push_str("WEBVTT\n\n");
+	}
+	let Some(first) = entries.first() else {
+		return output;
+	};
+	let start_times: Vec<f64> = entries.iter()
+		.map(|entry| entry.time.duration_since(first.time).unwrap_or_default().as_secs_f64() + shift)
+		.collect();
+	let mut number = 0;
+	for (index, entry) in entries.iter().enumerate() {
+		// A blank line ends the cue
+		let text = entry.translation.lines().map(str::trim_end).filter(|line| !line.trim().is_empty()).collect::<Vec<_>>().join("\n");
+		let start = start_times[index];
+		let shown = (SUBTITLE_TIME_PER_CHAR * text.chars().count() as u32).clamp(MIN_SUBTITLE_TIME, MAX_SUBTITLE_TIME);
+		let end = start_times.get(index + 1).map_or(f64::MAX, |next| *next).min(start + shown.as_secs_f64());
+		if text.is_empty() || end <= 0.0 {
+			continue;
+		}
+		number += 1;
+		let (start, end) = (subtitle_time(start.max(0.0), vtt), subtitle_time(end, vtt));
+		// WebVTT cue text is HTML like, "-->" can't appear in it either
+		let text = if vtt { text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;") } else { text };
+		let _ = write!(output, "{}\n{} --> {}\n{}\n\n", number, start, end, text);
+	}
+	output
+}
+
+/// "01:02:03,456" for SRT, "01:02:03.456" for WebVTT
+fn subtitle_time(seconds: f64, vtt: bool) -> String {
+	let millis = (seconds * 1000.0).round() as u64;
+	let separator = if vtt { '.' } else { ',' };
+	format!("{:02}:{:02}:{:02}{}{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, separator, millis % 1000)
+}
+
+/// "2026-01-31 12:34:56", times are UTC
+fn readable_time(entry: &SessionEntry) -> String {
+	utc_timestamp(entry.time)[..19].replace('T', " ")
+}
+
+/// "capture (0, 1, 0, 1)", "terminal"
+fn origin_label(entry: &SessionEntry) -> String {
+	match &entry.region {
+		Some(region) => format!("{} {}", entry.origin.name(), region),
+		None => entry.origin.name().to_string(),
+	}
+}
+
+fn markdown(entries: &[SessionEntry]) -> String {
+	let mut output = String::new();
+	if let Some(first) = entries.first() {
+		let _ = write!(output, "# Session {} UTC\n\n", readable_time(first));
+	}
+	for entry in entries {
+		let _ = write!(output, "## {} · {}\n\n", &readable_time(entry)[11..], origin_label(entry));
+		for line in entry.source.trim().lines() {
+			let _ = writeln!(output, "> {}", line);
+		}
+		let _ = write!(output, "\n{}\n\n", entry.translation.trim());
+	}
+	output
+}
+
+fn html(entries: &[SessionEntry]) -> String {
+	let title = match entries.first() {
+		Some(first) => format!("Session {} UTC", readable_time(first)),
+		None => "Session".to_string(),
+	};
+	let mut output = format!(concat!(
+		"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n",
+		"\tbody {{ font-family: sans-serif; margin: 2em; }}\n",
+		"\ttable {{ border-collapse: collapse; width: 100%; }}\n",
+		"\tth, td {{ border-bottom: 1px solid #ccc; padding: 0.5em; text-align: left; vertical-align: top; white-space: pre-wrap; }}\n",
+		"\t.time {{ color: #666; white-space: nowrap; }}\n",
+		"\ttd:nth-child(2), td:nth-child(3) {{ width: 45%; }}\n",
+		"</style>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n<tr><th>Time</th><th>Source</th><th>Translation</th></tr>\n",
+	), title);
+	for entry in entries {
+		let _ = writeln!(
+			output,
+			"<tr><td class=\"time\" title=\"{}\">{}</td><td>{}</td><td>{}</td></tr>",
+			escape_html(&origin_label(entry)),
+			&readable_time(entry)[11..],
+			escape_html(entry.source.trim()),
+			escape_html(entry.translation.trim()),
+		);
+	}
+	output.push_str("</table>\n</body>\n</html>\n");
+	output
+}
+
+fn csv(entries: &[SessionEntry]) -> String {
+	let mut output = String::from("timestamp,origin,region,source,translation,target_lang,model,latency_ms\r\n");
+	for entry in entries {
+		let fields = [
+			utc_timestamp(entry.time),
+			entry.origin.name().to_string(),
+			entry.region.clone().unwrap_or_default(),
+			entry.source.trim().to_string(),
+			entry.translation.trim().to_string(),
+			entry.target_lang.clone(),
+			entry.model.clone(),
+			entry.latency.as_millis().to_string(),
+		];
+		output.push_str(&fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+		output.push_str("\r\n");
+	}
+	output
+}
+
+/// Quoted when needed, see RFC 4180
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::SystemTime;
+	use crate::display::Origin;
+
+	fn entry(seconds: u64, source: &str, translation: &str) -> SessionEntry {
+		SessionEntry {
+			time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_355_242 + seconds),
+			origin: Origin::Capture,
+			region: Some("(0, 1, 0, 1)".to_string()),
+			source: source.to_string(),
+			translation: translation.to_string(),
+			target_lang: "English".to_string(),
+			model: "gpt-4o".to_string(),
+			latency: Duration::from_millis(800),
+		}
+	}
+
+	#[test]
+	fn subtitle_timing() {
+		let entries = [entry(0, "はい", "Yes"), entry(1, "いいえ", "No"), entry(30, "行こう", "Let's go")];
+		assert_eq!(export(&entries, ExportFormat::Srt, 0.0), concat!(
+			"1\n00:00:00,000 --> 00:00:01,000\nYes\n\n",
+			"2\n00:00:01,000 --> 00:00:03,000\nNo\n\n",
+			"3\n00:00:30,000 --> 00:00:32,000\nLet's go\n\n",
+		));
+		// Shifted earlier, the first one ends before the recording starts
+		assert_eq!(export(&entries, ExportFormat::Vtt, -1.5), concat!(
+			"WEBVTT\n\n",
+			"1\n00:00:00.000 --> 00:00:01.500\nNo\n\n",
+			"2\n00:00:28.500 --> 00:00:30.500\nLet's go\n\n",
+		));
+	}
+
+	#[test]
+	fn multi_paragraph_translations_stay_in_one_cue() {
+		let entries = [entry(0, "はい。\n\nいいえ。", "Yes.\n\n  \nNo.\n"), entry(5, "行こう", "Let's go")];
+		assert_eq!(export(&entries, ExportFormat::Srt, 0.0), concat!(
+			"1\n00:00:00,000 --> 00:00:02,000\nYes.\nNo.\n\n",
+			"2\n00:00:05,000 --> 00:00:07,000\nLet's go\n\n",
+		));
+		assert_eq!(export(&entries, ExportFormat::Vtt, 0.0), concat!(
+			"WEBVTT\n\n",
+			"1\n00:00:00.000 --> 00:00:02.000\nYes.\nNo.\n\n",
+			"2\n00:00:05.000 --> 00:00:07.000\nLet's go\n\n",
+		));
+	}
+
+	#[test]
+	fn csv_quoting() {
+		let entries = [entry(0, "「はい、\"そう\"」\n次", "Yes, \"right\"")];
+		assert_eq!(export(&entries, ExportFormat::Csv, 0.0), concat!(
+			"timestamp,origin,region,source,translation,target_lang,model,latency_ms\r\n",
+			"2026-10-18T20:27:22.000Z,capture,\"(0, 1, 0, 1)\",\"「はい、\"\"そう\"\"」\n次\",\"Yes, \"\"right\"\"\",English,gpt-4o,800\r\n",
+		));
+	}
+}
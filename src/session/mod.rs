@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::display::{jsonl_record, parse_utc_timestamp, utc_timestamp, DisplayEvent, DisplaySink, Origin};
+
+mod export;
+pub use export::{export, ExportFormat};
+
+// Each run records its translations to its own file in the sessions directory(outputs.sessions), named after the
+// UTC time of the first one. The records are the JsonlSink ones, so outputs.jsonl files can be exported too
+
+const SESSION_EXTENSION: &str = "jsonl";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionEntry {
+	pub time: SystemTime,
+	pub origin: Origin,
+	pub region: Option<String>,
+	pub source: String,
+	pub translation: String,
+	pub target_lang: String,
+	pub model: String,
+	pub latency: Duration,
+}
+
+#[derive(Deserialize)]
+struct Record {
+	timestamp: String,
+	origin: Origin,
+	#[serde(default)]
+	region: Option<String>,
+	source: String,
+	translation: String,
+	#[serde(default)]
+	target_lang: String,
+	#[serde(default)]
+	model: String,
+	#[serde(default)]
+	latency_ms: u64,
+}
+
+/// Writes every finished translation to a new session file, created with the first one
+pub struct SessionRecorder {
+	dir: PathBuf,
+	file: Option<File>,
+}
+
+impl SessionRecorder {
+	pub fn new(dir: &Path) -> Self {
+		Self { dir: dir.to_path_buf(), file: None }
+	}
+
+	fn file(&mut self, time: SystemTime) -> Result<&mut File> {
+		if self.file.is_none() {
+			std::fs::create_dir_all(&self.dir).with_context(|| format!("Creating {} failed", self.dir.display()))?;
+			// "2026-01-31T12:34:56.789Z" -> "2026-01-31_12-34-56.jsonl", sorting by name sorts by time.
+			// Runs started within the same second get "_2", "_3"... instead of sharing a file
+			let stem = utc_timestamp(time)[..19].replace('T', "_").replace(':', "-");
+			let mut number = 1;
+			let file = loop {
+				let name = if number == 1 { format!("{}.{}", stem, SESSION_EXTENSION) } else { format!("{}_{}.{}", stem, number, SESSION_EXTENSION) };
+				let path = self.dir.join(name);
+				match File::options().create_new(true).append(true).open(&path) {
+					Ok(file) => break file,
+					Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => number += 1,
+					Err(e) => return Err(anyhow!("Creating {} failed: {}", path.display(), e)),
+				}
+			};
+			self.file = Some(file);
+		}
+		Ok(self.file.as_mut().expect("opened above"))
+	}
+}
+
+impl DisplaySink for SessionRecorder {
+	fn handle(&mut self, event: &DisplayEvent) {
+		let DisplayEvent::Done(done) = event else {
+			return;
+		};
+		let time = SystemTime::now();
+		let result = self.file(time).and_then(|file| Ok(writeln!(file, "{}", jsonl_record(done, time))?));
+		if let Err(e) = result {
+			eprintln!("Recording the session failed: {:#}", e);
+		}
+	}
+}
+
+/// Session files in dir, oldest first
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>> {
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(anyhow!("Reading {} failed: {}", dir.display(), e)),
+	};
+	let mut sessions: Vec<PathBuf> = entries
+		.filter_map(|entry| Some(entry.ok()?.path()))
+		.filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == SESSION_EXTENSION))
+		.collect();
+	sessions.sort();
+	Ok(sessions)
+}
+
+/// A session name from list() or a path, the latest session when None
+pub fn find(dir: &Path, session: Option<&str>) -> Result<PathBuf> {
+	match session {
+		Some(session) if Path::new(session).is_file() => Ok(PathBuf::from(session)),
+		Some(session) => {
+			let path = dir.join(session).with_extension(SESSION_EXTENSION);
+			path.is_file().then_some(path).ok_or_else(|| anyhow!("No session {} in {}", session, dir.display()))
+		},
+		None => list(dir)?.pop().ok_or_else(|| anyhow!("No sessions recorded in {} yet", dir.display())),
+	}
+}
+
+/// Entries of a session file in the order they were recorded. Lines that don't parse are skipped with a warning,
+/// a crash or kill while writing leaves the last one cut off
+pub fn load(path: &Path) -> Result<Vec<SessionEntry>> {
+	let file = File::open(path).with_context(|| format!("Opening {} failed", path.display()))?;
+	let mut entries = Vec::new();
+	for (index, line) in BufReader::new(file).lines().enumerate() {
+		let line = line.with_context(|| format!("Reading {} failed", path.display()))?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let record = serde_json::from_str::<Record>(&line).map_err(|e| e.to_string()).and_then(|record| {
+			let time = parse_utc_timestamp(&record.timestamp).ok_or_else(|| format!("Invalid timestamp {}", record.timestamp))?;
+			Ok((time, record))
+		});
+		let (time, record) = match record {
+			Ok(record) => record,
+			Err(e) => {
+				eprintln!("Skipping {} line {}: {}", path.display(), index + 1, e);
+				continue;
+			},
+		};
+		entries.push(SessionEntry {
+			time,
+			origin: record.origin,
+			region: record.region,
+			source: record.source,
+			translation: record.translation,
+			target_lang: record.target_lang,
+			model: record.model,
+			latency: Duration::from_millis(record.latency_ms),
+		});
+	}
+	Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn skips_a_truncated_last_line() {
+		let path = std::env::temp_dir().join(format!("ocrtrans_session_test_{}.jsonl", std::process::id()));
+		std::fs::write(&path, concat!(
+			r#"{"timestamp":"2026-10-18T20:27:22.050Z","origin":"capture","source":"はい","translation":"Yes"}"#, "\n",
+			r#"{"timestamp":"not a time","origin":"capture","source":"いいえ","translation":"No"}"#, "\n",
+			r#"{"timestamp":"2026-10-18T20:27:25.000Z","origin":"terminal","source":"行こう","translation":"Let's go"}"#, "\n",
+			r#"{"timestamp":"2026-10-18T20:27:30.000Z","origin":"capture","sour"#,
+		)).unwrap();
+		let entries = load(&path);
+		let _ = std::fs::remove_file(&path);
+		let translations: Vec<String> = entries.unwrap().into_iter().map(|entry| entry.translation).collect();
+		assert_eq!(translations, ["Yes", "Let's go"]);
+	}
+
+	#[test]
+	fn runs_started_in_the_same_second_get_their_own_file() {
+		let dir = std::env::temp_dir().join(format!("ocrtrans_sessions_test_{}", std::process::id()));
+		let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_355_242);
+		for _ in 0..3 {
+			SessionRecorder::new(&dir).file(time).unwrap();
+		}
+		let names: Vec<String> = list(&dir).unwrap().iter().map(|path| path.file_name().unwrap().to_string_lossy().to_string()).collect();
+		let _ = std::fs::remove_dir_all(&dir);
+		assert_eq!(names, ["2026-10-18_20-27-22.jsonl", "2026-10-18_20-27-22_2.jsonl", "2026-10-18_20-27-22_3.jsonl"]);
+	}
+}
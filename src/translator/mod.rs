@@ -1,42 +1,227 @@
 use openai::chat::{ChatCompletionMessage, ChatCompletionMessageRole, ChatCompletionDelta};
+use reqwest::StatusCode;
+use reqwest_eventsource::{Event, EventSource};
+use futures::future::BoxFuture;
+use futures::StreamExt;
 use std::io::Write;
-use anyhow::Result;
+use std::time::Duration;
+use thiserror::Error;
+use crate::display::{DisplayEvent, DisplaySender};
+use crate::language;
+use crate::retry::{self, RetryPolicy};
 
-pub(crate) async fn translate_openai(request: &TranslateRequest, steaming_output_async_channel: Option<tokio::sync::mpsc::Sender<String>>, steaming_output_sync_channel: Option<std::sync::mpsc::Sender<String>>) -> Result<String> {
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a multilingual translator, but mainly focused on video games or visual novels in Japanese.";
+
+/// Where and how to reach an OpenAI compatible chat completions API
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranslatorConfig {
+	/// Base URL, "/chat/completions" is appended
+	pub endpoint: String,
+	/// Optional if using non-official services
+	pub api_key: Option<String>,
+	pub model: String,
+	pub system_prompt: String,
+	/// (source language name, system prompt) pairs used instead of system_prompt for text in that language
+	pub language_prompts: Vec<(String, String)>,
+	/// (source term, translation) pairs, only the ones found in the text are sent
+	pub glossary: Vec<(String, String)>,
+	/// Tried in order when the main backend keeps failing
+	pub fallbacks: Vec<TranslationBackend>,
+	pub retry: RetryPolicy,
+}
+
+/// One chat completions API and the model used on it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranslationBackend {
+	pub endpoint: String,
+	pub api_key: Option<String>,
+	pub model: String,
+}
+
+impl TranslatorConfig {
+	/// The main backend followed by the fallbacks
+	fn backends(&self) -> Vec<TranslationBackend> {
+		std::iter::once(TranslationBackend {
+			endpoint: self.endpoint.clone(),
+			api_key: self.api_key.clone(),
+			model: self.model.clone(),
+		}).chain(self.fallbacks.iter().cloned()).collect()
+	}
+
+	/// The prompt of the source language if it has one
+	fn system_prompt(&self, src_lang: &str) -> &str {
+		let src_lang = language::find(src_lang).map_or(src_lang, |language| language.name);
+		self.language_prompts.iter()
+			.find(|(language, _)| language.eq_ignore_ascii_case(src_lang))
+			.map_or(&self.system_prompt, |(_, prompt)| prompt)
+	}
+}
+
+/// Why a translation failed, the messages are short enough for the overlay
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum TranslateError {
+	#[error("Building chat completion request failed: {0}")]
+	BuildRequest(String),
+	/// 429 Too Many Requests, with the delay the API asked for
+	#[error("Rate limited by the translation API")]
+	RateLimited(Option<Duration>),
+	#[error("Translation API returned {0}: {1}")]
+	Status(StatusCode, String),
+	#[error("Translation API connection failed: {0}")]
+	Connection(reqwest_eventsource::Error),
+	#[error("Invalid translation API response: {0}")]
+	InvalidResponse(serde_json::Error),
+}
+
+/// Translate stage backend, the translation is also streamed to the channels that are set as it comes in
+pub trait Translator: Send + Sync {
+	fn translate<'a>(
+		&'a self,
+		config: &'a TranslatorConfig,
+		request: &'a TranslateRequest,
+		steaming_output_async_channel: Option<tokio::sync::mpsc::Sender<String>>,
+		steaming_output_sync_channel: Option<DisplaySender>,
+	) -> BoxFuture<'a, Result<String, TranslateError>>;
+}
+
+/// OpenAI compatible chat completions APIs, see translate_openai()
+pub struct OpenAiTranslator;
+
+impl Translator for OpenAiTranslator {
+	fn translate<'a>(
+		&'a self,
+		config: &'a TranslatorConfig,
+		request: &'a TranslateRequest,
+		steaming_output_async_channel: Option<tokio::sync::mpsc::Sender<String>>,
+		steaming_output_sync_channel: Option<DisplaySender>,
+	) -> BoxFuture<'a, Result<String, TranslateError>> {
+		Box::pin(translate_openai(config, request, steaming_output_async_channel, steaming_output_sync_channel))
+	}
+}
+
+pub async fn translate_openai(config: &TranslatorConfig, request: &TranslateRequest, steaming_output_async_channel: Option<tokio::sync::mpsc::Sender<String>>, steaming_output_sync_channel: Option<DisplaySender>) -> Result<String, TranslateError> {
 	let mut messages = vec![ChatCompletionMessage {
         role: ChatCompletionMessageRole::System,
-        content: Some("You are a multilingual translator, but mainly focused on video games or visual novels in Japanese.".to_string()),
+        content: Some(config.system_prompt(&request.src_lang).to_string()),
         name: None,
         function_call: None,
     }];
 
+	let mut user_message = if request.src_lang.is_empty() || language::is_auto(&request.src_lang) {
+		format!("Translate ```\n{}\n``` to {}, reply translation only", request.content, request.target_lang)
+	} else {
+		format!("Translate ```\n{}\n``` from {} to {}, reply translation only", request.content, request.src_lang, request.target_lang)
+	};
+	let glossary: Vec<String> = config.glossary.iter()
+		.filter(|(term, _)| request.content.contains(term.as_str()))
+		.map(|(term, translation)| format!("{} = {}", term, translation))
+		.collect();
+	if !glossary.is_empty() {
+		user_message.push_str(&format!("\nUse these translations for names and terms:\n{}", glossary.join("\n")));
+	}
 	messages.push(ChatCompletionMessage {
 		role: ChatCompletionMessageRole::User,
-		content: Some(format!("Translate ```\n{}\n``` to {}, reply translation only", request.content, request.target_lang)),
+		content: Some(user_message),
 		name: None,
 		function_call: None,
 	});
 
-	let mut translation_result_stream = ChatCompletionDelta::builder("gpt-4o", messages.clone())
-		.create_stream()
-		.await
-		.unwrap();
+	let client = reqwest::Client::builder()
+		.connect_timeout(config.retry.connect_timeout)
+		.read_timeout(config.retry.read_timeout)
+		.build().map_err(|e| TranslateError::BuildRequest(e.to_string()))?;
+	let mut last_error = None;
+	for backend in config.backends() {
+		if let Some(e) = &last_error {
+			eprintln!("Translation failed ({}), trying fallback {} at {}", e, backend.model, backend.endpoint);
+		}
+		let mut retries = 0;
+		let error = loop {
+			let mut concatenated_result = String::new();
+			let result = stream_completion(&client, &backend, &messages, &mut concatenated_result, &steaming_output_async_channel, &steaming_output_sync_channel).await;
+			let (error, retry_after) = match result {
+				Ok(()) => {
+					concatenated_result.push('\n');
+					return Ok(concatenated_result);
+				},
+				// Part of the translation was already shown, starting over would repeat it
+				Err(e) if !concatenated_result.is_empty() => return Err(e),
+				Err(TranslateError::RateLimited(retry_after)) => (TranslateError::RateLimited(retry_after), retry_after),
+				Err(TranslateError::Status(status, body)) if retry::is_retryable(status) => (TranslateError::Status(status, body), None),
+				Err(e @ TranslateError::Connection(_)) => (e, None),
+				Err(e) => break e,
+			};
+			retries += 1;
+			let Some(delay) = config.retry.retry_delay(retries, retry_after) else {
+				break error;
+			};
+			eprintln!("Translation failed ({}), retrying in {:.1}s", error, delay.as_secs_f32());
+			tokio::time::sleep(delay).await;
+		};
+		last_error = Some(error);
+	}
+	Err(last_error.expect("the main translation backend is always tried"))
+}
 
-	let mut concatenated_result = String::new();
+/// Stream one chat completion, the text received is appended to concatenated_result even when it fails midway
+async fn stream_completion(
+	client: &reqwest::Client,
+	backend: &TranslationBackend,
+	messages: &[ChatCompletionMessage],
+	concatenated_result: &mut String,
+	steaming_output_async_channel: &Option<tokio::sync::mpsc::Sender<String>>,
+	steaming_output_sync_channel: &Option<DisplaySender>,
+) -> Result<(), TranslateError> {
+	let request_body = ChatCompletionDelta::builder(&backend.model, messages.to_vec())
+		.stream(true)
+		.build()
+		.map_err(|e| TranslateError::BuildRequest(e.to_string()))?;
+	let mut request_builder = client
+		.post(format!("{}/chat/completions", backend.endpoint.trim_end_matches('/')))
+		.json(&request_body);
+	if let Some(api_key) = &backend.api_key {
+		request_builder = request_builder.bearer_auth(api_key);
+	}
+	let mut translation_result_stream = EventSource::new(request_builder).map_err(|e| TranslateError::BuildRequest(e.to_string()))?;
+	// Retries are done by the caller, which can also switch backends
+	translation_result_stream.set_retry_policy(Box::new(reqwest_eventsource::retry::Never));
 
-	while let Some(delta) = translation_result_stream.recv().await {
-		let choice = &delta.choices[0];
+	while let Some(event) = translation_result_stream.next().await {
+		let message = match event {
+			Ok(Event::Open) => continue,
+			Ok(Event::Message(message)) => message,
+			Err(reqwest_eventsource::Error::StreamEnded) => break,
+			Err(reqwest_eventsource::Error::InvalidStatusCode(status, response)) => {
+				translation_result_stream.close();
+				if status == StatusCode::TOO_MANY_REQUESTS {
+					return Err(TranslateError::RateLimited(retry::retry_after(response.headers())));
+				}
+				return Err(TranslateError::Status(status, response.text().await.unwrap_or_default()));
+			},
+			Err(e) => {
+				translation_result_stream.close();
+				return Err(TranslateError::Connection(e));
+			},
+		};
+		if message.data == "[DONE]" {
+			break;
+		}
+		let delta: ChatCompletionDelta = serde_json::from_str(&message.data).map_err(TranslateError::InvalidResponse)?;
+		let Some(choice) = delta.choices.first() else {
+			continue;
+		};
 		// if let Some(role) = &choice.delta.role {
 		// 	print!("{:#?}: ", role);
 		// }
 		if let Some(content) = &choice.delta.content {
 			if !content.replace(['\n', ' '], "").is_empty() {
-				if let Some(ref channel) = steaming_output_sync_channel {
-					if let Err(e) = channel.send(content.clone()) {
+				if let Some(channel) = steaming_output_sync_channel {
+					if let Err(e) = channel.send(DisplayEvent::Append(content.clone())) {
 						eprintln!("steaming_output_sync_channel error: {}", e);
 					}
 				}
-				if let Some(ref channel) = steaming_output_async_channel {
+				if let Some(channel) = steaming_output_async_channel {
 					if let Err(e) = channel.send(content.clone()).await {
 						eprintln!("steaming_output_async_channel error: {}", e);
 					}
@@ -49,11 +234,12 @@ pub(crate) async fn translate_openai(request: &TranslateRequest, steaming_output
 		// 	print!("\n");
 		// }
 	}
-
-	concatenated_result.push('\n');
-	Ok(concatenated_result)
+	translation_result_stream.close();
+	Ok(())
 }
 
+/// Text to translate, build it with new()
+#[non_exhaustive]
 pub struct TranslateRequest {
 	pub content: String,
 	pub src_lang: String,
@@ -69,3 +255,73 @@ impl TranslateRequest {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::retry::mock_server::{MockResponse, MockServer};
+
+	fn translator_config(endpoint: &str, fallbacks: Vec<TranslationBackend>) -> TranslatorConfig {
+		TranslatorConfig {
+			endpoint: endpoint.to_string(),
+			api_key: Some("main-key".to_string()),
+			model: "main-model".to_string(),
+			system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+			language_prompts: Vec::new(),
+			glossary: Vec::new(),
+			fallbacks,
+			retry: RetryPolicy {
+				backoff: Duration::from_millis(10),
+				..Default::default()
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn retries_rate_limits_after_retry_after() {
+		let server = MockServer::start(vec![
+			MockResponse::new(429, "").header("retry-after-ms", "20"),
+			MockResponse::new(502, ""),
+			MockResponse::completion_stream("Hello"),
+		]);
+		let request = TranslateRequest::new("こんにちは", "Japanese", "English");
+		let translation = translate_openai(&translator_config(&server.url, Vec::new()), &request, None, None).await.unwrap();
+		assert_eq!(translation, "Hello\n");
+		assert_eq!(server.requests().len(), 3);
+	}
+
+	#[tokio::test]
+	async fn falls_back_to_the_next_backend() {
+		let main = MockServer::start(vec![MockResponse::new(401, "invalid key")]);
+		let fallback = MockServer::start(vec![MockResponse::completion_stream("Hello")]);
+		let config = translator_config(&main.url, vec![TranslationBackend {
+			endpoint: format!("{}/v1", fallback.url),
+			api_key: None,
+			model: "fallback-model".to_string(),
+		}]);
+		let request = TranslateRequest::new("こんにちは", "Japanese", "English");
+		assert_eq!(translate_openai(&config, &request, None, None).await.unwrap(), "Hello\n");
+		assert_eq!(main.requests().len(), 1);
+		let fallback_request = &fallback.requests()[0];
+		assert!(fallback_request.starts_with("POST /v1/chat/completions"));
+		assert!(!fallback_request.to_ascii_lowercase().contains("authorization"));
+	}
+
+	#[tokio::test]
+	async fn gives_up_with_the_last_error() {
+		let server = MockServer::start(vec![MockResponse::new(429, ""), MockResponse::new(429, ""), MockResponse::new(429, "")]);
+		let request = TranslateRequest::new("こんにちは", "Japanese", "English");
+		let error = translate_openai(&translator_config(&server.url, Vec::new()), &request, None, None).await.unwrap_err();
+		assert!(matches!(error, TranslateError::RateLimited(None)), "{:?}", error);
+		assert_eq!(server.requests().len(), 3);
+	}
+
+	#[test]
+	fn picks_the_source_language_prompt() {
+		let mut config = translator_config("http://localhost", Vec::new());
+		config.language_prompts = vec![("Korean".to_string(), "You translate Korean webtoons.".to_string())];
+		assert_eq!(config.system_prompt("ko"), "You translate Korean webtoons.");
+		assert_eq!(config.system_prompt("Korean"), "You translate Korean webtoons.");
+		assert_eq!(config.system_prompt("auto"), DEFAULT_SYSTEM_PROMPT);
+	}
+}